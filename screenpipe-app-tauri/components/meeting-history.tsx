@@ -15,6 +15,7 @@ import { X, Activity } from "lucide-react"; // Import the X icon and Activity ic
 import { useInterval } from "@/lib/hooks/use-interval"; // Add this import
 import { usePostHog } from "posthog-js/react";
 import debounce from 'lodash/debounce';
+import { invoke } from "@tauri-apps/api/core";
 
 function setItem(key: string, value: any): void {
   if (typeof window !== 'undefined') {
@@ -219,10 +220,12 @@ export default function MeetingHistory() {
 
   async function sendNotification(title: string, body: string) {
     try {
+      const token = await invoke<string>("get_server_token");
       const response = await fetch("http://localhost:11435/notify", {
         method: "POST",
         headers: {
           "Content-Type": "application/json",
+          Authorization: `Bearer ${token}`,
         },
         body: JSON.stringify({ title, body }),
       });
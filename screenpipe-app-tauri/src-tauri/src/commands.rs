@@ -49,6 +49,12 @@ pub fn reset_microphone_permissions() {
         .expect("failed to reset microphone permissions");
 }
 
+/// Token for the app's local server, for the frontend's `/notify` calls.
+#[tauri::command]
+pub fn get_server_token(token: tauri::State<'_, crate::server::ServerToken>) -> String {
+    token.0.clone()
+}
+
 #[tauri::command]
 pub async fn load_pipe_config(pipe_name: String) -> Result<Value, String> {
     info!("Loading pipe config for {}", pipe_name);
@@ -42,6 +42,7 @@ pub use commands::reset_all_pipes;
 pub use sidecar::kill_all_sreenpipes;
 pub use sidecar::spawn_screenpipe;
 pub use server::spawn_server;
+use server::{bind_addr_from_env, ServerToken};
 
 pub struct SidecarState(Arc<tokio::sync::Mutex<Option<SidecarManager>>>);
 
@@ -111,7 +112,8 @@ async fn main() {
             open_screen_capture_preferences,
             load_pipe_config,
             save_pipe_config,
-            reset_all_pipes
+            reset_all_pipes,
+            commands::get_server_token
         ])
         .setup(|app| {
             // Logging setup
@@ -283,6 +285,10 @@ async fn main() {
             })
             .unwrap_or(false);
 
+            // Shared with the sidecar, whose pipes send notifications and logs to the server
+            let server_token = ServerToken::from_env_or_generate();
+            app.manage(server_token.clone());
+
             let sidecar_manager = Arc::new(Mutex::new(SidecarManager::new()));
             app.manage(sidecar_manager.clone());
 
@@ -306,7 +312,12 @@ async fn main() {
             }
 
             // Inside the main function, after the `app.manage(port);` line, add:
-            let server_shutdown_tx = spawn_server(app.handle().clone(), 11435);
+            let server_shutdown_tx = spawn_server(
+                app.handle().clone(),
+                bind_addr_from_env(),
+                11435,
+                server_token,
+            );
             app.manage(server_shutdown_tx);
 
             // Add this custom activate handler
@@ -1,19 +1,26 @@
 use axum::{
     extract::State,
-    http::{Method, StatusCode},
+    http::{header::AUTHORIZATION, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::Response,
     Json, Router,
 };
-use tower_http::cors::{CorsLayer, Any};
+use tower_http::cors::{AllowOrigin, CorsLayer, Any};
 use tower_http::trace::{TraceLayer, DefaultMakeSpan};
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
-use tauri::Emitter;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+use tauri::{Emitter, Manager};
 #[allow(unused_imports)]
 use tauri_plugin_notification::NotificationExt;
 use tokio::sync::mpsc;
 use tracing::{error, info};
 use http::header::HeaderValue;
 
+use crate::SidecarState;
+
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 struct LogEntry {
     pipe_id: String,
@@ -22,9 +29,58 @@ struct LogEntry {
     timestamp: String,
 }
 
+/// Environment variable holding the token `/notify` and `/log` require, for the sidecar and
+/// to pin the token instead of generating one at startup.
+pub const SERVER_TOKEN_ENV: &str = "SCREENPIPE_SERVER_TOKEN";
+
+/// Environment variable with the address to bind, e.g. `0.0.0.0` in a container or `::1`.
+pub const SERVER_HOST_ENV: &str = "SCREENPIPE_SERVER_HOST";
+
+/// Bind address from `SCREENPIPE_SERVER_HOST`, loopback when unset or not an IP address.
+pub fn bind_addr_from_env() -> IpAddr {
+    let default = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    match std::env::var(SERVER_HOST_ENV) {
+        Ok(host) if !host.trim().is_empty() => host
+            // Accept the bracketed form used in URLs, e.g. `[::1]`
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .unwrap_or_else(|e| {
+                error!("Invalid {} '{}': {}, binding {}", SERVER_HOST_ENV, host, e, default);
+                default
+            }),
+        _ => default,
+    }
+}
+
+/// Bearer token clients must send as `Authorization: Bearer <token>`.
+#[derive(Clone)]
+pub struct ServerToken(pub String);
+
+impl ServerToken {
+    /// `SCREENPIPE_SERVER_TOKEN` when set, otherwise a random token for this run.
+    pub fn from_env_or_generate() -> Self {
+        match std::env::var(SERVER_TOKEN_ENV) {
+            Ok(token) if !token.is_empty() => ServerToken(token),
+            _ => ServerToken(uuid::Uuid::new_v4().simple().to_string()),
+        }
+    }
+}
+
+/// Origins of the app's own webview: macOS/Linux, Windows, and the dev server.
+const APP_ORIGINS: [&str; 4] = [
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+    "http://localhost:3000",
+];
+
 #[derive(Clone)]
 pub struct ServerState {
     app_handle: tauri::AppHandle,
+    token: Arc<String>,
+    started_at: Instant,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -39,11 +95,50 @@ struct ApiResponse {
     message: String,
 }
 
-pub async fn run_server(app_handle: tauri::AppHandle, port: u16) {
-    let state = ServerState { app_handle };
+/// Compares in constant time so the token can't be guessed byte by byte from response times.
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn require_token<B>(
+    State(state): State<ServerState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token_matches(token, &state.token));
+    if authorized {
+        Ok(next.run(request).await)
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+pub async fn run_server(
+    app_handle: tauri::AppHandle,
+    bind_addr: IpAddr,
+    port: u16,
+    token: ServerToken,
+) -> io::Result<()> {
+    let state = ServerState {
+        app_handle,
+        token: Arc::new(token.0),
+        started_at: Instant::now(),
+    };
 
     let cors = CorsLayer::new()
-        .allow_origin("*".parse::<HeaderValue>().unwrap())
+        .allow_origin(AllowOrigin::list(
+            APP_ORIGINS.map(HeaderValue::from_static),
+        ))
         .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
         .allow_headers(Any)
         .allow_credentials(false);
@@ -51,6 +146,10 @@ pub async fn run_server(app_handle: tauri::AppHandle, port: u16) {
     let app = Router::new()
         .route("/notify", axum::routing::post(send_notification))
         .route("/log", axum::routing::post(log_message))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        // Added after the token layer so supervisors can poll them without the token
+        .route("/health", axum::routing::get(health))
+        .route("/version", axum::routing::get(version))
         .layer(cors)
         .layer(
             TraceLayer::new_for_http()
@@ -58,13 +157,70 @@ pub async fn run_server(app_handle: tauri::AppHandle, port: u16) {
         )
         .with_state(state);
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let addr = SocketAddr::new(bind_addr, port);
+    // Bound here rather than by hyper so a port conflict surfaces as `AddrInUse`
+    let listener = tokio::net::TcpListener::bind(addr).await?.into_std()?;
     info!("Server listening on {}", addr);
 
-    axum::Server::bind(&addr)
+    axum::Server::from_tcp(listener)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
         .serve(app.into_make_service())
         .await
-        .unwrap();
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+/// Emitted when the server can't start, so the UI can say notifications are unavailable.
+#[derive(Clone, Serialize)]
+struct ServerErrorEvent {
+    addr: String,
+    error: String,
+    /// The port is taken, most likely by another screenpipe instance
+    addr_in_use: bool,
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    uptime_secs: u64,
+    /// `None` while the sidecar is being (re)started and its state can't be read
+    sidecar_running: Option<bool>,
+}
+
+async fn health(State(state): State<ServerState>) -> Json<HealthResponse> {
+    let sidecar_running = state
+        .app_handle
+        .try_state::<SidecarState>()
+        .and_then(|sidecar| {
+            sidecar
+                .0
+                .try_lock()
+                .ok()
+                .map(|manager| manager.as_ref().is_some_and(|m| m.is_running()))
+        });
+    Json(HealthResponse {
+        status: "ok",
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        sidecar_running,
+    })
+}
+
+#[derive(Serialize)]
+struct VersionResponse {
+    name: &'static str,
+    version: &'static str,
+    profile: &'static str,
+    os: &'static str,
+    arch: &'static str,
+}
+
+async fn version() -> Json<VersionResponse> {
+    Json(VersionResponse {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+    })
 }
 
 async fn send_notification(
@@ -112,12 +268,31 @@ async fn log_message(
     }
 }
 
-pub fn spawn_server(app_handle: tauri::AppHandle, port: u16) -> mpsc::Sender<()> {
+pub fn spawn_server(
+    app_handle: tauri::AppHandle,
+    bind_addr: IpAddr,
+    port: u16,
+    token: ServerToken,
+) -> mpsc::Sender<()> {
     let (tx, mut rx) = mpsc::channel(1);
 
     tokio::spawn(async move {
         tokio::select! {
-            _ = run_server(app_handle, port) => {},
+            result = run_server(app_handle.clone(), bind_addr, port, token) => {
+                // Recording runs in the sidecar, only notifications and pipe logs are lost
+                if let Err(e) = result {
+                    let addr = SocketAddr::new(bind_addr, port);
+                    error!("Server on {} stopped: {}", addr, e);
+                    let event = ServerErrorEvent {
+                        addr: addr.to_string(),
+                        error: e.to_string(),
+                        addr_in_use: e.kind() == io::ErrorKind::AddrInUse,
+                    };
+                    if let Err(e) = app_handle.emit("server-error", event) {
+                        error!("Failed to emit server-error: {}", e);
+                    }
+                }
+            },
             _ = rx.recv() => {
                 info!("Received shutdown signal for server");
             }
@@ -131,6 +306,7 @@ pub fn spawn_server(app_handle: tauri::AppHandle, port: u16) -> mpsc::Sender<()>
 
 
 curl -X POST http://localhost:11435/notify \
+  -H "Authorization: Bearer $SCREENPIPE_SERVER_TOKEN" \
   -H "Content-Type: application/json" \
   -d '{"title": "Test Notification", "body": "This is a test notification body"}'
 
@@ -1,3 +1,4 @@
+use crate::server::{ServerToken, SERVER_TOKEN_ENV};
 use crate::{get_base_dir, SidecarState};
 use serde_json::Value;
 use std::env;
@@ -80,7 +81,11 @@ pub async fn spawn_screenpipe(
 fn spawn_sidecar(app: &tauri::AppHandle) -> Result<CommandChild, String> {
     let stores = app.state::<StoreCollection<Wry>>();
     let base_dir = get_base_dir(app, None).expect("Failed to ensure local data directory");
-    let sidecar = app.shell().sidecar("screenpipe").unwrap();
+    let mut sidecar = app.shell().sidecar("screenpipe").unwrap();
+    // The server forwards pipe logs and notifications with it; pipes never see it
+    if let Some(token) = app.try_state::<ServerToken>() {
+        sidecar = sidecar.env(SERVER_TOKEN_ENV, token.0.clone());
+    }
     let path = base_dir.join("store.bin");
 
     let audio_transcription_engine =
@@ -313,6 +318,10 @@ impl SidecarManager {
         }
     }
 
+    pub fn is_running(&self) -> bool {
+        self.child.is_some()
+    }
+
     pub async fn spawn(&mut self, app: &tauri::AppHandle) -> Result<(), String> {
         // Update settings from store
         self.update_settings(app).await?;
@@ -0,0 +1,70 @@
+// cargo bench --bench http_client_benchmark
+// Compares a new reqwest client per request, as the network engines used to do, with the
+// shared client from `http_client`, against a local keep-alive HTTP server.
+use criterion::{criterion_group, criterion_main, Criterion};
+use screenpipe_audio::stt::engines::http_client;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 13\r\n\r\n{\"text\":\"ok\"}";
+
+/// Answers every request with `RESPONSE`, keeping connections open between requests.
+async fn start_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buffer = vec![0u8; 64 * 1024];
+                let mut request = Vec::new();
+                loop {
+                    let n = match socket.read(&mut buffer).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => n,
+                    };
+                    request.extend_from_slice(&buffer[..n]);
+                    // The benchmark only sends bodyless requests
+                    while let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                        request.drain(..end + 4);
+                        if socket.write_all(RESPONSE).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+fn bench_http_client(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let addr = runtime.block_on(start_server());
+    let url = format!("http://{}/transcribe", addr);
+    let url = url.as_str();
+
+    let mut group = c.benchmark_group("http_client");
+
+    group.bench_function("new_client_per_request", |b| {
+        b.to_async(&runtime).iter(|| async move {
+            let client = reqwest::Client::new();
+            client.get(url).send().await.unwrap().text().await.unwrap()
+        })
+    });
+
+    let shared = http_client(Duration::from_secs(10)).unwrap();
+    let shared = &shared;
+    group.bench_function("shared_client", |b| {
+        b.to_async(&runtime).iter(|| async move {
+            shared.get(url).send().await.unwrap().text().await.unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_http_client);
+criterion_main!(benches);
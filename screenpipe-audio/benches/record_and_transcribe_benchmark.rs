@@ -1,18 +1,17 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use screenpipe_audio::{
-    create_whisper_channel, default_input_device, record_and_transcribe, AudioDevice, AudioInput,
-    AudioTranscriptionEngine,
+    create_whisper_channel, default_input_device, record_and_transcribe, AudioDevice,
+    AudioInputSender, AudioTranscriptionEngine,
 };
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
 
 async fn setup_test() -> (
     Arc<AudioDevice>,
     PathBuf,
-    mpsc::UnboundedSender<AudioInput>,
+    AudioInputSender,
     Arc<AtomicBool>,
 ) {
     let audio_device = default_input_device().unwrap(); // TODO feed voice in automatically somehow
@@ -0,0 +1,28 @@
+// cargo bench --bench resample_benchmark
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use screenpipe_audio::{resample, ResampleQuality};
+use std::time::Duration;
+
+fn benchmark_resample(c: &mut Criterion) {
+    // A 30 second stereo chunk from a 48kHz device, speech-like mix of a few tones
+    let chunk: Vec<f32> = (0..48000 * 30 * 2)
+        .map(|i| {
+            let t = (i / 2) as f32 / 48000.0;
+            0.3 * (2.0 * std::f32::consts::PI * 220.0 * t).sin()
+                + 0.1 * (2.0 * std::f32::consts::PI * 1800.0 * t).sin()
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("resample_48k_stereo_to_16k");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(20));
+    for quality in [ResampleQuality::Fast, ResampleQuality::Balanced, ResampleQuality::High] {
+        group.bench_function(format!("{:?}", quality), |b| {
+            b.iter(|| resample(black_box(chunk.clone()), 2, 48000, 16000, quality).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_resample);
+criterion_main!(benches);
@@ -2,7 +2,8 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use memory_stats::memory_stats;
 use screenpipe_audio::vad_engine::SileroVad;
 use screenpipe_audio::{
-    create_whisper_channel, stt, AudioTranscriptionEngine, VadEngineEnum, WhisperModel,
+    create_whisper_channel, stt, AudioTranscriptionEngine, DevicePreference, VadEngineEnum,
+    WhisperModel,
 };
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -10,7 +11,7 @@ use std::time::Duration;
 
 fn criterion_benchmark(c: &mut Criterion) {
     let audio_transcription_engine = Arc::new(AudioTranscriptionEngine::WhisperTiny);
-    let whisper_model = WhisperModel::new(audio_transcription_engine.clone()).unwrap();
+    let whisper_model = WhisperModel::new(audio_transcription_engine.clone(), DevicePreference::Auto, None).unwrap();
     let test_file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
         .join("test_data")
         .join("selah.mp4");
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use tokio::sync::Notify;
+
+use crate::AudioInput;
+
+/// What `AudioInputSender::send` does when the queue is full.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Wait for the STT worker to take a chunk; nothing is lost, recording falls behind
+    #[default]
+    Block,
+    /// Discard the oldest queued chunk to make room, keeping transcription close to real time
+    DropOldest,
+    /// Discard the chunk being sent
+    DropNewest,
+}
+
+struct QueueState {
+    items: VecDeque<AudioInput>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    policy: DropPolicy,
+    item_ready: Notify,
+    space_ready: Notify,
+}
+
+/// Creates the bounded queue of recorded chunks between the recorders and the STT worker.
+pub fn audio_queue(capacity: usize, policy: DropPolicy) -> (AudioInputSender, AudioInputReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(QueueState {
+            items: VecDeque::new(),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        capacity: capacity.max(1),
+        policy,
+        item_ready: Notify::new(),
+        space_ready: Notify::new(),
+    });
+    (
+        AudioInputSender {
+            shared: shared.clone(),
+        },
+        AudioInputReceiver { shared },
+    )
+}
+
+pub struct AudioInputSender {
+    shared: Arc<Shared>,
+}
+
+impl AudioInputSender {
+    /// Queues `input`, applying the queue's `DropPolicy` when it is full, and returns the chunk
+    /// the policy discarded if any (the oldest queued one or `input`). Fails once the receiver
+    /// is gone.
+    pub async fn send(&self, input: AudioInput) -> Result<Option<AudioInput>> {
+        loop {
+            // Registered before checking, so a chunk taken between the check and the await
+            // still wakes us
+            let mut space_ready = pin!(self.shared.space_ready.notified());
+            space_ready.as_mut().enable();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if !state.receiver_alive {
+                    return Err(anyhow!("audio queue receiver dropped"));
+                }
+                if state.items.len() < self.shared.capacity {
+                    state.items.push_back(input);
+                    self.shared.item_ready.notify_one();
+                    return Ok(None);
+                }
+                match self.shared.policy {
+                    DropPolicy::Block => {}
+                    DropPolicy::DropOldest => {
+                        let dropped = state.items.pop_front();
+                        if let Some(dropped) = &dropped {
+                            warn!("audio queue full, dropped the oldest chunk from {}", dropped.device);
+                        }
+                        state.items.push_back(input);
+                        self.shared.item_ready.notify_one();
+                        return Ok(dropped);
+                    }
+                    DropPolicy::DropNewest => {
+                        warn!("audio queue full, dropped a new chunk from {}", input.device);
+                        return Ok(Some(input));
+                    }
+                }
+            }
+            space_ready.await;
+        }
+    }
+}
+
+impl Clone for AudioInputSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for AudioInputSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.item_ready.notify_one();
+        }
+    }
+}
+
+pub struct AudioInputReceiver {
+    shared: Arc<Shared>,
+}
+
+impl AudioInputReceiver {
+    /// Next chunk in the order it was queued, or `None` once every sender is dropped and the
+    /// queue is empty.
+    pub async fn recv(&mut self) -> Option<AudioInput> {
+        loop {
+            let mut item_ready = pin!(self.shared.item_ready.notified());
+            item_ready.as_mut().enable();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some(input) = state.items.pop_front() {
+                    self.shared.space_ready.notify_one();
+                    return Some(input);
+                }
+                if state.senders == 0 {
+                    return None;
+                }
+            }
+            item_ready.await;
+        }
+    }
+}
+
+impl Drop for AudioInputReceiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().receiver_alive = false;
+        self.shared.space_ready.notify_waiters();
+    }
+}
@@ -1,82 +1,549 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use copypasta::{ClipboardContext, ClipboardProvider};
 use log::debug;
+use log::error;
 use log::info;
 use log::warn;
-use screenpipe_audio::create_comm_channel;
+use serde::{Deserialize, Serialize};
+use screenpipe_audio::audio_levels;
+use screenpipe_audio::coalesce_transcriptions;
+use screenpipe_audio::create_comm_channel_with_status;
 use screenpipe_audio::default_input_device;
 use screenpipe_audio::default_output_device;
-use screenpipe_audio::list_audio_devices;
-use screenpipe_audio::parse_audio_device;
+use screenpipe_audio::describe_audio_devices;
+use screenpipe_audio::is_device_excluded;
+use screenpipe_audio::SttError;
+use screenpipe_audio::SttStatus;
+use screenpipe_audio::pcm_decode;
+use screenpipe_audio::perform_stt;
+use screenpipe_audio::list_audio_devices_with_filter;
 use screenpipe_audio::record_and_transcribe;
+use screenpipe_audio::resolve_audio_device;
+use screenpipe_audio::spawn_device_mixer;
+use screenpipe_audio::spawn_stereo_capture;
+use screenpipe_audio::srt;
 use screenpipe_audio::stt::engines::initialize_stt_engines;
+use screenpipe_audio::stt::engines::AssemblyAiConfig;
+use screenpipe_audio::stt::engines::DeepgramConfig;
+use screenpipe_audio::stt::engines::RestPipeConfig;
+use screenpipe_audio::stt::engines::RestPipeUpload;
+use screenpipe_audio::stt::engines::RetryPolicy;
+use screenpipe_audio::stt::engines::SttEngineConfig;
 use screenpipe_audio::stt::RecordingState;
+use screenpipe_audio::stt::SttEngine;
+use screenpipe_audio::stt::SttOptions;
+use screenpipe_audio::stt::Task;
 use screenpipe_audio::AudioDevice;
+use screenpipe_audio::AudioFormat;
+use screenpipe_audio::FfmpegOptions;
+use screenpipe_audio::Normalization;
+use screenpipe_audio::SpeechFilter;
+use screenpipe_audio::ResampleQuality;
+use screenpipe_audio::DevicePreference;
+use screenpipe_audio::download_repo_files;
+use screenpipe_audio::AudioTranscriptionEngine;
+use screenpipe_audio::{HallucinationFilter, WhisperDecodeParams};
 use screenpipe_audio::AudioInput;
+use screenpipe_audio::AudioInputSender;
+use screenpipe_audio::DropPolicy;
+use screenpipe_audio::trim_overlap;
 use screenpipe_audio::TranscriptionResult;
+use screenpipe_audio::Word;
 use screenpipe_audio::VadEngineEnum;
+use screenpipe_audio::WavSampleFormat;
+use screenpipe_audio::vad_engine::create_vad_engine;
+use screenpipe_audio::vad_engine::VadEngine;
+use screenpipe_audio::vad_engine::VadSensitivity;
 use screenpipe_audio::stt::engines::whisper::CandleWhisperModel;
+use screenpipe_audio::stt::engines::whisper::DEFAULT_HALLUCINATIONS;
+use screenpipe_audio::stt::engines::whisper::{default_model_repo, DEFAULT_MODEL_REVISION};
 use tokio::sync::watch::Receiver;
 use tokio::sync::watch::Sender;
+use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
 use tokio::time::timeout;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 use tokio::sync::watch;
 use tokio::sync::mpsc::UnboundedReceiver;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     #[clap(
         short,
         long,
-        help = "Audio device name (can be specified multiple times)"
+        help = "Audio device name, or @N for the Nth device of --list-audio-devices (can be specified multiple times)"
     )]
     audio_device: Vec<String>,
 
+    #[clap(long, help = "Never record this device, e.g. \"Loopback Audio (input)\" (can be specified multiple times)", value_name = "NAME")]
+    exclude_device: Vec<String>,
+
     #[clap(long, help = "List available audio devices")]
     list_audio_devices: bool,
 
+    #[clap(long, help = "Don't hide speakers and AirPods from the macOS output devices")]
+    allow_all_output_devices: bool,
+
     #[clap(long, help = "Local model to use", value_enum)]
     local_model: Option<CandleWhisperModel>,
 
+    #[clap(long, help = "GGML model (.bin) for the whisper.cpp engine, replaces --local-model (requires the whisper-cpp feature)", value_name = "FILE")]
+    whisper_cpp_model: Option<PathBuf>,
+
+    #[clap(long, help = "Unpacked Vosk model directory, a light engine for low-resource devices; replaces --local-model and --whisper-cpp-model (requires the vosk feature)", value_name = "DIR")]
+    vosk_model: Option<PathBuf>,
+
+    #[clap(long, help = "Directory with config.json, tokenizer.json and model.safetensors for the local model, instead of downloading from Hugging Face", value_name = "DIR")]
+    model_dir: Option<PathBuf>,
+
+    #[clap(long, global = true, help = "hf-hub cache directory local models are downloaded into, e.g. one shared between users (default $HF_HOME/hub)", value_name = "DIR")]
+    model_cache_dir: Option<PathBuf>,
+
+    #[clap(long, global = true, help = "Hugging Face repo local whisper loads instead of the one of --local-model, e.g. a fine-tune in the transformers layout", value_name = "REPO")]
+    model_repo: Option<String>,
+
+    #[clap(long, global = true, help = "Branch, tag or commit of the local model repo, to pin the weights (default main)", value_name = "REVISION")]
+    model_revision: Option<String>,
+
+    #[clap(long, help = "Compute device for local whisper: auto, cpu, cuda[:N] or metal[:N]", default_value = "auto")]
+    compute_device: DevicePreference,
+
     #[clap(long, help = "Deepgram API key")]
     deepgram_api_key: Option<String>,
 
-    #[clap(long, help = "API URL", conflicts_with = "deepgram_api_key")]
+    #[clap(long, help = "Deepgram model, e.g. nova-2, nova-3 or whisper", default_value = "nova-2")]
+    deepgram_model: String,
+
+    #[clap(long, help = "Ask Deepgram to label speakers")]
+    deepgram_diarize: bool,
+
+    #[clap(long, help = "Ask Deepgram to add punctuation")]
+    deepgram_punctuate: bool,
+
+    #[clap(long, help = "Ask Deepgram to write numbers as digits")]
+    deepgram_numerals: bool,
+
+    #[clap(long, help = "Language code passed to Deepgram, e.g. en-US")]
+    deepgram_language: Option<String>,
+
+    #[clap(long, help = "Language code to transcribe in (e.g. en) instead of detecting it per chunk", value_name = "CODE")]
+    language: Option<String>,
+
+    #[clap(long, help = "OpenAI API key, transcribes with the hosted whisper-1 model")]
+    openai_api_key: Option<String>,
+
+    #[clap(long, help = "AssemblyAI API key, transcribes with AssemblyAI's pre-recorded API")]
+    assemblyai_api_key: Option<String>,
+
+    #[clap(long, help = "Ask AssemblyAI to label words with their speaker")]
+    assemblyai_speaker_labels: bool,
+
+    #[clap(long, help = "Language code passed to AssemblyAI, e.g. en_us")]
+    assemblyai_language: Option<String>,
+
+    #[clap(long, help = "Milliseconds between checks of a pending AssemblyAI transcript", default_value_t = 1000)]
+    assemblyai_poll_interval_ms: u64,
+
+    #[clap(long, help = "Seconds to wait for an AssemblyAI transcript before giving up", default_value_t = 300)]
+    assemblyai_timeout_secs: u64,
+
+    #[clap(long, help = "Azure Speech resource key, transcribes with Azure's short-audio API (requests capped at 60s, longer audio is split)", requires = "azure_region")]
+    azure_key: Option<String>,
+
+    #[clap(long, help = "Region of the Azure Speech resource, e.g. westeurope")]
+    azure_region: Option<String>,
+
+    #[clap(long, help = "Language passed to Azure Speech, e.g. en-US (the default)")]
+    azure_language: Option<String>,
+
+    #[clap(long, help = "API URL")]
     api_url: Option<String>,
 
-    #[clap(long, help = "API Headers in the `Name: Value;` format", conflicts_with = "deepgram_api_key")]
+    #[clap(long, help = "API Headers in the `Name: Value;` format")]
     api_headers: Option<String>,
 
+    #[clap(long, help = "Where the transcription is in the API's JSON response, as a dotted path (result.transcript) or a JSON pointer (/results/0/text)", default_value = "text")]
+    api_response_field: String,
+
+    #[clap(long, help = "Bearer token sent to the API in the Authorization header", requires = "api_url")]
+    api_token: Option<String>,
+
+    #[clap(long, help = "Seconds before a request to the API is abandoned", default_value_t = 60)]
+    api_timeout_secs: u64,
+
+    #[clap(long, help = "Send the audio to the API as a WAV file or as raw PCM", value_enum, default_value_t = RestPipeUpload::Wav)]
+    api_upload: RestPipeUpload,
+
+    #[clap(long, help = "Sample encoding of the audio sent to the API", value_enum, default_value_t = WavSampleFormat::I16)]
+    api_sample_format: WavSampleFormat,
+
+    #[clap(long, help = "Content type for raw PCM uploads (default describes the samples, e.g. audio/pcm;rate=16000;channels=1;encoding=s16le)")]
+    api_content_type: Option<String>,
+
+    #[clap(long, help = "Resample audio to this rate before sending it to the API: 8000, 16000, 22050, 44100 or 48000 (default: send as is, 16kHz after VAD)", value_name = "HZ")]
+    restpipe_sample_rate: Option<u32>,
+
+    #[clap(long, help = "Attempts per request for network STT engines (retries connection errors, 429 and 5xx)", default_value_t = 3)]
+    stt_attempts: u32,
+
+    #[clap(long, help = "Translate the speech into English instead of transcribing it (local whisper only)")]
+    translate: bool,
+
+    #[clap(long, help = "VAD sensitivity: high keeps quieter speech, low drops more background noise", value_enum)]
+    vad_sensitivity: Option<VadSensitivity>,
+
+    #[clap(
+        long,
+        help = "WebRTC VAD aggressiveness from 0 to 3 (default 3). Higher drops more non-speech but may clip quiet speech",
+        value_parser = clap::value_parser!(u8).range(0..=3),
+        conflicts_with = "vad_sensitivity"
+    )]
+    vad_aggressiveness: Option<u8>,
+
+    #[clap(long, help = "Use the Silero VAD model (downloaded on first use) instead of WebRTC", conflicts_with = "vad_aggressiveness")]
+    silero_vad: bool,
+
+    #[clap(long, help = "File with one phrase per line that local whisper drops when a segment is only that phrase (replaces the built-in list)", value_name = "FILE")]
+    hallucination_blocklist: Option<PathBuf>,
+
+    #[clap(long, help = "Local whisper drops segments whose no-speech probability is above this", default_value_t = 0.8)]
+    max_no_speech_prob: f64,
+
+    #[clap(long, help = "Text local whisper decodes every chunk after, e.g. names and jargon the audio contains; long prompts keep their end", value_name = "TEXT")]
+    prompt: Option<String>,
+
+    #[clap(long, help = "Beams local whisper searches at temperature 0; 1 decodes greedily", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    beam_size: u16,
+
+    #[clap(long, help = "Samples local whisper draws at each fallback temperature, keeping the most likely", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    best_of: u16,
+
+    #[clap(long, help = "Comma-separated temperatures local whisper falls back through when a decoding looks repetitive or unlikely (default 0,0.2,0.4,0.6,0.8,1)", value_delimiter = ',', value_name = "TEMPERATURES")]
+    temperatures: Option<Vec<f64>>,
+
+    #[clap(long, help = "Local whisper retries at the next temperature when a decoding compresses better than this (default 2.4)")]
+    compression_ratio_threshold: Option<f64>,
+
+    #[clap(long, help = "Local whisper retries at the next temperature when the average token log-probability is below this (default -1)", allow_hyphen_values = true)]
+    logprob_threshold: Option<f64>,
+
+    #[clap(long, help = "How many chunks from different devices to transcribe at once; each local whisper call needs its own GPU memory", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    stt_concurrency: u16,
+
+    #[clap(long, help = "Recorded chunks that may wait for transcription before --drop-policy applies", default_value_t = 32, value_parser = clap::value_parser!(u64).range(1..))]
+    queue_capacity: u64,
+
+    #[clap(long, help = "What to do with new chunks when the transcription queue is full", value_enum, default_value_t = DropPolicy::Block)]
+    drop_policy: DropPolicy,
+
+    #[clap(long, help = "Give up on an STT call after this many seconds and try the fallback engine (0 to wait forever)", default_value_t = 120)]
+    stt_timeout_secs: u64,
+
+    #[clap(long, help = "Drop transcribed segments whose confidence (mean token probability, 0-1) is below this; local whisper only", value_name = "SCORE")]
+    min_confidence: Option<f32>,
+
+    #[clap(long, help = "Skip voice activity detection and send all audio to the STT engine", conflicts_with_all = ["vad_sensitivity", "vad_aggressiveness", "silero_vad"])]
+    no_vad: bool,
+
+    #[clap(long, help = "Milliseconds of audio kept around detected speech", default_value_t = 200)]
+    speech_padding_ms: u64,
+
+    #[clap(long, help = "Boost quiet recordings to -20 dBFS RMS before VAD and transcription, leaving audio below -60 dBFS alone")]
+    normalize: bool,
+
+    #[clap(long, help = "High-pass filter below this many Hz before VAD, against mains hum (80 suits speech)", value_name = "HZ")]
+    highpass: Option<f32>,
+
+    #[clap(long, help = "Resampler quality: fast uses far less CPU for real-time use on weak machines", value_enum, default_value_t = ResampleQuality::High)]
+    resample_quality: ResampleQuality,
+
+    #[clap(long, help = "Low-pass filter above this many Hz before VAD, against hiss (below 8000, e.g. 7000)", value_name = "HZ")]
+    lowpass: Option<f32>,
+
+    #[clap(long, help = "Stop recording after this many seconds without speech on every device (default: stop on the first chunk without speech)", value_name = "SECONDS", conflicts_with = "no_vad")]
+    max_silence: Option<u64>,
+
     #[clap(short, long, help = "Enable verbose output", conflicts_with = "very_verbose")]
     verbose: bool,
     
     #[clap(short = 'D', long = "very-verbose", help = "Enable very verbose output", conflicts_with = "verbose")]
     very_verbose: bool,
 
+    #[clap(long, help = "Transcribe this audio or video file instead of recording", value_name = "PATH", conflicts_with_all = ["audio_device", "duration"])]
+    input_file: Option<PathBuf>,
+
+    #[clap(long, help = "Transcribe every supported audio file in this directory instead of recording", value_name = "DIR", conflicts_with_all = ["audio_device", "duration", "input_file", "file"])]
+    input_dir: Option<PathBuf>,
+
+    #[clap(long, help = "With --input-dir, where to write the per-file transcripts (defaults to the input directory)", value_name = "DIR", requires = "input_dir")]
+    output_dir: Option<PathBuf>,
+
+    #[clap(long, help = "With --input-dir, how many files to transcribe at once", default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
     #[clap(short, long, help = "Output to file", value_name = "FILE")]
     file: Option<PathBuf>,
 
+    #[clap(long, help = "Copy the transcription to the clipboard when recording ends", conflicts_with = "input_dir")]
+    clipboard: bool,
+
+    #[clap(long, help = "Keep the clipboard updated with the transcription so far while recording, for pasting mid-session", conflicts_with_all = ["input_file", "input_dir"])]
+    clipboard_live: bool,
+
+    #[clap(long, help = "Append every transcription to this JSONL file as it arrives, so a crash loses nothing", value_name = "FILE", conflicts_with_all = ["input_file", "input_dir"])]
+    journal: Option<PathBuf>,
+
+    #[clap(long, help = "Continue the session of --journal: its transcriptions start the output and new ones are appended to it", requires = "journal")]
+    resume: bool,
+
+    #[clap(long, help = "Seconds to wait for the queued transcriptions when the recording ends, the chunks still queued after that are abandoned. Defaults to an estimate from the queue depth and the last chunk's transcription time (10-600s)", value_name = "SECONDS", conflicts_with_all = ["input_file", "input_dir"])]
+    drain_timeout: Option<u64>,
+
+    #[clap(long, help = "Write one transcript per recorded device instead of merging them, named <FILE stem>-<device> next to --file (default transcript.txt or .json)", conflicts_with_all = ["input_file", "input_dir"])]
+    split_by_device: bool,
+
+    #[clap(
+        long,
+        help = "Seconds of audio per transcription chunk (1-60). Short chunks give lower latency for live captions, long chunks give whisper more context and better accuracy",
+        default_value_t = 5,
+        value_parser = clap::value_parser!(u64).range(1..=60)
+    )]
+    chunk_duration: u64,
+
+    #[clap(long, help = "Milliseconds of audio repeated at the start of each chunk so words across chunk boundaries aren't cut; repeated words are removed from the output", default_value_t = 0)]
+    chunk_overlap_ms: u64,
+
+    #[clap(long, help = "Mix the audio of all recorded devices (e.g. mic and system audio) into one stream and transcribe it as a single timeline", conflicts_with_all = ["chunk_overlap_ms", "input_file", "input_dir"])]
+    mix_devices: bool,
+
+    #[clap(long, help = "Merge consecutive transcriptions of the same device into one passage, dropping words repeated across chunks (text, JSON and clipboard output; SRT keeps one cue per chunk)")]
+    coalesce: bool,
+
+    #[clap(long, help = "Record the two --audio-device devices into one stereo stream, the first (e.g. the mic) on the left channel and the second (e.g. the system audio) on the right", conflicts_with_all = ["mix_devices", "chunk_overlap_ms", "input_file", "input_dir"])]
+    stereo: bool,
+
+    #[clap(long, help = "Print interim transcriptions to stderr as they arrive (streaming engines such as Deepgram)")]
+    partials: bool,
+
+    #[clap(long, help = "Output a JSON array with one object per transcribed chunk instead of plain text; with --list-audio-devices, list the devices as JSON")]
+    json: bool,
+
+    #[clap(long, help = "Write SRT subtitles to the given file; with --input-dir, the directory to write one .srt per input file to", value_name = "FILE")]
+    srt: Option<PathBuf>,
+
     #[clap(long, help = "Recording output directory", value_name = "DIR")]
     dir: Option<PathBuf>,
 
+    #[clap(long, help = "ffmpeg executable the recordings are encoded with, 4.0 or newer with the aac, flac and libopus encoders (default: $FFMPEG_PATH, then PATH)", value_name = "FILE")]
+    ffmpeg_path: Option<PathBuf>,
+
+    #[clap(long, help = "Extra ffmpeg argument for the recordings, placed before the output path so it can override the codec settings (can be specified multiple times)", value_name = "ARG", allow_hyphen_values = true)]
+    ffmpeg_arg: Vec<String>,
+
+    #[clap(long, help = "Save the resampled and VAD-filtered audio of every chunk as WAV here, for debugging dropped speech", value_name = "DIR")]
+    debug_audio_dir: Option<PathBuf>,
+
+    #[clap(long, help = "Format of the recordings saved to --dir", value_enum, default_value_t = AudioFormat::Mp4)]
+    audio_format: AudioFormat,
+
+    #[clap(
+        long,
+        help = "Bitrate in kbps for mp4/opus recordings (default 64). Recordings are the device's original audio; for speech 24-32 kbps Opus or 48 kbps AAC is plenty. Ignored for flac and wav",
+        value_name = "KBPS"
+    )]
+    audio_bitrate: Option<u32>,
+
     #[clap(short, long, help = "Duration in seconds to record")]
     duration: Option<u32>,
 }
 
+/// One line of the `--journal` file, readable as a transcript on its own
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    device: String,
+    timestamp: u64,
+    text: Option<String>,
+    path: String,
+    error: Option<String>,
+    words: Vec<Word>,
+    engine: String,
+    used_fallback: bool,
+    no_speech: bool,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+impl From<&TranscriptionResult> for JournalEntry {
+    fn from(result: &TranscriptionResult) -> Self {
+        Self {
+            device: result.input.device.clone(),
+            timestamp: result.timestamp,
+            text: result.transcription.clone(),
+            path: result.path.clone(),
+            error: result.error.clone(),
+            words: result.words.clone(),
+            engine: result.engine.clone(),
+            used_fallback: result.used_fallback,
+            no_speech: result.no_speech,
+            language: result.language.clone(),
+        }
+    }
+}
+
+impl From<JournalEntry> for TranscriptionResult {
+    fn from(entry: JournalEntry) -> Self {
+        TranscriptionResult {
+            // The journal doesn't keep the audio, the recording at `path` has it
+            input: AudioInput {
+                data: Vec::new(),
+                sample_rate: 0,
+                channels: 1,
+                device: entry.device,
+                peak: 0.0,
+                rms: 0.0,
+            },
+            transcription: entry.text,
+            words: entry.words,
+            path: entry.path,
+            timestamp: entry.timestamp,
+            error: entry.error,
+            is_final: true,
+            engine: entry.engine,
+            used_fallback: entry.used_fallback,
+            no_speech: entry.no_speech,
+            vad_timeline: None,
+            language: entry.language,
+        }
+    }
+}
+
+/// The `--journal` file results are appended to as they arrive. Write errors are logged and
+/// don't stop the recording.
+struct Journal {
+    path: PathBuf,
+    file: std::fs::File,
+}
+
+impl Journal {
+    /// Opens `path` for appending. With `resume` the results already journaled are returned,
+    /// without it the journal must be new or empty so a crashed session isn't mixed into the
+    /// next one.
+    fn open(path: &Path, resume: bool) -> Result<(Self, Vec<TranscriptionResult>)> {
+        let existing = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.len() > 0,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => return Err(anyhow!("Failed to read journal {:?}: {}", path, e)),
+        };
+        if existing && !resume {
+            return Err(anyhow!(
+                "Journal {:?} already has transcriptions, pass --resume to continue it or remove it",
+                path
+            ));
+        }
+        let resumed = if existing { Self::read(path)? } else { Vec::new() };
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("Failed to open journal {:?}: {}", path, e))?;
+        Ok((Self { path: path.to_path_buf(), file }, resumed))
+    }
+
+    fn read(path: &Path) -> Result<Vec<TranscriptionResult>> {
+        let file = std::fs::File::open(path).map_err(|e| anyhow!("Failed to open journal {:?}: {}", path, e))?;
+        let mut results = Vec::new();
+        for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // A crash mid-write leaves a truncated last line
+            match serde_json::from_str::<JournalEntry>(&line) {
+                Ok(entry) => results.push(entry.into()),
+                Err(e) => warn!("Skipping line {} of journal {:?}: {}", index + 1, path, e),
+            }
+        }
+        info!("Resuming {} transcriptions from {:?}", results.len(), path);
+        Ok(results)
+    }
+
+    fn append(&mut self, result: &TranscriptionResult) {
+        if let Err(e) = self.write(result) {
+            error!("Failed to write to journal {:?}: {}", self.path, e);
+        }
+    }
+
+    fn write(&mut self, result: &TranscriptionResult) -> Result<()> {
+        let line = serde_json::to_string(&JournalEntry::from(result))?;
+        writeln!(self.file, "{}", line)?;
+        // Synced per line, the point of the journal is surviving a crash
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// One entry of the `--json` output
+#[derive(Serialize)]
+struct JsonTranscription<'a> {
+    device: &'a str,
+    timestamp: u64,
+    text: Option<&'a str>,
+    path: &'a str,
+    error: Option<&'a str>,
+    words: &'a [Word],
+    engine: &'a str,
+    used_fallback: bool,
+    language: Option<&'a str>,
+}
+
+impl<'a> From<&'a TranscriptionResult> for JsonTranscription<'a> {
+    fn from(result: &'a TranscriptionResult) -> Self {
+        Self {
+            device: &result.input.device,
+            timestamp: result.timestamp,
+            text: result.transcription.as_deref(),
+            path: &result.path,
+            error: result.error.as_deref(),
+            words: &result.words,
+            engine: &result.engine,
+            used_fallback: result.used_fallback,
+            language: result.language.as_deref(),
+        }
+    }
+}
+
 fn print_devices(devices: &[AudioDevice]) {
     println!("Available audio devices:");
-    for (_, device) in devices.iter().enumerate() {
-        println!("  {}", device);
+    for (i, device) in devices.iter().enumerate() {
+        println!("  @{} {}", i, device);
     }
 
     #[cfg(target_os = "macos")]
     println!("On macOS, it's not intuitive but output devices are your displays");
 }
 
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Download the weights of a local model into the cache and exit, so the first
+    /// transcription doesn't wait for them
+    DownloadModel {
+        #[clap(help = "Model to download (default: --local-model, or tiny)", value_enum)]
+        model: Option<CandleWhisperModel>,
+    },
+}
+
 // ! usage - cargo run --bin screenpipe-audio -- --audio-device "Display 1 (output)"
 
 #[tokio::main]
@@ -97,10 +564,36 @@ async fn main() -> Result<()> {
         .filter_module("tokenizers", LevelFilter::Error)
         .init();
 
-    let devices = list_audio_devices().await?;
+    if let Some(Command::DownloadModel { model }) = &args.command {
+        let model = model.clone().or(args.local_model.clone()).unwrap_or(CandleWhisperModel::Tiny);
+        let engine = match model {
+            CandleWhisperModel::Tiny => AudioTranscriptionEngine::WhisperTiny,
+            CandleWhisperModel::DistillLarge => AudioTranscriptionEngine::WhisperDistilLargeV3,
+        };
+        let repo = args.model_repo.as_deref().unwrap_or(default_model_repo(&engine));
+        let revision = args.model_revision.as_deref().unwrap_or(DEFAULT_MODEL_REVISION);
+        let (config, tokenizer, weights) = download_repo_files(repo, revision, args.model_cache_dir.as_deref())?;
+        for path in [config, tokenizer, weights] {
+            println!("{}", path.display());
+        }
+        return Ok(());
+    }
+
+    // File transcription doesn't touch the audio host
+    let transcribing_files = args.input_file.is_some() || args.input_dir.is_some();
+    let devices = if transcribing_files && !args.list_audio_devices {
+        Vec::new()
+    } else {
+        list_audio_devices_with_filter(args.allow_all_output_devices).await?
+    };
 
     if args.list_audio_devices {
-        print_devices(&devices);
+        if args.json {
+            let infos = describe_audio_devices(&devices).await;
+            println!("{}", serde_json::to_string_pretty(&infos)?);
+        } else {
+            print_devices(&devices);
+        }
         return Ok(());
     }
 
@@ -110,60 +603,592 @@ async fn main() -> Result<()> {
         }
     }
     
-    let devices = if args.audio_device.is_empty() {
+    if let Some(dir) = &args.debug_audio_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let devices = if transcribing_files {
+        Vec::new()
+    } else if args.audio_device.is_empty() {
         vec![default_input_device()?, default_output_device().await?]
     } else {
         args.audio_device
             .iter()
-            .map(|d| parse_audio_device(d))
+            .map(|spec| resolve_audio_device(spec, &devices))
             .collect::<Result<Vec<_>>>()?
     };
+    let devices: Vec<AudioDevice> = devices
+        .into_iter()
+        .filter(|device| {
+            let excluded = is_device_excluded(device, &args.exclude_device);
+            if excluded {
+                info!("Skipping excluded device: {}", device);
+            }
+            !excluded
+        })
+        .collect();
 
-    if devices.is_empty() {
+    if devices.is_empty() && !transcribing_files {
         return Err(anyhow!("No audio input devices found"));
     }
 
-    let chunk_duration = Duration::from_secs(5);
+    let chunk_duration = Duration::from_secs(args.chunk_duration);
+    let chunk_overlap = Duration::from_millis(args.chunk_overlap_ms);
+    if chunk_overlap >= chunk_duration {
+        return Err(anyhow!("--chunk-overlap-ms must be shorter than --chunk-duration"));
+    }
     let output_path = args.dir.map(PathBuf::from);
 
-    let (primary_engine, fallback_engine) = initialize_stt_engines(
-        args.local_model,
-        args.api_url,
-        args.api_headers,
-        args.deepgram_api_key,
-    )?;
+    let hallucination_filter = match &args.hallucination_blocklist {
+        Some(path) => {
+            let phrases = std::fs::read_to_string(path)?;
+            HallucinationFilter::new(phrases.lines(), args.max_no_speech_prob)
+        }
+        None => HallucinationFilter::new(DEFAULT_HALLUCINATIONS, args.max_no_speech_prob),
+    };
 
-    let (whisper_sender, whisper_receiver, state_tx, state_rx) = create_comm_channel(
-        primary_engine,
-        fallback_engine,
-        VadEngineEnum::WebRtc,
-        &output_path,
-    )?;
+    let defaults = WhisperDecodeParams::default();
+    let whisper_decode_params = WhisperDecodeParams {
+        temperatures: args.temperatures.unwrap_or(defaults.temperatures),
+        best_of: args.best_of as usize,
+        beam_size: args.beam_size as usize,
+        compression_ratio_threshold: args.compression_ratio_threshold.unwrap_or(defaults.compression_ratio_threshold),
+        logprob_threshold: args.logprob_threshold.unwrap_or(defaults.logprob_threshold),
+    };
 
-    // Spawn recording threads
-    let recording_threads = spawn_recording_threads(devices, whisper_sender, state_tx.clone(), state_rx.clone(),  chunk_duration);
-    wait_for_initialization(state_rx.clone()).await?;
+    let (primary_engine, fallback_engines) = initialize_stt_engines(SttEngineConfig {
+        local_model: args.local_model,
+        whisper_cpp_model: args.whisper_cpp_model,
+        vosk_model: args.vosk_model,
+        local_model_dir: args.model_dir,
+        model_cache_dir: args.model_cache_dir,
+        model_repo: args.model_repo,
+        model_revision: args.model_revision,
+        compute_device: args.compute_device,
+        api_url: args.api_url,
+        api_headers: args.api_headers,
+        restpipe_config: RestPipeConfig {
+            response_field: args.api_response_field,
+            bearer_token: args.api_token,
+            timeout: Duration::from_secs(args.api_timeout_secs),
+            upload: args.api_upload,
+            sample_format: args.api_sample_format,
+            raw_content_type: args.api_content_type,
+            resample_to_rate: args.restpipe_sample_rate,
+            ..Default::default()
+        },
+        deepgram_api_key: args.deepgram_api_key,
+        deepgram_config: DeepgramConfig {
+            model: args.deepgram_model,
+            diarize: args.deepgram_diarize,
+            punctuate: args.deepgram_punctuate,
+            numerals: args.deepgram_numerals,
+            language: args.deepgram_language,
+            ..Default::default()
+        },
+        openai_api_key: args.openai_api_key,
+        assemblyai_api_key: args.assemblyai_api_key,
+        azure_key: args.azure_key,
+        azure_region: args.azure_region,
+        azure_language: args.azure_language,
+        assemblyai_config: AssemblyAiConfig {
+            speaker_labels: args.assemblyai_speaker_labels,
+            language_code: args.assemblyai_language,
+            poll_interval: Duration::from_millis(args.assemblyai_poll_interval_ms),
+            timeout: Duration::from_secs(args.assemblyai_timeout_secs),
+        },
+        language: args.language,
+        hallucination_filter,
+        whisper_decode_params,
+        initial_prompt: args.prompt,
+        task: if args.translate { Task::Translate } else { Task::Transcribe },
+        retry_policy: RetryPolicy::with_max_attempts(args.stt_attempts),
+        resample_quality: args.resample_quality,
+        ..Default::default()
+    })?;
 
-    // TODO: Remove this. Replace with tokio control c handler for early shutdown
-    let kb_task_join_handle = start_keyboard_listener_task(state_tx.clone(), state_rx.clone());
+    let vad_engine = if args.no_vad {
+        VadEngineEnum::None
+    } else if args.silero_vad {
+        VadEngineEnum::Silero
+    } else {
+        VadEngineEnum::WebRtc { aggressiveness: args.vad_aggressiveness }
+    };
+    let stt_options = SttOptions {
+        speech_padding: Duration::from_millis(args.speech_padding_ms),
+        partials: args.partials,
+        output_format: args.audio_format,
+        output_bitrate_kbps: args.audio_bitrate,
+        ffmpeg: FfmpegOptions {
+            binary: args.ffmpeg_path.clone(),
+            extra_args: args.ffmpeg_arg.clone(),
+        },
+        debug_audio_dir: args.debug_audio_dir.clone(),
+        min_confidence: args.min_confidence,
+        max_concurrent_transcriptions: args.stt_concurrency as usize,
+        input_queue_capacity: args.queue_capacity as usize,
+        drop_policy: args.drop_policy,
+        transcribe_timeout: (args.stt_timeout_secs > 0).then(|| Duration::from_secs(args.stt_timeout_secs)),
+        max_silence: args.max_silence.map(Duration::from_secs),
+        // Logged by log_vad_timeline
+        vad_timeline: args.very_verbose,
+        normalize: args.normalize.then(Normalization::default),
+        filter: SpeechFilter::new(args.highpass, args.lowpass)?,
+        resample_quality: args.resample_quality,
+    };
 
-    // Spawn duration task if duration is specified
-    if let Some(duration) = args.duration {
-        start_max_duration_task(state_tx.clone(), duration as u64);
+    if let Some(input_dir) = &args.input_dir {
+        let output_dir = args.output_dir.clone().unwrap_or_else(|| input_dir.clone());
+        std::fs::create_dir_all(&output_dir)?;
+        if let Some(srt_dir) = &args.srt {
+            std::fs::create_dir_all(srt_dir)?;
+        }
+        let outputs = BatchOutputs {
+            dir: output_dir,
+            srt_dir: args.srt.clone(),
+            json: args.json,
+        };
+        return transcribe_dir(
+            input_dir,
+            Arc::from(primary_engine),
+            fallback_engines.into_iter().map(Arc::from).collect(),
+            vad_engine,
+            args.vad_sensitivity,
+            output_path,
+            stt_options,
+            outputs,
+            args.jobs as usize,
+        )
+        .await;
     }
+
+    let (results, status) = if let Some(input_file) = &args.input_file {
+        let mut vad = create_vad_engine(vad_engine)?;
+        if let Some(sensitivity) = args.vad_sensitivity {
+            vad.set_sensitivity(sensitivity);
+        }
+        let fallback_engines: Vec<&(dyn SttEngine + Send + Sync)> = fallback_engines.iter().map(|engine| &**engine).collect();
+        let result = transcribe_file(input_file, &*primary_engine, &fallback_engines, &mut *vad, &output_path, &stt_options).await;
+        if let (Some(error), false) = (&result.error, result.no_speech) {
+            return Err(anyhow!("Failed to transcribe {:?}: {}", input_file, error));
+        }
+        let results = vec![result];
+        let status = RunStatus::from_results(&results);
+        (results, status)
+    } else {
+        // Opened first, a journal that can't be resumed shouldn't cost a recording
+        let (mut journal, resumed) = match &args.journal {
+            Some(path) => {
+                let (journal, resumed) = Journal::open(path, args.resume)?;
+                (Some(journal), resumed)
+            }
+            None => (None, Vec::new()),
+        };
+
+        let (whisper_sender, whisper_receiver, state_tx, state_rx, status_rx) = create_comm_channel_with_status(
+            primary_engine,
+            fallback_engines,
+            vad_engine,
+            args.vad_sensitivity,
+            &output_path,
+            stt_options,
+        )?;
+
+        let whisper_sender = if args.stereo {
+            let [left, right] = devices.as_slice() else {
+                return Err(anyhow!("--stereo needs exactly two recorded devices, got {}", devices.len()));
+            };
+            spawn_stereo_capture(left.to_string(), right.to_string(), chunk_duration, args.resample_quality, args.queue_capacity as usize, args.drop_policy, whisper_sender)
+        } else if args.mix_devices && devices.len() > 1 {
+            let names = devices.iter().map(|device| device.to_string()).collect();
+            spawn_device_mixer(names, chunk_duration, args.resample_quality, args.queue_capacity as usize, args.drop_policy, whisper_sender)
+        } else {
+            whisper_sender
+        };
+
+        // Spawn recording threads
+        let recording_threads = spawn_recording_threads(devices, whisper_sender, state_tx.clone(), state_rx.clone(), chunk_duration, chunk_overlap);
+        wait_for_initialization(state_rx.clone()).await?;
+
+        start_signal_handler_task(state_tx.clone());
+        let kb_task_join_handle = start_keyboard_listener_task(state_tx.clone(), state_rx.clone());
+
+        // Spawn duration task if duration is specified
+        if let Some(duration) = args.duration {
+            start_max_duration_task(state_tx.clone(), duration as u64);
+        }
   
-    // Start main transcription loop
-    let transcription_buffer = run_transcription_loop(whisper_receiver, state_rx, state_tx).await?;
+        let mut live_clipboard = args.clipboard_live.then(LiveClipboard::new);
+
+        let drain_timeout = DrainTimeout {
+            fixed: args.drain_timeout.map(Duration::from_secs),
+            chunk_duration,
+            status_rx,
+        };
+
+        // Start main transcription loop
+        let (mut results, status) = run_transcription_loop(whisper_receiver, state_rx, state_tx, live_clipboard.as_mut(), journal.as_mut(), drain_timeout).await?;
+        if !chunk_overlap.is_zero() {
+            remove_overlapping_words(&mut results);
+        }
+        let (results, status) = if resumed.is_empty() {
+            (results, status)
+        } else {
+            let results: Vec<TranscriptionResult> = resumed.into_iter().chain(results).collect();
+            let status = RunStatus::from_results(&results);
+            (results, status)
+        };
+
+        shutdown_and_cleanup(recording_threads, kb_task_join_handle).await?;
+
+        (results, status)
+    };
+
+    if let Some(srt_path) = &args.srt {
+        let cues = srt::build_cues(&results);
+        std::fs::write(srt_path, srt::render(&cues))?;
+        info!("Wrote {} subtitle cues to {:?}", cues.len(), srt_path);
+    }
+
+    let results = if args.coalesce {
+        coalesce_transcriptions(results)
+    } else {
+        results
+    };
+
+    if args.clipboard || args.clipboard_live {
+        // Plain text even with --json, that's what gets pasted
+        let text = format_output(&results, false)?;
+        if let Err(e) = ClipboardContext::new().and_then(|mut clipboard| clipboard.set_contents(text)) {
+            error!("Failed to copy the transcription to the clipboard: {}", e);
+        }
+    }
 
-    shutdown_and_cleanup(recording_threads, kb_task_join_handle).await?;
+    if args.split_by_device {
+        write_per_device(results, args.file.as_deref(), args.json)?;
+    } else {
+        let output = format_output(&results, args.json)?;
 
-    println!("{}", transcription_buffer.trim());
+        match &args.file {
+            Some(file) => std::fs::write(file, format!("{}\n", output))?,
+            None => println!("{}", output),
+        }
+    }
 
-    info!("Application ending");
+    info!("Application ending: {:?}", status);
+    if status != RunStatus::Transcribed {
+        // Nothing is left to drop, the output above is already written and flushed
+        std::process::exit(status.exit_code());
+    }
 
     Ok(())
 }
 
+/// Outcome of a recording or file transcription, reported in the exit code so scripts can
+/// tell silence from failures. Device and setup errors exit with 1 through `main`'s error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunStatus {
+    /// Some text was transcribed: exit code 0
+    Transcribed,
+    /// VAD found no speech in anything recorded: exit code 2
+    NoSpeech,
+    /// No text, and at least one chunk failed in the STT engine: exit code 3
+    EngineErrors,
+}
+
+impl RunStatus {
+    fn from_results(results: &[TranscriptionResult]) -> Self {
+        let transcribed = results
+            .iter()
+            .any(|result| result.transcription.as_deref().is_some_and(|text| !text.trim().is_empty()));
+        if transcribed {
+            RunStatus::Transcribed
+        } else if results.iter().any(|result| result.error.is_some() && !result.no_speech) {
+            RunStatus::EngineErrors
+        } else {
+            RunStatus::NoSpeech
+        }
+    }
+
+    fn exit_code(self) -> i32 {
+        match self {
+            RunStatus::Transcribed => 0,
+            RunStatus::NoSpeech => 2,
+            RunStatus::EngineErrors => 3,
+        }
+    }
+}
+
+/// Shows where VAD heard voice in the chunk of `result`, with `--very-verbose`.
+fn log_vad_timeline(result: &TranscriptionResult) {
+    if let Some(timeline) = &result.vad_timeline {
+        debug!("device: {}, VAD {}", result.input.device, timeline);
+    } else if result.no_speech {
+        debug!("device: {}, VAD heard no voice in the chunk", result.input.device);
+    }
+}
+
+/// Renders results as the plain-text transcript, or as the `--json` array.
+fn format_output(results: &[TranscriptionResult], json: bool) -> Result<String> {
+    if json {
+        let entries: Vec<JsonTranscription> = results.iter().map(JsonTranscription::from).collect();
+        return Ok(serde_json::to_string_pretty(&entries)?);
+    }
+    Ok(results
+        .iter()
+        .filter_map(|result| result.transcription.as_deref())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim()
+        .to_string())
+}
+
+/// Writes the results of each device to its own file for `--split-by-device`, named after
+/// `file` (or `transcript.txt`/`.json`) with the device appended to the stem.
+fn write_per_device(results: Vec<TranscriptionResult>, file: Option<&Path>, json: bool) -> Result<()> {
+    let default_name = if json { "transcript.json" } else { "transcript.txt" };
+    let base = file.unwrap_or(Path::new(default_name));
+    let stem = base.file_stem().and_then(|s| s.to_str()).unwrap_or("transcript");
+    let extension = base
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or(if json { "json" } else { "txt" });
+
+    let mut by_device: HashMap<String, Vec<TranscriptionResult>> = HashMap::new();
+    for result in results {
+        by_device.entry(result.input.device.clone()).or_default().push(result);
+    }
+    for (device, results) in by_device {
+        // Same sanitizing as the recordings perform_stt saves
+        let device = device.replace([' ', ':', '/', '\\'], "_");
+        let path = base.with_file_name(format!("{}-{}.{}", stem, device, extension));
+        std::fs::write(&path, format!("{}\n", format_output(&results, json)?))?;
+        info!("Wrote {} chunks to {:?}", results.len(), path);
+    }
+    Ok(())
+}
+
+/// Extensions `pcm_decode` can read with the enabled symphonia features
+const SUPPORTED_EXTENSIONS: [&str; 8] = ["wav", "flac", "ogg", "mp4", "m4a", "aac", "mkv", "webm"];
+
+/// Where `--input-dir` writes the transcript of each file
+struct BatchOutputs {
+    dir: PathBuf,
+    srt_dir: Option<PathBuf>,
+    json: bool,
+}
+
+impl BatchOutputs {
+    fn write(&self, path: &Path, result: &TranscriptionResult) -> Result<()> {
+        let stem = path.file_stem().unwrap_or(path.as_os_str());
+        let extension = if self.json { "json" } else { "txt" };
+        let results = std::slice::from_ref(result);
+        std::fs::write(
+            self.dir.join(stem).with_extension(extension),
+            format!("{}\n", format_output(results, self.json)?),
+        )?;
+        if let Some(srt_dir) = &self.srt_dir {
+            let cues = srt::build_cues(results);
+            std::fs::write(srt_dir.join(stem).with_extension("srt"), srt::render(&cues))?;
+        }
+        Ok(())
+    }
+}
+
+fn list_input_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let supported = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map_or(false, |e| SUPPORTED_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+        if path.is_file() && supported {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Transcribes every supported file in `dir`, at most `jobs` at a time, and writes one transcript
+/// per file. Failures are logged and summarized at the end rather than stopping the batch.
+#[allow(clippy::too_many_arguments)]
+async fn transcribe_dir(
+    dir: &Path,
+    primary_engine: Arc<dyn SttEngine + Send + Sync>,
+    fallback_engines: Arc<[Arc<dyn SttEngine + Send + Sync>]>,
+    vad_engine: VadEngineEnum,
+    vad_sensitivity: Option<VadSensitivity>,
+    output_path: Option<PathBuf>,
+    stt_options: SttOptions,
+    outputs: BatchOutputs,
+    jobs: usize,
+) -> Result<()> {
+    let files = list_input_files(dir)?;
+    if files.is_empty() {
+        return Err(anyhow!("No supported audio files found in {:?}", dir));
+    }
+    info!("Transcribing {} files from {:?} with {} workers", files.len(), dir, jobs);
+
+    let semaphore = Arc::new(Semaphore::new(jobs));
+    let output_path = Arc::new(output_path);
+    let stt_options = Arc::new(stt_options);
+    let outputs = Arc::new(outputs);
+    let mut handles = Vec::with_capacity(files.len());
+    for path in files {
+        let semaphore = semaphore.clone();
+        let primary_engine = primary_engine.clone();
+        let fallback_engines = fallback_engines.clone();
+        let output_path = output_path.clone();
+        let stt_options = stt_options.clone();
+        let outputs = outputs.clone();
+        handles.push((
+            path.clone(),
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                let mut vad = create_vad_engine(vad_engine)?;
+                if let Some(sensitivity) = vad_sensitivity {
+                    vad.set_sensitivity(sensitivity);
+                }
+                let fallback_engines: Vec<&(dyn SttEngine + Send + Sync)> =
+                    fallback_engines.iter().map(|engine| &**engine).collect();
+                let result = transcribe_file(
+                    &path,
+                    &*primary_engine,
+                    &fallback_engines,
+                    &mut *vad,
+                    &output_path,
+                    &stt_options,
+                )
+                .await;
+                if result.no_speech {
+                    return Ok(FileOutcome::NoSpeech);
+                }
+                match &result.error {
+                    Some(error) => Err(anyhow!("{}", error)),
+                    None => outputs.write(&path, &result).map(|_| FileOutcome::Transcribed),
+                }
+            }),
+        ));
+    }
+
+    let total = handles.len();
+    let mut skipped = Vec::new();
+    let mut failures = Vec::new();
+    for (path, handle) in handles {
+        let outcome: Result<FileOutcome> = handle.await.map_err(anyhow::Error::from).and_then(|r| r);
+        match outcome {
+            Ok(FileOutcome::Transcribed) => {}
+            Ok(FileOutcome::NoSpeech) => {
+                info!("No speech in {:?}, skipped", path);
+                skipped.push(path);
+            }
+            Err(e) => {
+                error!("Failed to transcribe {:?}: {}", path, e);
+                failures.push(path);
+            }
+        }
+    }
+
+    eprintln!(
+        "Transcribed {} of {} files, {} skipped without speech",
+        total - failures.len() - skipped.len(),
+        total,
+        skipped.len()
+    );
+    for path in &skipped {
+        eprintln!("  no speech: {}", path.display());
+    }
+    for path in &failures {
+        eprintln!("  failed: {}", path.display());
+    }
+    if failures.len() == total {
+        return Err(anyhow!("All {} files failed to transcribe", total));
+    }
+    Ok(())
+}
+
+/// What became of one file of `transcribe_dir`
+enum FileOutcome {
+    Transcribed,
+    /// VAD found no speech in it, neither transcribed nor a failure
+    NoSpeech,
+}
+
+/// Decodes an audio or video file and transcribes it in one piece. Decoding and STT errors are
+/// reported in the result's `error`.
+async fn transcribe_file(
+    path: &Path,
+    primary_engine: &(dyn SttEngine + Send + Sync),
+    fallback_engines: &[&(dyn SttEngine + Send + Sync)],
+    vad_engine: &mut (dyn VadEngine + Send),
+    output_path: &Option<PathBuf>,
+    stt_options: &SttOptions,
+) -> TranscriptionResult {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    info!("Transcribing file {:?}", path);
+
+    // pcm_decode returns the first channel only
+    let (data, sample_rate, decode_error) = match pcm_decode(path) {
+        Ok((data, sample_rate)) if data.is_empty() => (data, sample_rate, Some("No audio decoded".to_string())),
+        Ok((data, sample_rate)) => (data, sample_rate, None),
+        Err(e) => (Vec::new(), 0, Some(format!("Failed to decode: {}", e))),
+    };
+    let (peak, rms) = audio_levels(&data);
+    let input = AudioInput {
+        data,
+        sample_rate,
+        channels: 1,
+        device: path.display().to_string(),
+        peak,
+        rms,
+    };
+
+    let output = match decode_error {
+        Some(error) => Err((error, false)),
+        None => perform_stt(&input, primary_engine, fallback_engines, vad_engine, output_path, stt_options, None)
+            .await
+            .map_err(|e| (e.to_string(), matches!(e, SttError::NoSpeech))),
+    };
+
+    let result = match output {
+        Ok(output) => TranscriptionResult {
+            path: output.path.unwrap_or_default(),
+            input,
+            transcription: Some(output.text),
+            words: output.words,
+            timestamp,
+            error: None,
+            is_final: true,
+            engine: output.engine,
+            used_fallback: output.used_fallback,
+            no_speech: false,
+            vad_timeline: output.vad_timeline,
+            language: output.language,
+        },
+        Err((error, no_speech)) => TranscriptionResult {
+            path: String::new(),
+            input,
+            transcription: None,
+            words: Vec::new(),
+            timestamp,
+            error: Some(error),
+            is_final: true,
+            engine: String::new(),
+            used_fallback: false,
+            no_speech,
+            vad_timeline: None,
+            language: None,
+        },
+    };
+    log_vad_timeline(&result);
+    result
+}
+
 fn start_keyboard_listener_task(state_tx: Sender<RecordingState>, mut state_rx: Receiver<RecordingState>) -> JoinHandle<()> {
     use device_query::{DeviceQuery, DeviceState, Keycode};
     
@@ -208,6 +1233,40 @@ fn start_keyboard_listener_task(state_tx: Sender<RecordingState>, mut state_rx:
     })
 }
 
+/// The first Ctrl-C (or SIGTERM) finishes the recording like Enter does, so what was recorded is
+/// still transcribed and written out. A second one exits right away.
+fn start_signal_handler_task(state_tx: Sender<RecordingState>) {
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Finishing the recording, press Ctrl-C again to exit without waiting for the transcription");
+        let _ = state_tx.send(RecordingState::RecordingFinished);
+        shutdown_signal().await;
+        warn!("Exiting before the transcription finished");
+        std::process::exit(130);
+    });
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
 fn start_max_duration_task(state_tx: Sender<RecordingState>, duration: u64) {
     tokio::spawn(async move {
         tokio::time::sleep(Duration::from_secs(duration)).await;
@@ -218,10 +1277,11 @@ fn start_max_duration_task(state_tx: Sender<RecordingState>, duration: u64) {
 
 fn spawn_recording_threads(
     devices: Vec<AudioDevice>,
-    whisper_sender: tokio::sync::mpsc::UnboundedSender<AudioInput>,
+    whisper_sender: AudioInputSender,
     state_tx: watch::Sender<RecordingState>,
     state_rx: watch::Receiver<RecordingState>,
     chunk_duration: Duration,
+    chunk_overlap: Duration,
 ) -> Vec<tokio::task::JoinHandle<Result<()>>> {
     devices
         .into_iter()
@@ -240,6 +1300,7 @@ fn spawn_recording_threads(
                 record_and_transcribe(
                     device_clone_2,
                     chunk_duration,
+                    chunk_overlap,
                     whisper_sender,
                     state_rx_clone
                 ).await?;
@@ -260,42 +1321,116 @@ async fn wait_for_initialization(mut rx: watch::Receiver<RecordingState>) -> Res
     Ok(())
 }
 
+/// Drops the words each chunk repeats from the previous chunk of the same device.
+fn remove_overlapping_words(results: &mut [TranscriptionResult]) {
+    let mut last_texts: HashMap<String, String> = HashMap::new();
+    for result in results.iter_mut() {
+        let Some(text) = result.transcription.take() else {
+            continue;
+        };
+        let (trimmed, dropped) = match last_texts.get(&result.input.device) {
+            Some(previous) => trim_overlap(previous, &text),
+            None => (text.clone(), 0),
+        };
+        if dropped > 0 {
+            debug!("Removed {} overlapping words for device: {}", dropped, result.input.device);
+            result.words.drain(..dropped.min(result.words.len()));
+        }
+        last_texts.insert(result.input.device.clone(), text);
+        result.transcription = Some(trimmed);
+    }
+}
+
+/// Shortest time between two `--clipboard-live` updates
+const CLIPBOARD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// The clipboard `--clipboard-live` keeps up to date. Clipboard errors are logged and don't stop
+/// the recording; once it can't be opened, updates are skipped. Results that arrive within
+/// `CLIPBOARD_DEBOUNCE` of an update wait for the next one, or for the final copy in `main`.
+struct LiveClipboard {
+    clipboard: Option<ClipboardContext>,
+    last_update: Option<std::time::Instant>,
+}
+
+impl LiveClipboard {
+    fn new() -> Self {
+        let clipboard = ClipboardContext::new()
+            .map_err(|e| error!("Failed to open the clipboard, --clipboard-live is disabled: {}", e))
+            .ok();
+        Self { clipboard, last_update: None }
+    }
+
+    fn update(&mut self, results: &[TranscriptionResult]) {
+        if self.last_update.is_some_and(|last| last.elapsed() < CLIPBOARD_DEBOUNCE) {
+            return;
+        }
+        let Some(clipboard) = &mut self.clipboard else {
+            return;
+        };
+        let text = match format_output(results, false) {
+            Ok(text) => text,
+            Err(e) => {
+                error!("Failed to format the transcription for the clipboard: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = clipboard.set_contents(text) {
+            warn!("Failed to update the clipboard: {}", e);
+        }
+        self.last_update = Some(std::time::Instant::now());
+    }
+}
+
 async fn run_transcription_loop(
     mut whisper_receiver: UnboundedReceiver<TranscriptionResult>,
     mut state_rx: watch::Receiver<RecordingState>,
     state_tx: watch::Sender<RecordingState>,
-) -> Result<String> {
-    let mut transcription_buffer = String::new();
+    mut live_clipboard: Option<&mut LiveClipboard>,
+    mut journal: Option<&mut Journal>,
+    drain_timeout: DrainTimeout,
+) -> Result<(Vec<TranscriptionResult>, RunStatus)> {
+    let mut results = Vec::new();
     let mut consecutive_timeouts = 0;
     let max_consecutive_timeouts = 3;
 
     loop {
         tokio::select! {
             Some(result) = whisper_receiver.recv() => {
+                if !result.is_final {
+                    if let Some(text) = &result.transcription {
+                        eprintln!("[{}] {}", result.input.device, text);
+                    }
+                    continue;
+                }
                 info!("Transcription: {:?}", result.transcription);
+                debug!(
+                    "device: {}, chunk peak: {:.4}, rms: {:.4}, error: {:?}",
+                    result.input.device, result.input.peak, result.input.rms, result.error
+                );
+                log_vad_timeline(&result);
+                if let Some(journal) = journal.as_deref_mut() {
+                    journal.append(&result);
+                }
                 consecutive_timeouts = 0;
-                match result.transcription {
-                    Some(text) => {
-                        if !transcription_buffer.is_empty() {
-                            transcription_buffer.push(' ');
-                        }
-                        transcription_buffer.push_str(&text);
-                        if RecordingState::RecordingFinished == *state_rx.borrow() {
-                            debug!("Recording has finished. Exit here.");
-                            break;
-                        }
-                    },
-                    None if RecordingState::RecordingFinished == *state_rx.borrow() => {
+                let has_transcription = result.transcription.is_some();
+                results.push(result);
+                if let (Some(clipboard), true) = (live_clipboard.as_deref_mut(), has_transcription) {
+                    clipboard.update(&results);
+                }
+                if RecordingState::RecordingFinished == *state_rx.borrow() {
+                    if has_transcription {
+                        debug!("Recording has finished. Exit here.");
+                    } else {
                         debug!("Recording has finished and no transcriptions are available. Exit here.");
-                        break;
-                    },
-                    None => {}
+                    }
+                    break;
                 }
             }
             Ok(()) = state_rx.changed() => {
                 let state = *state_rx.borrow();
                 debug!("Current state: {:?}", state);
-                if  state == RecordingState::Stopping {
+                // What's left of a finished recording is drained below
+                if matches!(state, RecordingState::RecordingFinished | RecordingState::Stopping) {
                     break;
                 }
             }
@@ -312,28 +1447,94 @@ async fn run_transcription_loop(
             }
         }
     }
+    let drained_from = results.len();
+    if *state_rx.borrow() != RecordingState::Stopping {
+        // The recorders stop and send their last chunk, then everything queued or being
+        // transcribed is waited for before the workers are stopped
+        state_tx.send(RecordingState::RecordingFinished)?;
+        drain_remaining_transcriptions(&mut whisper_receiver, &mut results, &drain_timeout).await;
+    }
     state_tx.send(RecordingState::Stopping)?;
-    drain_remaining_transcriptions(&mut whisper_receiver).await;
+    // Sent before the workers stopped
+    while let Ok(result) = whisper_receiver.try_recv() {
+        if result.is_final {
+            results.push(result);
+        }
+    }
+    if let Some(journal) = journal {
+        for result in &results[drained_from..] {
+            journal.append(result);
+        }
+    }
 
-    Ok(transcription_buffer)
+    let status = RunStatus::from_results(&results);
+    Ok((results, status))
+}
+
+/// Shortest and longest `--drain-timeout` estimate
+const MIN_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_DRAIN_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long `drain_remaining_transcriptions` waits, `--drain-timeout` or an estimate.
+struct DrainTimeout {
+    fixed: Option<Duration>,
+    chunk_duration: Duration,
+    status_rx: watch::Receiver<SttStatus>,
+}
+
+impl DrainTimeout {
+    fn get(&self) -> Duration {
+        if let Some(fixed) = self.fixed {
+            return fixed;
+        }
+        // The queued chunks and the recorders' last ones, at the pace of the last transcription
+        // (real time before the first), twice over for slower chunks
+        let status = self.status_rx.borrow();
+        let per_chunk = status.last_latency.unwrap_or(self.chunk_duration);
+        let chunks = status.inputs_queued as u32 + 1;
+        (per_chunk * chunks * 2).clamp(MIN_DRAIN_TIMEOUT, MAX_DRAIN_TIMEOUT)
+    }
+
+    /// Chunks not transcribed yet
+    fn queued(&self) -> usize {
+        self.status_rx.borrow().inputs_queued
+    }
 }
 
 async fn drain_remaining_transcriptions(
-    whisper_receiver: &mut UnboundedReceiver<TranscriptionResult>
+    whisper_receiver: &mut UnboundedReceiver<TranscriptionResult>,
+    results: &mut Vec<TranscriptionResult>,
+    drain_timeout: &DrainTimeout,
 ) {
-    debug!("Draining remaining transcriptions...");
-    let drain_timeout = Duration::from_secs(10);
+    let timeout_after = drain_timeout.get();
+    debug!("Draining remaining transcriptions for up to {:?}...", timeout_after);
     let drain_start = std::time::Instant::now();
+    let mut drained = 0;
 
-    while let Ok(Some(result)) = timeout(drain_timeout.saturating_sub(drain_start.elapsed()), whisper_receiver.recv()).await {
-        debug!("Drained transcription for device: {}", result.input.device);
-        if drain_start.elapsed() >= drain_timeout {
-            warn!("Draining timed out");
-            break;
+    loop {
+        match timeout(timeout_after.saturating_sub(drain_start.elapsed()), whisper_receiver.recv()).await {
+            Ok(Some(result)) => {
+                debug!("Drained transcription for device: {}", result.input.device);
+                if result.is_final {
+                    results.push(result);
+                    drained += 1;
+                }
+            }
+            Ok(None) => {
+                info!("Drained {} remaining transcription(s)", drained);
+                break;
+            }
+            Err(_) => {
+                warn!(
+                    "Draining timed out after {:?}: {} transcription(s) drained, {} chunk(s) abandoned. Raise --drain-timeout to keep them",
+                    timeout_after,
+                    drained,
+                    drain_timeout.queued()
+                );
+                break;
+            }
         }
     }
-
-    debug!("Finished draining transcriptions");
 }
 
 async fn shutdown_and_cleanup(
@@ -341,10 +1542,19 @@ async fn shutdown_and_cleanup(
     kb_task_join_handle: JoinHandle<()>
 ) -> Result<()> {
 
+    let mut failed = 0;
     for (i, thread) in recording_threads.into_iter().enumerate() {
-        thread.await??;
-        info!("Recording {} complete", i);
+        match thread.await? {
+            Ok(()) => info!("Recording {} complete", i),
+            Err(e) => {
+                error!("Recording {} failed: {:?}", i, e);
+                failed += 1;
+            }
+        }
     }
     kb_task_join_handle.await?;
+    if failed > 0 {
+        return Err(anyhow!("{} recording(s) failed", failed));
+    }
     Ok(())
 }
@@ -1,10 +1,12 @@
 use crate::stt::RecordingState;
-use crate::AudioInput;
+use crate::{AudioInput, AudioInputSender};
 use anyhow::{anyhow, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::Sample;
 use log::{debug, error, info, warn};
 use serde::Serialize;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use std::{fmt, thread};
@@ -19,6 +21,10 @@ pub enum AudioTranscriptionEngine {
     #[default]
     WhisperTiny,
     WhisperDistilLargeV3,
+    /// whisper.cpp with a local GGML model file (requires the `whisper-cpp` feature)
+    WhisperCpp(PathBuf),
+    /// Vosk with an unpacked model directory (requires the `vosk` feature)
+    Vosk(PathBuf),
 }
 
 impl fmt::Display for AudioTranscriptionEngine {
@@ -28,6 +34,8 @@ impl fmt::Display for AudioTranscriptionEngine {
             AudioTranscriptionEngine::Deepgram => write!(f, "Deepgram"),
             AudioTranscriptionEngine::WhisperTiny => write!(f, "WhisperTiny"),
             AudioTranscriptionEngine::WhisperDistilLargeV3 => write!(f, "WhisperLarge"),
+            AudioTranscriptionEngine::WhisperCpp(_) => write!(f, "WhisperCpp"),
+            AudioTranscriptionEngine::Vosk(_) => write!(f, "Vosk"),
         }
     }
 }
@@ -95,10 +103,64 @@ impl fmt::Display for AudioDevice {
     }
 }
 
+/// `AudioDevice` plus what's needed to pick one in a UI, for machine-readable device listings.
+#[derive(Clone, Serialize)]
+pub struct AudioDeviceInfo {
+    pub name: String,
+    pub device_type: DeviceType,
+    pub is_default: bool,
+    /// `None` when the device config can't be queried
+    pub default_sample_rate: Option<u32>,
+    pub default_channels: Option<u16>,
+}
+
+/// Looks up the default stream config of each device and flags the default input/output.
+pub async fn describe_audio_devices(devices: &[AudioDevice]) -> Vec<AudioDeviceInfo> {
+    let default_input = default_input_device().ok();
+    let default_output = default_output_device().await.ok();
+
+    let mut infos = Vec::with_capacity(devices.len());
+    for device in devices {
+        let config = match get_device_and_config(device).await {
+            Ok((_, config)) => Some(config),
+            Err(e) => {
+                debug!("could not read config of device {}: {}", device, e);
+                None
+            }
+        };
+        infos.push(AudioDeviceInfo {
+            name: device.name.clone(),
+            device_type: device.device_type.clone(),
+            is_default: default_input.as_ref() == Some(device) || default_output.as_ref() == Some(device),
+            default_sample_rate: config.as_ref().map(|c| c.sample_rate().0),
+            default_channels: config.as_ref().map(|c| c.channels()),
+        });
+    }
+    infos
+}
+
 pub fn parse_audio_device(name: &str) -> Result<AudioDevice> {
     AudioDevice::from_name(name)
 }
 
+/// Resolves `@N` to the Nth (0-based) entry of `devices`, as returned by `list_audio_devices`;
+/// anything else is parsed as a device name with its `(input)`/`(output)` suffix.
+pub fn resolve_audio_device(spec: &str, devices: &[AudioDevice]) -> Result<AudioDevice> {
+    let Some(index) = spec.trim().strip_prefix('@') else {
+        return parse_audio_device(spec);
+    };
+    let index: usize = index
+        .parse()
+        .map_err(|_| anyhow!("Invalid device index {:?}, expected @N", spec))?;
+    devices.get(index).cloned().ok_or_else(|| {
+        anyhow!(
+            "Device index @{} is out of range, {} devices available (see --list-audio-devices)",
+            index,
+            devices.len()
+        )
+    })
+}
+
 async fn get_device_and_config(
     audio_device: &AudioDevice,
 ) -> Result<(cpal::Device, cpal::SupportedStreamConfig)> {
@@ -153,13 +215,114 @@ async fn get_device_and_config(
     Ok((cpal_audio_device, config))
 }
 
+/// Reconnection schedule used when a device disappears mid-recording
+const RECONNECT_MAX_ATTEMPTS: u32 = 10;
+const RECONNECT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Records `audio_device` and sends it to `whisper_sender` in `chunk_duration` pieces. Each chunk
+/// after the first starts with the last `overlap` of the previous one so words spoken across a
+/// boundary are heard whole at least once; see `trim_overlap` for removing the repeated words.
 pub async fn record_and_transcribe(
     audio_device: Arc<AudioDevice>,
     chunk_duration: Duration,
-    whisper_sender: UnboundedSender<AudioInput>,
+    overlap: Duration,
+    whisper_sender: AudioInputSender,
     state_rx: watch::Receiver<RecordingState>,
 ) -> Result<()> {
-    let (cpal_audio_device, config) = get_device_and_config(&audio_device).await?;
+    if overlap >= chunk_duration {
+        return Err(anyhow!(
+            "chunk overlap ({:?}) must be shorter than the chunk duration ({:?})",
+            overlap,
+            chunk_duration
+        ));
+    }
+
+    let (mut cpal_audio_device, mut config) = get_device_and_config(&audio_device).await?;
+
+    info!(
+        "Recording {} continuously",
+        audio_device.to_string(),
+    );
+
+    loop {
+        let disconnected = record_stream(
+            &audio_device,
+            cpal_audio_device,
+            config,
+            chunk_duration,
+            overlap,
+            &whisper_sender,
+            &state_rx,
+        )
+        .await?;
+
+        if !disconnected || !is_recording_active(*state_rx.borrow()) {
+            break;
+        }
+
+        match reconnect(&audio_device, &state_rx).await {
+            Some((device, new_config)) => {
+                cpal_audio_device = device;
+                config = new_config;
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Recording or paused: the stream stays open while paused so resuming doesn't have to
+/// reopen the device.
+fn is_recording_active(state: RecordingState) -> bool {
+    matches!(state, RecordingState::Recording | RecordingState::RecordingPaused)
+}
+
+/// Waits for a disconnected device to come back, retrying with exponential backoff.
+/// Returns `None` when recording stops in the meantime or the retries run out.
+async fn reconnect(
+    audio_device: &AudioDevice,
+    state_rx: &watch::Receiver<RecordingState>,
+) -> Option<(cpal::Device, cpal::SupportedStreamConfig)> {
+    let mut backoff = RECONNECT_INITIAL_BACKOFF;
+    for attempt in 1..=RECONNECT_MAX_ATTEMPTS {
+        info!(
+            "device {} disconnected, reconnecting in {:?} (attempt {}/{})",
+            audio_device, backoff, attempt, RECONNECT_MAX_ATTEMPTS
+        );
+        tokio::time::sleep(backoff).await;
+        if !is_recording_active(*state_rx.borrow()) {
+            return None;
+        }
+        match get_device_and_config(audio_device).await {
+            Ok(found) => {
+                info!("reconnected to device {}", audio_device);
+                return Some(found);
+            }
+            Err(e) => warn!("failed to reconnect to device {}: {}", audio_device, e),
+        }
+        backoff = (backoff * 2).min(RECONNECT_MAX_BACKOFF);
+    }
+    error!(
+        "giving up on device {} after {} reconnection attempts",
+        audio_device, RECONNECT_MAX_ATTEMPTS
+    );
+    None
+}
+
+/// Records from one stream until recording stops or the device goes away, forwarding chunks to
+/// `whisper_sender`. Returns whether the stream ended because the device disconnected, or an
+/// error if the stream could not be started (e.g. an unsupported sample format).
+async fn record_stream(
+    audio_device: &AudioDevice,
+    cpal_audio_device: cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    chunk_duration: Duration,
+    overlap: Duration,
+    whisper_sender: &AudioInputSender,
+    state_rx: &watch::Receiver<RecordingState>,
+) -> Result<bool> {
     let sample_rate = config.sample_rate().0;
     let channels = config.channels();
     debug!(
@@ -171,22 +334,36 @@ pub async fn record_and_transcribe(
     let is_running = Arc::new(AtomicBool::new(true));
     let is_running_clone = is_running.clone();
     let is_running_weak_2 = Arc::downgrade(&is_running);
+    let disconnected = Arc::new(AtomicBool::new(false));
+    let disconnected_clone = disconnected.clone();
+    let is_paused = Arc::new(AtomicBool::new(*state_rx.borrow() == RecordingState::RecordingPaused));
+    let is_paused_clone = is_paused.clone();
 
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
-    let whisper_sender = Arc::new(whisper_sender);
+    // Reports whether the stream thread managed to start playing
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel::<Result<()>>();
 
+    #[allow(clippy::too_many_arguments)]
     fn build_stream<T>(
         device: &cpal::Device,
         config: cpal::StreamConfig,
         audio_data: Arc<Mutex<Vec<f32>>>,
         tx: UnboundedSender<Vec<f32>>,
         is_running: Arc<AtomicBool>,
-        chunk_duration: Duration
+        is_paused: Arc<AtomicBool>,
+        disconnected: Arc<AtomicBool>,
+        chunk_duration: Duration,
+        overlap: Duration,
     ) -> Result<cpal::Stream>
     where
-        T: cpal::Sample + cpal::SizedSample + bytemuck::Pod,
+        T: cpal::SizedSample,
+        f32: cpal::FromSample<T>,
     {
         let is_running_weak = Arc::downgrade(&is_running);
+        let channels = config.channels as usize;
+        // whole frames only, so the next chunk still starts on the first channel
+        let overlap_samples =
+            (overlap.as_millis() as usize * config.sample_rate.0 as usize / 1000) * channels;
         device.build_input_stream(
             &config,
             move |data: &[T], _: &cpal::InputCallbackInfo| {
@@ -194,22 +371,29 @@ pub async fn record_and_transcribe(
                     return;
                 }
 
-                // Convert the input data to f32
-                let samples: &[f32] = bytemuck::cast_slice(data);
-
                 let mut buffer = audio_data.blocking_lock();
-                buffer.extend_from_slice(samples);
+                // Paused audio is dropped, along with the partial chunk from before the pause, so
+                // resuming starts from fresh audio
+                if is_paused.load(Ordering::Relaxed) {
+                    buffer.clear();
+                    return;
+                }
+                // Convert the input data to f32
+                buffer.extend(data.iter().map(|&sample| sample.to_sample::<f32>()));
 
                 // Check if the buffer has reached the chunk size
                 if buffer.len() >= (chunk_duration.as_secs() as usize) * config.sample_rate.0 as usize * config.channels as usize {
                     let chunk = buffer.split_off(0); // Remove the chunk from the buffer
+                    let keep = overlap_samples.min(chunk.len());
+                    buffer.extend_from_slice(&chunk[chunk.len() - keep..]);
                     tx.send(chunk).ok();
                 }
             },
             move |err| {
                 error!("An error occurred on the audio stream: {}", err);
                 if err.to_string().contains("device is no longer valid") {
-                    warn!("Audio device disconnected. Stopping recording.");
+                    warn!("Audio device disconnected. Stopping stream.");
+                    disconnected.store(true, Ordering::Relaxed);
                     if let Some(arc) = is_running_weak.upgrade() {
                         arc.store(false, Ordering::Relaxed);
                     }
@@ -220,23 +404,27 @@ pub async fn record_and_transcribe(
         .map_err(|e| anyhow::anyhow!(e))
     }
 
+    // The audio recorded since the last full chunk, sent when the recording finishes
+    let pending_audio = Arc::clone(&audio_data);
+
     let audio_handle = thread::spawn(move || {
         let stream = match config.sample_format() {
-            cpal::SampleFormat::I8 => build_stream::<i8>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), chunk_duration),
-            cpal::SampleFormat::I16 => build_stream::<i16>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), chunk_duration),
-            cpal::SampleFormat::I32 => build_stream::<i32>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), chunk_duration),
-            cpal::SampleFormat::F32 => build_stream::<f32>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), chunk_duration),
-            _ => {
-                error!("Unsupported sample format: {:?}", config.sample_format());
-                return;
-            }
+            cpal::SampleFormat::I8 => build_stream::<i8>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), is_paused_clone, disconnected_clone, chunk_duration, overlap),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), is_paused_clone, disconnected_clone, chunk_duration, overlap),
+            cpal::SampleFormat::I32 => build_stream::<i32>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), is_paused_clone, disconnected_clone, chunk_duration, overlap),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), is_paused_clone, disconnected_clone, chunk_duration, overlap),
+            cpal::SampleFormat::F32 => build_stream::<f32>(&cpal_audio_device, config.into(), Arc::clone(&audio_data), tx.clone(), Arc::clone(&is_running), is_paused_clone, disconnected_clone, chunk_duration, overlap),
+            sample_format => Err(anyhow!("Unsupported sample format: {:?}", sample_format)),
         };
 
         match stream {
             Ok(s) => {
                 if let Err(e) = s.play() {
                     error!("Failed to play stream: {}", e);
+                    started_tx.send(Err(anyhow!("Failed to play stream: {}", e))).ok();
+                    return;
                 }
+                started_tx.send(Ok(())).ok();
                 // Keep the stream alive until the recording is done
                 while is_running_weak_2
                     .upgrade()
@@ -247,28 +435,62 @@ pub async fn record_and_transcribe(
                 s.pause().ok();
                 drop(s);
             }
-            Err(e) => error!("Failed to build input stream: {}", e),
+            Err(e) => {
+                error!("Failed to build input stream: {}", e);
+                started_tx.send(Err(e)).ok();
+            }
         }
     });
 
-    info!(
-        "Recording {} continuously",
-        audio_device.to_string(),
-    );
+    let started = started_rx
+        .await
+        .unwrap_or_else(|_| Err(anyhow!("Audio thread exited before the stream started")));
+    if let Err(e) = started {
+        if let Err(join_err) = audio_handle.join() {
+            error!("Error joining audio thread: {:?}", join_err);
+        }
+        return Err(e.context(format!("Failed to record {}", audio_device)));
+    }
 
-    while *state_rx.borrow() == RecordingState::Recording {
-        if let Some(chunk) = rx.recv().await {
-            debug!("Sending audio chunk of length {} to audio model", chunk.len());
-            if let Err(e) = whisper_sender.send(AudioInput {
-                data: chunk,
-                device: audio_device.to_string(),
-                sample_rate,
-                channels,
-            }) {
-                error!("Failed to send audio to audio model: {}", e);
+    let mut state_rx = state_rx.clone();
+    // Cleared if the state sender goes away, the last state then stays in effect
+    let mut watching_state = true;
+    loop {
+        let state = *state_rx.borrow_and_update();
+        if !is_recording_active(state) {
+            break;
+        }
+        is_paused.store(state == RecordingState::RecordingPaused, Ordering::Relaxed);
+
+        let chunk = tokio::select! {
+            chunk = rx.recv() => chunk,
+            changed = state_rx.changed(), if watching_state => {
+                watching_state = changed.is_ok();
+                continue;
             }
-            debug!("Sent audio chunk to audio model");
+        };
+        // `None` once the stream thread has exited, e.g. after a disconnect
+        let Some(chunk) = chunk else {
+            break;
+        };
+        let (peak, rms) = audio_levels(&chunk);
+        debug!(
+            "Sending audio chunk of length {} to audio model, peak: {:.4}, rms: {:.4}",
+            chunk.len(),
+            peak,
+            rms
+        );
+        if let Err(e) = whisper_sender.send(AudioInput {
+            data: chunk,
+            device: audio_device.to_string(),
+            sample_rate,
+            channels,
+            peak,
+            rms,
+        }).await {
+            error!("Failed to send audio to audio model: {}", e);
         }
+        debug!("Sent audio chunk to audio model");
     }
 
     //Signal the recording thread to stop
@@ -279,9 +501,55 @@ pub async fn record_and_transcribe(
         error!("Error joining audio thread: {:?}", e);
     }
 
-    Ok(())
+    // Finishing keeps the tail of the recording, stopping drops it. Starts with the overlap of
+    // the last chunk, only sent if there's more.
+    if *state_rx.borrow() == RecordingState::RecordingFinished {
+        let rest = std::mem::take(&mut *pending_audio.lock().await);
+        let overlap_samples =
+            (overlap.as_millis() as usize * sample_rate as usize / 1000) * channels as usize;
+        if rest.len() > overlap_samples {
+            let (peak, rms) = audio_levels(&rest);
+            debug!("Sending the last {} samples of {}", rest.len(), audio_device);
+            if let Err(e) = whisper_sender.send(AudioInput {
+                data: rest,
+                device: audio_device.to_string(),
+                sample_rate,
+                channels,
+                peak,
+                rms,
+            }).await {
+                error!("Failed to send audio to audio model: {}", e);
+            }
+        }
+    }
+
+    Ok(disconnected.load(Ordering::Relaxed))
+}
+
+/// Peak and RMS level of a chunk, telling silence apart from speech the VAD dropped.
+pub fn audio_levels(samples: &[f32]) -> (f32, f32) {
+    if samples.is_empty() {
+        return (0.0, 0.0);
+    }
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    (peak, rms)
+}
+
+/// Whether a ScreenCaptureKit device is listed as an output device. ScreenCaptureKit also
+/// reports the built-in speakers and AirPods, which it can't capture system audio from.
+pub fn should_include_output_device(name: &str) -> bool {
+    let name = name.to_lowercase();
+    !name.contains("speakers") && !name.contains("airpods")
 }
+
 pub async fn list_audio_devices() -> Result<Vec<AudioDevice>> {
+    list_audio_devices_with_filter(false).await
+}
+
+/// Like `list_audio_devices`; `allow_all_output_devices` disables the macOS
+/// ScreenCaptureKit filter (see `should_include_output_device`).
+pub async fn list_audio_devices_with_filter(allow_all_output_devices: bool) -> Result<Vec<AudioDevice>> {
     let host = cpal::default_host();
     let mut devices = Vec::new();
 
@@ -291,11 +559,6 @@ pub async fn list_audio_devices() -> Result<Vec<AudioDevice>> {
         }
     }
 
-    // Filter function to exclude macOS speakers and AirPods for output devices
-    fn should_include_output_device(name: &str) -> bool {
-        !name.to_lowercase().contains("speakers") && !name.to_lowercase().contains("airpods")
-    }
-
     // macos hack using screen capture kit for output devices - does not work well
     #[cfg(target_os = "macos")]
     {
@@ -304,26 +567,42 @@ pub async fn list_audio_devices() -> Result<Vec<AudioDevice>> {
         if let Ok(host) = cpal::host_from_id(cpal::HostId::ScreenCaptureKit) {
             for device in host.input_devices()? {
                 if let Ok(name) = device.name() {
-                    if should_include_output_device(&name) {
+                    if allow_all_output_devices || should_include_output_device(&name) {
                         devices.push(AudioDevice::new(name, DeviceType::Output));
                     }
                 }
             }
         }
     }
+    #[cfg(not(target_os = "macos"))]
+    let _ = allow_all_output_devices;
 
     // add default output device - on macos think of custom virtual devices
     for device in host.output_devices()? {
         if let Ok(name) = device.name() {
-            if should_include_output_device(&name) {
-                devices.push(AudioDevice::new(name, DeviceType::Output));
-            }
+            devices.push(AudioDevice::new(name, DeviceType::Output));
         }
     }
 
     Ok(devices)
 }
 
+/// Whether `device` matches one of the `exclude` names, compared against `AudioDevice::to_string()`
+/// (e.g. `"Loopback Audio (input)"`).
+pub fn is_device_excluded(device: &AudioDevice, exclude: &[String]) -> bool {
+    let name = device.to_string();
+    exclude.iter().any(|excluded| excluded.trim() == name)
+}
+
+/// `list_audio_devices` without the devices named in `exclude`.
+pub async fn list_audio_devices_filtered(exclude: &[String]) -> Result<Vec<AudioDevice>> {
+    Ok(list_audio_devices()
+        .await?
+        .into_iter()
+        .filter(|device| !is_device_excluded(device, exclude))
+        .collect())
+}
+
 pub fn default_input_device() -> Result<AudioDevice> {
     let host = cpal::default_host();
     let device = host
@@ -1,20 +1,119 @@
-use screenpipe_core::find_ffmpeg_path;
-use std::io::Write;
+use anyhow::{anyhow, Context};
+use screenpipe_core::{find_ffmpeg_path, FFMPEG_PATH_ENV};
+use std::io::{ErrorKind, Write};
 use std::{
     path::PathBuf,
     process::{Command, Stdio},
 };
+use thiserror::Error;
 use tracing::{debug, error};
 
+/// Container/codec for saved recordings.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AudioFormat {
+    /// AAC in MP4
+    #[default]
+    Mp4,
+    /// Lossless FLAC
+    Flac,
+    /// 16-bit PCM WAV
+    Wav,
+    /// Opus in Ogg
+    Opus,
+}
+
+impl AudioFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp4 => "mp4",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Opus => "opus",
+        }
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        matches!(self, AudioFormat::Flac | AudioFormat::Wav)
+    }
+
+    /// Codec and container arguments. `bitrate_kbps` overrides the default 64k for the lossy
+    /// formats and is ignored for the lossless ones.
+    fn ffmpeg_args(&self, bitrate_kbps: Option<u32>) -> Vec<String> {
+        let bitrate = format!("{}k", bitrate_kbps.unwrap_or(64));
+        let args: Vec<&str> = match self {
+            AudioFormat::Mp4 => vec![
+                "-c:a",
+                "aac",
+                "-b:a",
+                bitrate.as_str(),
+                "-profile:a",
+                "aac_low", // Use AAC-LC profile for better compatibility
+                "-movflags",
+                "+faststart", // Optimize for web streaming
+                "-f",
+                "mp4",
+            ],
+            AudioFormat::Flac => vec!["-c:a", "flac", "-f", "flac"],
+            AudioFormat::Wav => vec!["-c:a", "pcm_s16le", "-f", "wav"],
+            AudioFormat::Opus => vec!["-c:a", "libopus", "-b:a", bitrate.as_str(), "-f", "ogg"],
+        };
+        args.into_iter().map(String::from).collect()
+    }
+}
+
+/// What the recordings need from ffmpeg, for the errors when it's missing
+const FFMPEG_REQUIREMENTS: &str =
+    "ffmpeg 4.0 or newer with the aac, flac, pcm_s16le and libopus encoders";
+
+/// `encode_single_audio` found no ffmpeg to run, as opposed to ffmpeg failing.
+#[derive(Debug, Error)]
+#[error("{0}")]
+pub struct FfmpegNotFound(String);
+
+/// How `encode_single_audio` runs ffmpeg.
+#[derive(Clone, Debug, Default)]
+pub struct FfmpegOptions {
+    /// ffmpeg executable; `find_ffmpeg_path` (`FFMPEG_PATH`, then `PATH` and the app bundle)
+    /// when `None`
+    pub binary: Option<PathBuf>,
+    /// Passed after the codec arguments and before the output path, so they can override them,
+    /// e.g. `-ar 22050`
+    pub extra_args: Vec<String>,
+}
+
+impl FfmpegOptions {
+    fn binary(&self) -> anyhow::Result<PathBuf> {
+        match &self.binary {
+            Some(path) => Ok(path.clone()),
+            None => find_ffmpeg_path().ok_or_else(|| {
+                FfmpegNotFound(format!(
+                    "ffmpeg not found: install {} and put it on PATH, or set {} to its path",
+                    FFMPEG_REQUIREMENTS, FFMPEG_PATH_ENV
+                ))
+                .into()
+            }),
+        }
+    }
+}
+
+/// Encodes interleaved f32 samples (`data` as bytes) to `output_path` with ffmpeg, which must be
+/// 4.0 or newer and have the encoder of `format`: aac, flac, pcm_s16le or libopus.
 pub fn encode_single_audio(
     data: &[u8],
     sample_rate: u32,
     channels: u16,
     output_path: &PathBuf,
+    format: AudioFormat,
+    bitrate_kbps: Option<u32>,
+    ffmpeg: &FfmpegOptions,
 ) -> anyhow::Result<()> {
     debug!("Starting FFmpeg process");
+    if bitrate_kbps.is_some() && format.is_lossless() {
+        debug!("Ignoring bitrate for lossless format {:?}", format);
+    }
 
-    let mut command = Command::new(find_ffmpeg_path().unwrap());
+    let binary = ffmpeg.binary()?;
+    let mut command = Command::new(&binary);
     command
         .args([
             "-f",
@@ -25,34 +124,33 @@ pub fn encode_single_audio(
             &channels.to_string(),
             "-i",
             "pipe:0",
-            "-c:a",
-            "aac",
-            "-b:a",
-            "64k", // Reduced bitrate for higher compression
-            "-profile:a",
-            "aac_low", // Use AAC-LC profile for better compatibility
-            "-movflags",
-            "+faststart", // Optimize for web streaming
-            "-f",
-            "mp4",
-            output_path.to_str().unwrap(),
         ])
+        .args(format.ffmpeg_args(bitrate_kbps))
+        .args(&ffmpeg.extra_args)
+        .arg(output_path)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
     debug!("FFmpeg command: {:?}", command);
 
-    let mut ffmpeg = command.spawn().expect("Failed to spawn FFmpeg process");
+    let mut ffmpeg = command.spawn().map_err(|e| match e.kind() {
+        ErrorKind::NotFound => FfmpegNotFound(format!(
+            "ffmpeg not found at {:?}: install {} or fix the path",
+            binary, FFMPEG_REQUIREMENTS
+        ))
+        .into(),
+        _ => anyhow!(e).context(format!("failed to run ffmpeg at {:?}", binary)),
+    })?;
     debug!("FFmpeg process spawned");
-    let mut stdin = ffmpeg.stdin.take().expect("Failed to open stdin");
+    let mut stdin = ffmpeg.stdin.take().context("failed to open ffmpeg's stdin")?;
 
     stdin.write_all(data)?;
 
     debug!("Dropping stdin");
     drop(stdin);
     debug!("Waiting for FFmpeg process to exit");
-    let output = ffmpeg.wait_with_output().unwrap();
+    let output = ffmpeg.wait_with_output()?;
     let status = output.status;
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
@@ -1,16 +1,30 @@
+mod audio_queue;
 mod core;
+mod mixer;
 mod multilingual;
 pub mod pcm_decode;
+mod preprocess;
+pub mod srt;
 pub mod stt;
 pub mod vad_engine;
 pub mod encode;
+#[cfg(feature = "pipes")]
+mod pipe_transcriber;
+pub use audio_queue::{audio_queue, AudioInputReceiver, AudioInputSender, DropPolicy};
 pub use core::{
-    default_input_device, default_output_device, list_audio_devices, parse_audio_device,
-    record_and_transcribe, AudioDevice, AudioTranscriptionEngine, DeviceControl, DeviceType
+    audio_levels, default_input_device, default_output_device, describe_audio_devices,
+    is_device_excluded, list_audio_devices, list_audio_devices_filtered,
+    list_audio_devices_with_filter, parse_audio_device, record_and_transcribe,
+    resolve_audio_device, should_include_output_device, AudioDevice, AudioDeviceInfo,
+    AudioTranscriptionEngine, DeviceControl, DeviceType,
 };
-pub use encode::encode_single_audio;
+pub use encode::{encode_single_audio, AudioFormat, FfmpegNotFound, FfmpegOptions};
+pub use mixer::{spawn_device_mixer, spawn_stereo_capture, MIXED_DEVICE, MIX_SAMPLE_RATE, STEREO_DEVICE};
 pub use pcm_decode::pcm_decode;
-pub use stt::{perform_stt, AudioInput, TranscriptionResult};
-pub use stt::engines::whisper::{create_whisper_channel, WhisperModel};
-pub use stt::engines::create_comm_channel;
-pub use vad_engine::VadEngineEnum;
\ No newline at end of file
+pub use preprocess::{Normalization, SpeechFilter};
+pub use stt::{coalesce_transcriptions, create_wav, encode_pcm, is_no_speech, perform_stt, resample, trim_overlap, AudioInput, ChannelMode, EngineOutput, ResampleQuality, SttError, SttOptions, Task, TranscriptSegment, TranscriptionOutput, TranscriptionResult, WavSampleFormat, Word};
+pub use stt::engines::whisper::{create_whisper_channel, download_model_files, download_repo_files, DevicePreference, HallucinationFilter, WhisperDecodeParams, WhisperModel};
+pub use stt::engines::{create_comm_channel, create_comm_channel_with_status, StopHandle, SttStatus};
+pub use vad_engine::VadEngineEnum;
+#[cfg(feature = "pipes")]
+pub use pipe_transcriber::PipeSttTranscriber;
\ No newline at end of file
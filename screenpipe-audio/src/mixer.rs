@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, error, warn};
+
+use crate::audio_queue::{audio_queue, AudioInputSender, DropPolicy};
+use crate::stt::{resample, ResampleQuality};
+use crate::{audio_levels, AudioInput};
+
+/// Sample rate of the mixed stream, the rate `perform_stt` resamples to anyway
+pub const MIX_SAMPLE_RATE: u32 = 16000;
+/// `AudioInput::device` of the mixed chunks
+pub const MIXED_DEVICE: &str = "mixed";
+/// `AudioInput::device` of the chunks of `spawn_stereo_capture`
+pub const STEREO_DEVICE: &str = "stereo";
+/// A device this many chunks behind the others is padded with silence rather than waited for
+const MAX_LAG_CHUNKS: usize = 3;
+
+/// Mixes the chunks recorded from `devices` into one mono stream sent to `output`, so e.g. a
+/// mic and the system audio give a single chronological transcript. Returns the sender the
+/// recorders send to instead of `output`.
+///
+/// Devices record continuously, so their audio is lined up by position in each device's
+/// stream rather than by chunk: chunks are downmixed, resampled to `MIX_SAMPLE_RATE` and
+/// appended to the device's buffer (with `resample_quality`), and a mixed chunk of `chunk_duration` is emitted once every
+/// device has that much buffered. Chunks must not overlap, overlapping audio would be mixed in
+/// twice.
+pub fn spawn_device_mixer(
+    devices: Vec<String>,
+    chunk_duration: Duration,
+    resample_quality: ResampleQuality,
+    capacity: usize,
+    drop_policy: DropPolicy,
+    output: AudioInputSender,
+) -> AudioInputSender {
+    spawn_mixer(
+        DeviceMixer::new(devices, chunk_duration, resample_quality, Layout::Mono),
+        capacity,
+        drop_policy,
+        output,
+    )
+}
+
+/// Records two devices into one interleaved stereo stream sent to `output`, `left` (e.g. the
+/// mic) on the left channel and `right` (e.g. the system audio) on the right, so a later pass
+/// can tell who spoke from the channel without a diarization model. Returns the sender the two
+/// recorders send to instead of `output`.
+///
+/// The devices are lined up the same way as in `spawn_device_mixer`, each downmixed to mono at
+/// `MIX_SAMPLE_RATE` first. Transcription downmixes the stereo chunks again, the saved
+/// recordings keep both channels.
+pub fn spawn_stereo_capture(
+    left: String,
+    right: String,
+    chunk_duration: Duration,
+    resample_quality: ResampleQuality,
+    capacity: usize,
+    drop_policy: DropPolicy,
+    output: AudioInputSender,
+) -> AudioInputSender {
+    spawn_mixer(
+        DeviceMixer::new(vec![left, right], chunk_duration, resample_quality, Layout::Stereo),
+        capacity,
+        drop_policy,
+        output,
+    )
+}
+
+fn spawn_mixer(
+    mut mixer: DeviceMixer,
+    capacity: usize,
+    drop_policy: DropPolicy,
+    output: AudioInputSender,
+) -> AudioInputSender {
+    let (input_sender, mut input_receiver) = audio_queue(capacity, drop_policy);
+    tokio::spawn(async move {
+        while let Some(input) = input_receiver.recv().await {
+            if let Err(e) = mixer.push(input) {
+                error!("Failed to mix audio chunk: {:?}", e);
+                continue;
+            }
+            while let Some(chunk) = mixer.next_chunk() {
+                if output.send(mixer.layout.input(chunk)).await.is_err() {
+                    return;
+                }
+            }
+        }
+        // Recording stopped, whatever is left goes out padded with silence
+        if let Some(chunk) = mixer.flush() {
+            output.send(mixer.layout.input(chunk)).await.ok();
+        }
+        debug!("Device mixer finished");
+    });
+    input_sender
+}
+
+/// How `DeviceMixer` combines the devices
+#[derive(Clone, Copy)]
+enum Layout {
+    /// Summed into one mono channel
+    Mono,
+    /// The first device on the left channel, the second on the right
+    Stereo,
+}
+
+impl Layout {
+    fn input(self, data: Vec<f32>) -> AudioInput {
+        let (peak, rms) = audio_levels(&data);
+        let (channels, device) = match self {
+            Layout::Mono => (1, MIXED_DEVICE),
+            Layout::Stereo => (2, STEREO_DEVICE),
+        };
+        AudioInput {
+            data,
+            sample_rate: MIX_SAMPLE_RATE,
+            channels,
+            device: device.to_string(),
+            peak,
+            rms,
+        }
+    }
+}
+
+struct DeviceMixer {
+    /// The devices in channel order
+    devices: Vec<String>,
+    /// Mono `MIX_SAMPLE_RATE` audio of each device not mixed yet
+    buffers: HashMap<String, Vec<f32>>,
+    chunk_len: usize,
+    resample_quality: ResampleQuality,
+    layout: Layout,
+}
+
+impl DeviceMixer {
+    fn new(devices: Vec<String>, chunk_duration: Duration, resample_quality: ResampleQuality, layout: Layout) -> Self {
+        Self {
+            buffers: devices.iter().map(|device| (device.clone(), Vec::new())).collect(),
+            devices,
+            chunk_len: ((chunk_duration.as_secs_f64() * MIX_SAMPLE_RATE as f64) as usize).max(1),
+            resample_quality,
+            layout,
+        }
+    }
+
+    fn push(&mut self, input: AudioInput) -> Result<()> {
+        // Never mixed, its buffer would only fill up and keep `next_chunk` emitting
+        let Some(buffer) = self.buffers.get_mut(&input.device) else {
+            warn!("dropping audio from {}, a device the mixer wasn't set up with", input.device);
+            return Ok(());
+        };
+        let samples = if input.sample_rate == MIX_SAMPLE_RATE && input.channels == 1 {
+            input.data
+        } else {
+            resample(input.data, input.channels, input.sample_rate, MIX_SAMPLE_RATE, self.resample_quality)?
+        };
+        buffer.extend(samples);
+        Ok(())
+    }
+
+    /// The next mixed chunk, once every device has a chunk buffered or one is far enough ahead
+    /// that the lagging ones (stalled, disconnected) are padded with silence.
+    fn next_chunk(&mut self) -> Option<Vec<f32>> {
+        let shortest = self.buffers.values().map(Vec::len).min()?;
+        let longest = self.buffers.values().map(Vec::len).max()?;
+        if shortest >= self.chunk_len {
+            return Some(self.mix(self.chunk_len));
+        }
+        if longest >= self.chunk_len * MAX_LAG_CHUNKS {
+            for (device, buffer) in &self.buffers {
+                if buffer.len() < self.chunk_len {
+                    debug!("device {} is falling behind, mixing in silence for it", device);
+                }
+            }
+            return Some(self.mix(self.chunk_len));
+        }
+        None
+    }
+
+    fn flush(&mut self) -> Option<Vec<f32>> {
+        let longest = self.buffers.values().map(Vec::len).max()?;
+        (longest > 0).then(|| self.mix(longest))
+    }
+
+    /// Combines the first `len` samples of every buffer per `layout`, missing samples counting
+    /// as silence.
+    fn mix(&mut self, len: usize) -> Vec<f32> {
+        match self.layout {
+            Layout::Mono => self.sum(len),
+            Layout::Stereo => self.interleave(len),
+        }
+    }
+
+    /// `len` stereo frames, left and right from the first two devices.
+    fn interleave(&mut self, len: usize) -> Vec<f32> {
+        let left = self.take(0, len);
+        let right = self.take(1, len);
+        left.into_iter()
+            .zip(right)
+            .flat_map(|(left, right)| [left, right])
+            .collect()
+    }
+
+    /// The first `len` samples of the `index`th device, padded with silence.
+    fn take(&mut self, index: usize, len: usize) -> Vec<f32> {
+        let mut samples: Vec<f32> = match self.buffers.get_mut(&self.devices[index]) {
+            Some(buffer) => {
+                let take = len.min(buffer.len());
+                buffer.drain(..take).collect()
+            }
+            None => Vec::new(),
+        };
+        samples.resize(len, 0.0);
+        samples
+    }
+
+    /// Sums the first `len` samples of every buffer into one channel.
+    fn sum(&mut self, len: usize) -> Vec<f32> {
+        let mut mixed = vec![0.0f32; len];
+        for buffer in self.buffers.values_mut() {
+            let take = len.min(buffer.len());
+            for (out, sample) in mixed.iter_mut().zip(buffer.drain(..take)) {
+                *out += sample;
+            }
+        }
+        // Summed rather than averaged so a quiet speaker isn't halved, clamped where both are loud
+        for sample in &mut mixed {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+        mixed
+    }
+}
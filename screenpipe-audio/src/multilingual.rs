@@ -107,12 +107,22 @@ const LANGUAGES: [(&str, &str); 99] = [
     ("su", "sundanese"),
 ];
 
-/// Returns the token id for the selected language.
+/// Whether whisper knows the language code, e.g. "en".
+pub fn is_supported_language(code: &str) -> bool {
+    LANGUAGES.iter().any(|(t, _)| *t == code)
+}
+
+/// Returns the token id for a language code checked with `is_supported_language`.
+pub fn language_token(tokenizer: &Tokenizer, code: &str) -> Result<u32> {
+    token_id(tokenizer, &format!("<|{code}|>"))
+}
+
+/// Returns the token id and the code of the most likely language, e.g. "en".
 pub fn detect_language(
     model: &mut Model,
     tokenizer: &Tokenizer,
     mel: &Tensor,
-) -> Result<u32> {
+) -> Result<(u32, &'static str)> {
     let (_bsize, _, seq_len) = mel.dims3()?;
     let mel = mel.narrow(
         2,
@@ -140,5 +150,5 @@ pub fn detect_language(
     }
     let language = token_id(tokenizer, &format!("<|{}|>", probs[0].0 .0))?;
     info!("detected language: {:?}", probs[0].0);
-    Ok(language)
+    Ok((language, probs[0].0 .0))
 }
\ No newline at end of file
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use screenpipe_core::PipeTranscriber;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::stt::engines::whisper::CandleWhisperModel;
+use crate::stt::engines::{initialize_stt_engines, SttEngineConfig};
+use crate::stt::{perform_stt, SttEngine, SttOptions};
+use crate::vad_engine::{create_vad_engine, VadEngineEnum};
+use crate::{audio_levels, pcm_decode, AudioInput};
+
+type SharedEngine = Arc<dyn SttEngine + Send + Sync>;
+
+/// Transcribes files for pipes' `pipe.transcribe(path, options)`: the file is decoded with
+/// `pcm_decode` and run through `perform_stt`.
+///
+/// `options.engine` picks the engine:
+/// - `whisper-tiny` (default) and `whisper-large`: local, no key needed
+/// - `deepgram`: needs `DEEPGRAM_API_KEY`
+/// - `openai`: needs `OPENAI_API_KEY`
+/// - `assemblyai`: needs `ASSEMBLYAI_API_KEY`
+/// - `azure`: needs `AZURE_SPEECH_KEY` and `AZURE_SPEECH_REGION`
+///
+/// The keys are read from the host's environment, not the pipe's. `options.language` is an
+/// ISO-639-1 hint such as `en`. Engines are loaded on first use and kept for later calls.
+pub struct PipeSttTranscriber {
+    engines: Mutex<HashMap<(String, Option<String>), SharedEngine>>,
+    /// Per-file STT limit; the pipe's own timeout still applies
+    transcribe_timeout: Option<Duration>,
+}
+
+impl std::fmt::Debug for PipeSttTranscriber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipeSttTranscriber")
+            .field("transcribe_timeout", &self.transcribe_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PipeSttTranscriber {
+    pub fn new(transcribe_timeout: Option<Duration>) -> Self {
+        Self {
+            engines: Mutex::new(HashMap::new()),
+            transcribe_timeout,
+        }
+    }
+
+    fn engine_config(engine: &str, language: Option<String>) -> Result<SttEngineConfig> {
+        let env_key = |name: &str| {
+            std::env::var(name).map_err(|_| anyhow!("The {} engine needs {} to be set", engine, name))
+        };
+        let mut config = SttEngineConfig {
+            language,
+            ..Default::default()
+        };
+        match engine {
+            "whisper-tiny" => config.local_model = Some(CandleWhisperModel::Tiny),
+            "whisper-large" => config.local_model = Some(CandleWhisperModel::DistillLarge),
+            "deepgram" => config.deepgram_api_key = Some(env_key("DEEPGRAM_API_KEY")?),
+            "openai" => config.openai_api_key = Some(env_key("OPENAI_API_KEY")?),
+            "assemblyai" => config.assemblyai_api_key = Some(env_key("ASSEMBLYAI_API_KEY")?),
+            "azure" => {
+                config.azure_key = Some(env_key("AZURE_SPEECH_KEY")?);
+                config.azure_region = Some(env_key("AZURE_SPEECH_REGION")?);
+            }
+            other => return Err(anyhow!("Unknown transcription engine {:?}", other)),
+        }
+        Ok(config)
+    }
+
+    async fn engine(&self, engine: &str, language: Option<String>) -> Result<SharedEngine> {
+        let key = (engine.to_string(), language.clone());
+        let mut engines = self.engines.lock().await;
+        if let Some(engine) = engines.get(&key) {
+            return Ok(engine.clone());
+        }
+        let config = Self::engine_config(engine, language)?;
+        // Loading a local model blocks for a while
+        let (primary, _) = tokio::task::spawn_blocking(move || initialize_stt_engines(config)).await??;
+        let primary: SharedEngine = Arc::from(primary);
+        engines.insert(key, primary.clone());
+        Ok(primary)
+    }
+
+    async fn transcribe_file(&self, path: &Path, options: &Value) -> Result<Value> {
+        let engine_name = options["engine"].as_str().unwrap_or("whisper-tiny");
+        let language = options["language"].as_str().map(str::to_string);
+        let engine = self.engine(engine_name, language).await?;
+
+        let file_path = path.to_path_buf();
+        // pcm_decode returns the first channel only
+        let (data, sample_rate) = tokio::task::spawn_blocking(move || pcm_decode(&file_path)).await??;
+        if data.is_empty() {
+            return Err(anyhow!("No audio decoded from {:?}", path));
+        }
+        let (peak, rms) = audio_levels(&data);
+        let input = AudioInput {
+            data,
+            sample_rate,
+            channels: 1,
+            device: path.display().to_string(),
+            peak,
+            rms,
+        };
+
+        // WebRTC needs no model download
+        let mut vad_engine = create_vad_engine(VadEngineEnum::WebRtc { aggressiveness: None })?;
+        let stt_options = SttOptions {
+            transcribe_timeout: self.transcribe_timeout,
+            ..Default::default()
+        };
+        info!("Transcribing {:?} for a pipe with {}", path, engine_name);
+        let output = perform_stt(&input, engine.as_ref(), &[], vad_engine.as_mut(), &None, &stt_options, None).await?;
+
+        Ok(json!({
+            "text": output.text,
+            "words": output.words,
+            "segments": output.segments,
+            "engine": output.engine,
+            "used_fallback": output.used_fallback,
+        }))
+    }
+}
+
+impl PipeTranscriber for PipeSttTranscriber {
+    fn transcribe<'a>(
+        &'a self,
+        path: &'a Path,
+        options: &'a Value,
+    ) -> Pin<Box<dyn Future<Output = Result<Value>> + Send + 'a>> {
+        Box::pin(self.transcribe_file(path, options))
+    }
+}
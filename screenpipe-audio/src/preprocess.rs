@@ -0,0 +1,158 @@
+use anyhow::{bail, Result};
+
+/// Auto-gain of `SttOptions::normalize`, so a mic recorded with a low gain isn't rejected by VAD
+/// and under-transcribed by whisper.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normalization {
+    /// RMS level quiet chunks are brought up to, 0.1 (-20 dBFS) by default
+    pub target_rms: f32,
+    /// Peak the gain never pushes a sample past, so boosted speech doesn't clip
+    pub max_peak: f32,
+    /// Largest gain applied, 30 dB by default
+    pub max_gain: f32,
+    /// Chunks with an RMS below this (-60 dBFS by default) are left alone, amplifying them would
+    /// only turn silence into noise VAD mistakes for speech
+    pub noise_floor: f32,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self {
+            target_rms: 0.1,
+            max_peak: 0.99,
+            max_gain: 31.6,
+            noise_floor: 0.001,
+        }
+    }
+}
+
+impl Normalization {
+    /// Brings `samples` up to `target_rms`, returning the gain applied. Only boosts: chunks at
+    /// or above the target, below the noise floor or already peaking are returned unchanged
+    /// with a gain of 1.
+    pub fn apply(&self, samples: &mut [f32]) -> f32 {
+        let (peak, rms) = crate::audio_levels(samples);
+        if rms < self.noise_floor || rms >= self.target_rms || peak == 0.0 {
+            return 1.0;
+        }
+        let gain = (self.target_rms / rms).min(self.max_peak / peak).min(self.max_gain);
+        if gain <= 1.0 {
+            return 1.0;
+        }
+        for sample in samples.iter_mut() {
+            *sample *= gain;
+        }
+        gain
+    }
+}
+
+/// Sample rate `SpeechFilter` runs at, the whisper rate `perform_stt` resamples to
+const FILTER_SAMPLE_RATE: f32 = 16000.0;
+
+/// High-pass and low-pass filtering of `SttOptions::filter`, against mains hum (50/60 Hz) and
+/// hiss. Runs after resampling to 16kHz, before VAD. Around 80 Hz is a sensible high-pass for
+/// speech, a low-pass at 7000 Hz keeps it intelligible while cutting hiss.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpeechFilter {
+    highpass_hz: Option<f32>,
+    lowpass_hz: Option<f32>,
+}
+
+impl SpeechFilter {
+    pub fn new(highpass_hz: Option<f32>, lowpass_hz: Option<f32>) -> Result<Self> {
+        let nyquist = FILTER_SAMPLE_RATE / 2.0;
+        for cutoff in [highpass_hz, lowpass_hz].into_iter().flatten() {
+            if cutoff.is_nan() || cutoff <= 0.0 || cutoff >= nyquist {
+                bail!("Filter cutoff {} Hz must be between 0 and {} Hz", cutoff, nyquist);
+            }
+        }
+        if let (Some(highpass), Some(lowpass)) = (highpass_hz, lowpass_hz) {
+            if lowpass <= highpass {
+                bail!("Low-pass cutoff {} Hz must be above the high-pass cutoff {} Hz", lowpass, highpass);
+            }
+        }
+        Ok(Self { highpass_hz, lowpass_hz })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.highpass_hz.is_none() && self.lowpass_hz.is_none()
+    }
+
+    /// Filters 16kHz `samples` in place, each of the interleaved `channels` separately.
+    pub fn apply(&self, samples: &mut [f32], channels: u16) {
+        let channels = channels.max(1) as usize;
+        for channel in 0..channels {
+            let mut stages: Vec<Biquad> = self
+                .highpass_hz
+                .map(Biquad::highpass)
+                .into_iter()
+                .chain(self.lowpass_hz.map(Biquad::lowpass))
+                .collect();
+            for sample in samples.iter_mut().skip(channel).step_by(channels) {
+                *sample = stages.iter_mut().fold(*sample, |x, stage| stage.process(x));
+            }
+        }
+    }
+}
+
+/// Second-order Butterworth section (RBJ audio EQ cookbook, Q = 1/sqrt(2)) at
+/// `FILTER_SAMPLE_RATE`, in transposed direct form II.
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    fn highpass(cutoff_hz: f32) -> Self {
+        let (cos, alpha) = Self::prototype(cutoff_hz);
+        Self::normalized(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    fn lowpass(cutoff_hz: f32) -> Self {
+        let (cos, alpha) = Self::prototype(cutoff_hz);
+        Self::normalized(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    fn prototype(cutoff_hz: f32) -> (f32, f32) {
+        let omega = 2.0 * std::f32::consts::PI * cutoff_hz / FILTER_SAMPLE_RATE;
+        (omega.cos(), omega.sin() / std::f32::consts::SQRT_2)
+    }
+
+    fn normalized(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt::Write;
+
+use crate::{TranscriptionResult, Word};
+
+/// Most words per cue when building cues from word timestamps
+const MAX_WORDS_PER_CUE: usize = 10;
+/// Silence between two words that starts a new cue
+const MAX_WORD_GAP_MS: u64 = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrtCue {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+/// Builds subtitle cues from final transcription results, in the order given. Times are relative
+/// to the earliest result's `timestamp`.
+///
+/// Results with word timestamps are split into short cues (on pauses, speaker changes or
+/// every few words); results without them become a single cue spanning their audio chunk.
+pub fn build_cues(results: &[TranscriptionResult]) -> Vec<SrtCue> {
+    let origin = match results.iter().map(|r| r.timestamp).min() {
+        Some(origin) => origin,
+        None => return Vec::new(),
+    };
+
+    let mut cues = Vec::new();
+    for result in results.iter().filter(|r| r.is_final) {
+        let text = match result.transcription.as_deref().map(str::trim) {
+            Some(text) if !text.is_empty() => text,
+            _ => continue,
+        };
+        let offset_ms = (result.timestamp - origin) * 1000;
+
+        if result.words.is_empty() {
+            let samples_per_second = result.input.sample_rate as u64 * result.input.channels.max(1) as u64;
+            let duration_ms = if samples_per_second == 0 {
+                0
+            } else {
+                result.input.data.len() as u64 * 1000 / samples_per_second
+            };
+            cues.push(SrtCue {
+                start_ms: offset_ms,
+                end_ms: offset_ms + duration_ms.max(1),
+                text: text.to_string(),
+            });
+            continue;
+        }
+
+        for group in group_words(&result.words) {
+            cues.push(SrtCue {
+                start_ms: offset_ms + group[0].start_ms,
+                end_ms: offset_ms + group[group.len() - 1].end_ms,
+                text: group.iter().map(|w| w.text.as_str()).collect::<Vec<_>>().join(" "),
+            });
+        }
+    }
+    cues
+}
+
+fn group_words(words: &[Word]) -> Vec<&[Word]> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for i in 1..words.len() {
+        let gap = words[i].start_ms.saturating_sub(words[i - 1].end_ms);
+        if i - start >= MAX_WORDS_PER_CUE
+            || gap > MAX_WORD_GAP_MS
+            || words[i].speaker != words[i - 1].speaker
+        {
+            groups.push(&words[start..i]);
+            start = i;
+        }
+    }
+    if start < words.len() {
+        groups.push(&words[start..]);
+    }
+    groups
+}
+
+/// Renders cues in the SubRip format.
+pub fn render(cues: &[SrtCue]) -> String {
+    let mut out = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        let _ = write!(
+            out,
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_timestamp(cue.start_ms),
+            format_timestamp(cue.end_ms),
+            cue.text
+        );
+    }
+    out
+}
+
+fn format_timestamp(ms: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        ms / 3_600_000,
+        (ms / 60_000) % 60,
+        (ms / 1000) % 60,
+        ms % 1000
+    )
+}
@@ -0,0 +1,191 @@
+use reqwest::Client;
+use anyhow::{Result, anyhow};
+use log::{debug, error, info};
+use serde_json::{json, Value};
+use crate::stt::{create_wav, WavSampleFormat, EngineOutput, SttEngine, Word};
+use std::{future::Future, pin::Pin, time::Duration};
+
+use super::{http_client, send_with_retry, RetryPolicy, HOSTED_API_TIMEOUT};
+
+const ASSEMBLYAI_UPLOAD_URL: &str = "https://api.assemblyai.com/v2/upload";
+const ASSEMBLYAI_TRANSCRIPT_URL: &str = "https://api.assemblyai.com/v2/transcript";
+
+/// Options for AssemblyAI transcripts and how long to wait for them.
+#[derive(Clone, Debug)]
+pub struct AssemblyAiConfig {
+    /// Label words with the speaker that said them
+    pub speaker_labels: bool,
+    /// e.g. `en_us`; AssemblyAI's default when `None`
+    pub language_code: Option<String>,
+    /// Delay between checks of a queued transcript
+    pub poll_interval: Duration,
+    /// Longest to wait for a transcript to complete after the upload
+    pub timeout: Duration,
+}
+
+impl Default for AssemblyAiConfig {
+    fn default() -> Self {
+        Self {
+            speaker_labels: false,
+            language_code: None,
+            poll_interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// AssemblyAI's pre-recorded API: the audio is uploaded, a transcript is requested for it and
+/// then polled until it completes.
+pub struct AssemblyAiEngine {
+    client: Client,
+    api_key: String,
+    config: AssemblyAiConfig,
+    retry_policy: RetryPolicy,
+}
+
+impl AssemblyAiEngine {
+    pub fn new(api_key: String, config: AssemblyAiConfig, retry_policy: RetryPolicy) -> Result<Self> {
+        let client = http_client(HOSTED_API_TIMEOUT)?;
+        Ok(Self { client, api_key, config, retry_policy })
+    }
+
+    async fn upload(&self, wav_data: Vec<u8>) -> Result<String> {
+        let response = send_with_retry(&self.retry_policy, || {
+            Ok(self
+                .client
+                .post(ASSEMBLYAI_UPLOAD_URL)
+                .header("Authorization", &self.api_key)
+                .header("Content-Type", "application/octet-stream")
+                .body(wav_data.clone()))
+        })
+        .await?;
+        let json = Self::json_or_error(response).await?;
+        json["upload_url"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("AssemblyAI upload response has no upload_url: {}", json))
+    }
+
+    async fn request_transcript(&self, audio_url: &str) -> Result<String> {
+        let mut body = json!({
+            "audio_url": audio_url,
+            "speaker_labels": self.config.speaker_labels,
+        });
+        if let Some(language_code) = &self.config.language_code {
+            body["language_code"] = json!(language_code);
+        }
+        let response = send_with_retry(&self.retry_policy, || {
+            Ok(self
+                .client
+                .post(ASSEMBLYAI_TRANSCRIPT_URL)
+                .header("Authorization", &self.api_key)
+                .json(&body))
+        })
+        .await?;
+        let json = Self::json_or_error(response).await?;
+        json["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("AssemblyAI transcript response has no id: {}", json))
+    }
+
+    /// Polls the transcript until AssemblyAI reports it completed or failed.
+    async fn wait_for_transcript(&self, id: &str) -> Result<Value> {
+        let url = format!("{}/{}", ASSEMBLYAI_TRANSCRIPT_URL, id);
+        loop {
+            let response = send_with_retry(&self.retry_policy, || {
+                Ok(self.client.get(&url).header("Authorization", &self.api_key))
+            })
+            .await?;
+            let json = Self::json_or_error(response).await?;
+            match json["status"].as_str() {
+                Some("completed") => return Ok(json),
+                Some("error") => {
+                    return Err(anyhow!("AssemblyAI transcript failed: {}", json["error"]));
+                }
+                status => debug!("assemblyai transcript {} is {:?}", id, status),
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    async fn json_or_error(response: reqwest::Response) -> Result<Value> {
+        let status = response.status();
+        let json: Value = response.json().await?;
+        if !status.is_success() {
+            error!("AssemblyAI API error: HTTP {}, response: {:?}", status, json);
+            return Err(anyhow!("AssemblyAI API error: HTTP {}: {}", status, json["error"]));
+        }
+        Ok(json)
+    }
+
+    fn parse_words(transcript: &Value) -> Vec<Word> {
+        let Some(words) = transcript["words"].as_array() else {
+            return Vec::new();
+        };
+        words
+            .iter()
+            .filter_map(|word| {
+                Some(Word {
+                    text: word["text"].as_str()?.to_string(),
+                    start_ms: word["start"].as_u64()?,
+                    end_ms: word["end"].as_u64()?,
+                    confidence: word["confidence"].as_f64().unwrap_or(0.0) as f32,
+                    // Speakers are labelled "A", "B", ...
+                    speaker: word["speaker"]
+                        .as_str()
+                        .and_then(|s| s.chars().next())
+                        .filter(char::is_ascii_uppercase)
+                        .map(|c| c as u32 - 'A' as u32),
+                })
+            })
+            .collect()
+    }
+
+    async fn transcribe_with_assemblyai(&self, wav_data: Vec<u8>, device: &str) -> Result<EngineOutput> {
+        debug!("device: {}, uploading {} bytes to assemblyai", device, wav_data.len());
+        let audio_url = self.upload(wav_data).await?;
+        let id = self.request_transcript(&audio_url).await?;
+        debug!("device: {}, waiting for assemblyai transcript {}", device, id);
+        let transcript = tokio::time::timeout(self.config.timeout, self.wait_for_transcript(&id))
+            .await
+            .map_err(|_| anyhow!("AssemblyAI transcript {} not ready after {:?}", id, self.config.timeout))??;
+
+        let text = transcript["text"].as_str().unwrap_or("").to_string();
+        if text.is_empty() {
+            info!("device: {}, transcription is empty", device);
+        } else {
+            info!(
+                "device: {}, transcription successful. length: {} characters",
+                device,
+                text.len()
+            );
+        }
+        Ok(EngineOutput {
+            text,
+            words: Self::parse_words(&transcript),
+            // Set or detected, e.g. `en_us`
+            language: transcript["language_code"].as_str().map(str::to_string),
+            ..Default::default()
+        })
+    }
+}
+
+impl SttEngine for AssemblyAiEngine {
+    fn name(&self) -> &'static str {
+        "assemblyai"
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        audio_data: &'a [f32],
+        sample_rate: u32,
+        channels: u16,
+        device_name: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        Box::pin(async move {
+            let wav_data = create_wav(audio_data, sample_rate, channels, WavSampleFormat::I16)?;
+            self.transcribe_with_assemblyai(wav_data, device_name).await
+        })
+    }
+}
@@ -0,0 +1,134 @@
+use reqwest::Client;
+use anyhow::{Result, anyhow};
+use log::{debug, error, info};
+use serde_json::Value;
+use crate::stt::{create_wav, resample_with_mode, ChannelMode, ResampleQuality, WavSampleFormat, EngineOutput, SttEngine};
+use std::{future::Future, pin::Pin};
+
+use super::{http_client, send_with_retry, RetryPolicy, HOSTED_API_TIMEOUT};
+
+/// The short-audio API only takes 16kHz mono PCM WAV
+const AZURE_SAMPLE_RATE: u32 = 16000;
+/// Azure rejects short-audio requests over 60s; longer input is sent in pieces of this length
+const AZURE_MAX_REQUEST_SECS: usize = 55;
+
+/// Azure Speech short-audio REST API, authenticated with a Speech resource key.
+pub struct AzureSpeechEngine {
+    client: Client,
+    subscription_key: String,
+    region: String,
+    /// e.g. `en-US`
+    language: String,
+    /// Of the downmix to `AZURE_SAMPLE_RATE`
+    resample_quality: ResampleQuality,
+    retry_policy: RetryPolicy,
+}
+
+impl AzureSpeechEngine {
+    pub fn new(
+        subscription_key: String,
+        region: String,
+        language: Option<String>,
+        resample_quality: ResampleQuality,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        let client = http_client(HOSTED_API_TIMEOUT)?;
+        Ok(Self {
+            client,
+            subscription_key,
+            region,
+            language: language.unwrap_or_else(|| "en-US".to_string()),
+            resample_quality,
+            retry_policy,
+        })
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "https://{}.stt.speech.microsoft.com/speech/recognition/conversation/cognitiveservices/v1",
+            self.region
+        )
+    }
+
+    async fn transcribe_with_azure(&self, wav_data: Vec<u8>, device: &str) -> Result<String> {
+        let url = self.url();
+        let response = send_with_retry(&self.retry_policy, || {
+            Ok(self
+                .client
+                .post(&url)
+                .query(&[("language", self.language.as_str()), ("format", "simple")])
+                .header("Ocp-Apim-Subscription-Key", &self.subscription_key)
+                .header(
+                    "Content-Type",
+                    format!("audio/wav; codecs=audio/pcm; samplerate={}", AZURE_SAMPLE_RATE),
+                )
+                .body(wav_data.clone()))
+        })
+        .await
+        .map_err(|e| {
+            error!("Failed to send request to Azure Speech: {:?}", e);
+            anyhow!("Failed to send request to Azure Speech: {:?}", e)
+        })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            error!("Azure Speech error: HTTP {}, response: {}", status, body);
+            return Err(anyhow!("Azure Speech error: HTTP {}: {}", status, body));
+        }
+
+        let json: Value = response.json().await?;
+        match json["RecognitionStatus"].as_str() {
+            Some("Success") => Ok(json["DisplayText"].as_str().unwrap_or("").to_string()),
+            // Nothing recognizable in the audio
+            Some("NoMatch") | Some("InitialSilenceTimeout") | Some("BabbleTimeout") => {
+                debug!("device: {}, azure found no speech: {}", device, json["RecognitionStatus"]);
+                Ok(String::new())
+            }
+            _ => Err(anyhow!("Azure Speech recognition failed: {}", json)),
+        }
+    }
+}
+
+impl SttEngine for AzureSpeechEngine {
+    fn name(&self) -> &'static str {
+        "azure"
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        audio_data: &'a [f32],
+        sample_rate: u32,
+        channels: u16,
+        device_name: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        Box::pin(async move {
+            let data = if sample_rate != AZURE_SAMPLE_RATE || channels != 1 {
+                resample_with_mode(audio_data.to_vec(), channels, sample_rate, AZURE_SAMPLE_RATE, ChannelMode::Downmix, self.resample_quality)?.0
+            } else {
+                audio_data.to_vec()
+            };
+
+            let mut texts = Vec::new();
+            for piece in data.chunks(AZURE_SAMPLE_RATE as usize * AZURE_MAX_REQUEST_SECS) {
+                let wav_data = create_wav(piece, AZURE_SAMPLE_RATE, 1, WavSampleFormat::I16)?;
+                let text = self.transcribe_with_azure(wav_data, device_name).await?;
+                if !text.is_empty() {
+                    texts.push(text);
+                }
+            }
+
+            let transcription = texts.join(" ");
+            if transcription.is_empty() {
+                info!("device: {}, transcription is empty", device_name);
+            } else {
+                info!(
+                    "device: {}, transcription successful. length: {} characters",
+                    device_name,
+                    transcription.len()
+                );
+            }
+            Ok(EngineOutput::from(transcription))
+        })
+    }
+}
@@ -1,5 +1,11 @@
+use futures_util::{SinkExt, StreamExt};
 use reqwest::Client;
 use serde_json::Value;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{client::IntoClientRequest, Message},
+};
 
 
 use anyhow::Result;
@@ -7,12 +13,69 @@ use log::{debug, error, info};
 #[cfg(target_os = "macos")]
 use objc::rc::autoreleasepool;
 
-use crate::stt::{create_wav, SttEngine};
+use crate::stt::{create_wav, encode_pcm, WavSampleFormat, EngineOutput, SttEngine, Word};
+
+use super::{http_client, send_with_retry, RetryPolicy, HOSTED_API_TIMEOUT};
 
 use std::{future::Future, pin::Pin};
 
+const DEEPGRAM_LISTEN_URL: &str = "https://api.deepgram.com/v1/listen";
+const DEEPGRAM_LISTEN_WS_URL: &str = "wss://api.deepgram.com/v1/listen";
+/// Audio sent per websocket message when streaming
+const STREAM_MESSAGE_MS: usize = 100;
+
+/// Query options for the Deepgram listen API. The default matches `model=nova-2&smart_format=true`.
+#[derive(Clone, Debug)]
+pub struct DeepgramConfig {
+    /// e.g. `nova-2`, `nova-3`, `whisper`
+    pub model: String,
+    pub smart_format: bool,
+    pub diarize: bool,
+    pub punctuate: bool,
+    pub numerals: bool,
+    /// BCP-47 tag such as `en` or `en-US`; Deepgram's default when `None`
+    pub language: Option<String>,
+}
+
+impl Default for DeepgramConfig {
+    fn default() -> Self {
+        Self {
+            model: "nova-2".to_string(),
+            smart_format: true,
+            diarize: false,
+            punctuate: false,
+            numerals: false,
+            language: None,
+        }
+    }
+}
+
+impl DeepgramConfig {
+    /// Flags are only sent when enabled so the request stays identical to the defaults otherwise.
+    fn query_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![("model", self.model.clone())];
+        for (name, enabled) in [
+            ("smart_format", self.smart_format),
+            ("diarize", self.diarize),
+            ("punctuate", self.punctuate),
+            ("numerals", self.numerals),
+        ] {
+            if enabled {
+                params.push((name, "true".to_string()));
+            }
+        }
+        if let Some(language) = &self.language {
+            params.push(("language", language.clone()));
+        }
+        params
+    }
+}
+
 pub struct DeepgramEngine {
+    client: Client,
     api_key: String,
+    config: DeepgramConfig,
+    retry_policy: RetryPolicy,
 }
 
 // // Replace the get_deepgram_api_key function with this:
@@ -21,28 +84,35 @@ pub struct DeepgramEngine {
 // }
 
 impl DeepgramEngine {
-    pub fn new(api_key: String) -> Self {
-        Self { api_key }
+    pub fn new(api_key: String, config: DeepgramConfig, retry_policy: RetryPolicy) -> Result<Self> {
+        let client = http_client(HOSTED_API_TIMEOUT)?;
+        Ok(Self { client, api_key, config, retry_policy })
     }
+    #[allow(clippy::too_many_arguments)]
     async fn transcribe_with_deepgram(
+        client: &Client,
         api_key: &str,
+        config: &DeepgramConfig,
+        retry_policy: &RetryPolicy,
         audio_data: &[f32],
         device: &str,
         sample_rate: u32,
         channels: u16,
-    ) -> Result<String> {
+    ) -> Result<EngineOutput> {
         debug!("starting deepgram transcription");
-        let client = Client::new();
 
         // Get the WAV data from the cursor
-        let wav_data = create_wav(&audio_data, sample_rate, channels, cpal::SampleFormat::F32)?;
+        let wav_data = create_wav(&audio_data, sample_rate, channels, WavSampleFormat::F32)?;
 
-        let response = client
-            .post("https://api.deepgram.com/v1/listen?model=nova-2&smart_format=true")
-            .header("Content-Type", "audio/wav")
-            .header("Authorization", format!("Token {}", api_key))
-            .body(wav_data)
-            .send();
+        let query_params = config.query_params();
+        let response = send_with_retry(retry_policy, || {
+            Ok(client
+                .post(DEEPGRAM_LISTEN_URL)
+                .query(&query_params)
+                .header("Content-Type", "audio/wav")
+                .header("Authorization", format!("Token {}", api_key))
+                .body(wav_data.clone()))
+        });
 
         match response.await {
             Ok(resp) => {
@@ -57,10 +127,12 @@ impl DeepgramEngine {
                             );
                             return Err(anyhow::anyhow!("Deepgram API error: {:?}", result));
                         }
-                        let transcription = result["results"]["channels"][0]["alternatives"][0]
-                            ["transcript"]
-                            .as_str()
-                            .unwrap_or("");
+                        let channel = &result["results"]["channels"][0];
+                        let alternative = &channel["alternatives"][0];
+                        let transcription = alternative["transcript"].as_str().unwrap_or("");
+                        let words = Self::parse_words(alternative);
+                        // Only there with `detect_language=true` or a multilingual model
+                        let language = channel["detected_language"].as_str().map(str::to_string);
 
                         if transcription.is_empty() {
                             info!(
@@ -75,7 +147,12 @@ impl DeepgramEngine {
                             );
                         }
 
-                        Ok(transcription.to_string())
+                        Ok(EngineOutput {
+                            text: transcription.to_string(),
+                            words,
+                            language,
+                            ..Default::default()
+                        })
                     }
                     Err(e) => {
                         error!("Failed to parse JSON response: {:?}", e);
@@ -92,23 +169,166 @@ impl DeepgramEngine {
             }
         }
     }
+
+    /// Sends the audio over Deepgram's streaming API with interim results enabled. Interim text
+    /// (the finalized part plus the current guess) goes to `partials`.
+    async fn stream_with_deepgram(
+        api_key: &str,
+        config: &DeepgramConfig,
+        audio_data: &[f32],
+        sample_rate: u32,
+        channels: u16,
+        partials: UnboundedSender<String>,
+    ) -> Result<EngineOutput> {
+        let mut query_params = config.query_params();
+        query_params.extend([
+            ("encoding", "linear16".to_string()),
+            ("sample_rate", sample_rate.to_string()),
+            ("channels", channels.to_string()),
+            ("interim_results", "true".to_string()),
+        ]);
+        let url = reqwest::Url::parse_with_params(DEEPGRAM_LISTEN_WS_URL, &query_params)?;
+        let mut request = url.as_str().into_client_request()?;
+        request
+            .headers_mut()
+            .insert("Authorization", format!("Token {}", api_key).parse()?);
+
+        debug!("connecting to deepgram streaming api");
+        let (socket, _) = connect_async(request).await?;
+        let (mut sink, mut stream) = socket.split();
+
+        let pcm = encode_pcm(audio_data, WavSampleFormat::I16);
+        let bytes_per_message = (sample_rate as usize * channels as usize * 2 * STREAM_MESSAGE_MS / 1000).max(2);
+
+        let send_audio = async {
+            for piece in pcm.chunks(bytes_per_message) {
+                sink.send(Message::Binary(piece.to_vec())).await?;
+            }
+            // Deepgram flushes the remaining results and closes the socket after this
+            sink.send(Message::Text(r#"{"type":"CloseStream"}"#.to_string())).await?;
+            Ok::<_, anyhow::Error>(())
+        };
+
+        let receive_results = async {
+            let mut finals: Vec<String> = Vec::new();
+            let mut words = Vec::new();
+            while let Some(message) = stream.next().await {
+                let text = match message? {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                let result: Value = serde_json::from_str(&text)?;
+                if result["type"] != "Results" {
+                    continue;
+                }
+                let alternative = &result["channel"]["alternatives"][0];
+                let transcript = alternative["transcript"].as_str().unwrap_or("");
+                if result["is_final"].as_bool().unwrap_or(false) {
+                    if !transcript.is_empty() {
+                        finals.push(transcript.to_string());
+                    }
+                    words.extend(Self::parse_words(alternative));
+                    partials.send(finals.join(" ")).ok();
+                } else if !transcript.is_empty() {
+                    let mut partial = finals.join(" ");
+                    if !partial.is_empty() {
+                        partial.push(' ');
+                    }
+                    partial.push_str(transcript);
+                    partials.send(partial).ok();
+                }
+            }
+            Ok::<_, anyhow::Error>(EngineOutput {
+                text: finals.join(" "),
+                words,
+                ..Default::default()
+            })
+        };
+
+        let (sent, output) = tokio::join!(send_audio, receive_results);
+        sent?;
+        output
+    }
+
+    fn parse_words(alternative: &Value) -> Vec<Word> {
+        alternative["words"]
+            .as_array()
+            .map(|words| {
+                words
+                    .iter()
+                    .filter_map(|w| {
+                        let text = w["punctuated_word"].as_str().or(w["word"].as_str())?;
+                        Some(Word {
+                            text: text.to_string(),
+                            start_ms: (w["start"].as_f64()? * 1000.0) as u64,
+                            end_ms: (w["end"].as_f64()? * 1000.0) as u64,
+                            confidence: w["confidence"].as_f64().unwrap_or(0.0) as f32,
+                            speaker: w["speaker"].as_u64().map(|s| s as u32),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 }
 
 impl SttEngine for DeepgramEngine {
+    fn name(&self) -> &'static str {
+        "deepgram"
+    }
+
     fn transcribe<'a>(
         &'a self,
         audio_data: &'a [f32],
         sample_rate: u32,
         channels: u16,
         device_name: &'a str,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
             Box::pin(async move {
                 info!(
                     "device: {}, using deepgram api key: {}...",
                     device_name,
                     &self.api_key[..8]
                 );
-                DeepgramEngine::transcribe_with_deepgram(&self.api_key, audio_data, device_name, sample_rate, channels).await
+                DeepgramEngine::transcribe_with_deepgram(&self.client, &self.api_key, &self.config, &self.retry_policy, audio_data, device_name, sample_rate, channels).await
+        })
+    }
+
+    fn transcribe_streaming<'a>(
+        &'a self,
+        audio_data: &'a [f32],
+        sample_rate: u32,
+        channels: u16,
+        device_name: &'a str,
+        partials: UnboundedSender<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        Box::pin(async move {
+            info!("device: {}, streaming to deepgram", device_name);
+            DeepgramEngine::stream_with_deepgram(&self.api_key, &self.config, audio_data, sample_rate, channels, partials).await
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_words_keeps_speaker_labels() {
+        // A diarized alternative, the second word lacking a label as without `diarize=true`
+        let alternative = serde_json::json!({
+            "words": [
+                { "word": "hello", "punctuated_word": "Hello,", "start": 0.5, "end": 0.9, "confidence": 0.98, "speaker": 1 },
+                { "word": "there", "start": 1.0, "end": 1.25, "confidence": 0.9 }
+            ]
+        });
+        let words = DeepgramEngine::parse_words(&alternative);
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].text, "Hello,");
+        assert_eq!((words[0].start_ms, words[0].end_ms), (500, 900));
+        assert_eq!(words[0].speaker, Some(1));
+        assert_eq!(words[1].text, "there");
+        assert_eq!(words[1].speaker, None);
+    }
+}
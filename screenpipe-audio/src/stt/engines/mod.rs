@@ -1,109 +1,442 @@
 pub mod whisper;
+mod assemblyai;
+mod azure;
 mod deepgram;
+mod openai;
 mod restpipe;
+#[cfg(feature = "whisper-cpp")]
+mod whisper_cpp;
+#[cfg(feature = "vosk")]
+mod vosk_engine;
 
-pub use deepgram::DeepgramEngine;
+pub use assemblyai::{AssemblyAiConfig, AssemblyAiEngine};
+pub use azure::AzureSpeechEngine;
+pub use deepgram::{DeepgramConfig, DeepgramEngine};
+pub use openai::OpenAiWhisperEngine;
+pub use restpipe::{RestPipeConfig, RestPipeUpload, SUPPORTED_SAMPLE_RATES};
 use restpipe::RestPipeEngine;
-use whisper::{CandleWhisperModel, WhisperEngine};
+#[cfg(feature = "whisper-cpp")]
+pub use whisper_cpp::WhisperCppEngine;
+#[cfg(feature = "vosk")]
+pub use vosk_engine::VoskEngine;
+use whisper::{
+    default_model_repo, CandleWhisperModel, DevicePreference, HallucinationFilter, WhisperDecodeParams, WhisperEngine,
+    DEFAULT_MODEL_REVISION,
+};
 use tokio::sync::watch;
 
 use std::{
-    collections::HashMap, path::PathBuf, sync::Arc, time::{SystemTime, UNIX_EPOCH}
+    collections::HashMap, path::PathBuf, sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}
 };
 
 use anyhow::Result;
-use log::{debug, error, info};
-#[cfg(target_os = "macos")]
-use objc::rc::autoreleasepool;
+use log::{debug, error, info, warn};
+use rand::Rng;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use crate::{
-    stt::{perform_stt, SttEngine, SttErrorKind}, vad_engine::{SileroVad, VadEngine, VadEngineEnum, WebRtcVad}, AudioInput, AudioTranscriptionEngine, TranscriptionResult, WhisperModel,
+    audio_queue::{audio_queue, AudioInputSender, DropPolicy},
+    multilingual,
+    stt::{perform_stt, ResampleQuality, SttEngine, SttError, SttOptions, Task}, vad_engine::{create_vad_engine, VadEngine, VadEngineEnum, VadSensitivity}, AudioInput, AudioTranscriptionEngine, TranscriptionResult, WhisperModel,
 };
 
 use super::RecordingState;
 
-pub fn initialize_stt_engines(
-    local_model: Option<CandleWhisperModel>,
-    api_url: Option<String>,
-    api_headers: Option<String>,
-    deepgram_api_key: Option<String>,
-) -> Result<(Box<dyn SttEngine + Send + Sync>, Option<Box<dyn SttEngine + Send + Sync>>)> {
-    let local_model_opt = local_model.clone();
-    let primary_engine: Box<dyn SttEngine + Send + Sync> = if let Some(ref api_key) = deepgram_api_key {
-        Box::new(DeepgramEngine::new(api_key.clone()))
-    } else if let Some(ref url) = api_url {
-        let api_headers: HashMap<String, String> = {
-            let mut map = HashMap::new();
-            if let Some(headers_arg) = api_headers {    
-                for header in headers_arg.split(';') {
-                    let parts: Vec<&str> = header.split(':').map(str::trim).collect();
-                    if parts.len() == 2 {
-                        map.insert(parts[0].to_string(), parts[1].to_string());
-                    }
-                }
+/// Retry schedule for the network engines. Connection errors, timeouts, HTTP 429 and 5xx
+/// are retried with exponential backoff and jitter; other 4xx responses are returned as is.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn with_max_attempts(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// Backoff before the attempt following `attempt`, randomized between half and the full delay.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt - 1));
+        let capped = exponential.min(self.max_backoff);
+        capped.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Request timeout of the hosted engines (Deepgram, OpenAI)
+pub(crate) const HOSTED_API_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// HTTP client shared by every request of one engine, so connections and TLS sessions are
+/// pooled across chunks instead of being set up again for each one.
+pub fn http_client(timeout: Duration) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(Duration::from_secs(10))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .pool_max_idle_per_host(4)
+        .tcp_keepalive(Duration::from_secs(30))
+        .build()?)
+}
+
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Sends the request produced by `build_request`, rebuilding it for every attempt since
+/// multipart bodies can't be cloned.
+pub(crate) async fn send_with_retry<F>(
+    retry_policy: &RetryPolicy,
+    mut build_request: F,
+) -> Result<reqwest::Response>
+where
+    F: FnMut() -> Result<reqwest::RequestBuilder>,
+{
+    let mut attempt = 1;
+    loop {
+        match build_request()?.send().await {
+            Ok(response)
+                if is_retryable_status(response.status()) && attempt < retry_policy.max_attempts =>
+            {
+                warn!(
+                    "STT request failed with HTTP {} (attempt {}/{}), retrying",
+                    response.status(),
+                    attempt,
+                    retry_policy.max_attempts
+                );
             }
-            map    
-        };
-        // TODO: File payload field has tobe configurable
-        Box::new(RestPipeEngine::new(url.clone(), api_headers, Some("file".to_string()), Some(16000)))
-    } else {
-        let whisper_model = match local_model_opt.unwrap_or(CandleWhisperModel::Tiny) {
-            CandleWhisperModel::Tiny => AudioTranscriptionEngine::WhisperTiny,
-            _ => AudioTranscriptionEngine::WhisperDistilLargeV3,
-        };
-        Box::new(WhisperEngine::new(WhisperModel::new(Arc::new(whisper_model))?).expect("Could not create the WhisperEngine"))
-    };
+            Ok(response) => return Ok(response),
+            Err(e) if (e.is_connect() || e.is_timeout()) && attempt < retry_policy.max_attempts => {
+                warn!(
+                    "STT request failed: {} (attempt {}/{}), retrying",
+                    e, attempt, retry_policy.max_attempts
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+        tokio::time::sleep(retry_policy.backoff(attempt)).await;
+        attempt += 1;
+    }
+}
+
+/// Engine selection for `initialize_stt_engines`. Every configured engine joins the chain, in
+/// order: Deepgram, OpenAI, AssemblyAI, Azure, `api_url`, then the local model. The first is
+/// the primary, the others are tried in turn when it fails.
+#[derive(Clone, Debug, Default)]
+pub struct SttEngineConfig {
+    pub local_model: Option<CandleWhisperModel>,
+    /// GGML model for the whisper.cpp engine; takes precedence over `local_model`.
+    /// Requires the `whisper-cpp` feature.
+    pub whisper_cpp_model: Option<PathBuf>,
+    /// Unpacked Vosk model directory; takes precedence over the whisper models. Requires the
+    /// `vosk` feature.
+    pub vosk_model: Option<PathBuf>,
+    /// Directory with `config.json`, `tokenizer.json` and `model.safetensors` for the candle
+    /// engine; skips the HF download when set.
+    pub local_model_dir: Option<PathBuf>,
+    /// hf-hub cache the candle engine downloads `local_model` into, e.g. a directory shared
+    /// between machines; the default hf-hub cache when `None`
+    pub model_cache_dir: Option<PathBuf>,
+    /// HF repo the candle engine loads instead of the one of `local_model`, e.g. a fine-tune
+    pub model_repo: Option<String>,
+    /// Branch, tag or commit of the model repo, `main` when `None`
+    pub model_revision: Option<String>,
+    /// Compute device for the candle engine
+    pub compute_device: DevicePreference,
+    pub api_url: Option<String>,
+    /// Extra RestPipe headers in the `Name: Value;` format
+    pub api_headers: Option<String>,
+    pub restpipe_config: RestPipeConfig,
+    pub deepgram_api_key: Option<String>,
+    pub deepgram_config: DeepgramConfig,
+    pub openai_api_key: Option<String>,
+    pub assemblyai_api_key: Option<String>,
+    pub assemblyai_config: AssemblyAiConfig,
+    /// Azure Speech resource key; needs `azure_region`
+    pub azure_key: Option<String>,
+    /// e.g. `westeurope`
+    pub azure_region: Option<String>,
+    /// BCP-47 tag such as `en-US`, the default
+    pub azure_language: Option<String>,
+    /// Language hint (ISO-639-1, e.g. `en`) for engines that accept one
+    pub language: Option<String>,
+    /// Segments the candle engine drops as hallucinations
+    pub hallucination_filter: HallucinationFilter,
+    /// Temperature fallback, best-of and beam search of the candle engine
+    pub whisper_decode_params: WhisperDecodeParams,
+    /// Text the candle engine decodes every chunk after, to steer it towards names and jargon
+    pub initial_prompt: Option<String>,
+    pub task: Task,
+    /// Retry schedule shared by the network engines
+    pub retry_policy: RetryPolicy,
+    /// Of the resampling of the engines that upload at a fixed rate (Azure, `api_url`)
+    pub resample_quality: ResampleQuality,
+}
 
-    let fallback_engine: Option<Box<dyn SttEngine + Send + Sync>> = if deepgram_api_key.is_some() || api_url.is_some() {
-        if let Some(local_model) = local_model{
-            let whisper_model = match local_model {
-                CandleWhisperModel::Tiny => AudioTranscriptionEngine::WhisperTiny,
-                _ => AudioTranscriptionEngine::WhisperDistilLargeV3,
-            };
-            Some(Box::new(WhisperEngine::new(WhisperModel::new(Arc::new(whisper_model))?).expect("Could not create the WhisperEngine")))
-        } else {
-            None
+impl SttEngineConfig {
+    fn has_remote_engine(&self) -> bool {
+        self.deepgram_api_key.is_some()
+            || self.openai_api_key.is_some()
+            || self.assemblyai_api_key.is_some()
+            || self.azure_key.is_some()
+            || self.api_url.is_some()
+    }
+}
+
+fn parse_api_headers(api_headers: &Option<String>) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    if let Some(headers_arg) = api_headers {
+        for header in headers_arg.split(';') {
+            let parts: Vec<&str> = header.split(':').map(str::trim).collect();
+            if parts.len() == 2 {
+                map.insert(parts[0].to_string(), parts[1].to_string());
+            }
         }
-    } else {
-        None
+    }
+    map
+}
+
+fn create_local_whisper_engine(local_model: &CandleWhisperModel, config: &SttEngineConfig) -> Result<Box<dyn SttEngine + Send + Sync>> {
+    let whisper_model = match local_model {
+        CandleWhisperModel::Tiny => AudioTranscriptionEngine::WhisperTiny,
+        _ => AudioTranscriptionEngine::WhisperDistilLargeV3,
+    };
+    let whisper_model = match config.local_model_dir {
+        Some(ref dir) => WhisperModel::from_dir(dir, config.compute_device)?,
+        None => WhisperModel::from_repo(
+            config.model_repo.clone().unwrap_or_else(|| default_model_repo(&whisper_model).to_string()),
+            config.model_revision.clone().unwrap_or_else(|| DEFAULT_MODEL_REVISION.to_string()),
+            config.compute_device,
+            config.model_cache_dir.as_deref(),
+        )?,
     };
+    let engine = WhisperEngine::new(
+        whisper_model,
+        config.task,
+        config.language.clone(),
+        config.whisper_decode_params.clone(),
+        config.initial_prompt.clone(),
+    )?
+    .with_hallucination_filter(config.hallucination_filter.clone());
+    Ok(Box::new(engine))
+}
+
+#[cfg(feature = "whisper-cpp")]
+fn create_whisper_cpp_engine(model_path: &std::path::Path, config: &SttEngineConfig) -> Result<Box<dyn SttEngine + Send + Sync>> {
+    Ok(Box::new(WhisperCppEngine::new(model_path, config.task, config.language.clone())?))
+}
 
-    Ok((primary_engine, fallback_engine))
+#[cfg(not(feature = "whisper-cpp"))]
+fn create_whisper_cpp_engine(_model_path: &std::path::Path, _config: &SttEngineConfig) -> Result<Box<dyn SttEngine + Send + Sync>> {
+    anyhow::bail!("whisper.cpp engine requested but screenpipe-audio was built without the `whisper-cpp` feature")
+}
+
+#[cfg(feature = "vosk")]
+fn create_vosk_engine(model_dir: &std::path::Path, config: &SttEngineConfig) -> Result<Box<dyn SttEngine + Send + Sync>> {
+    if config.task == Task::Translate {
+        anyhow::bail!("the vosk engine can't translate, use a whisper engine");
+    }
+    Ok(Box::new(VoskEngine::new(model_dir)?))
+}
+
+#[cfg(not(feature = "vosk"))]
+fn create_vosk_engine(_model_dir: &std::path::Path, _config: &SttEngineConfig) -> Result<Box<dyn SttEngine + Send + Sync>> {
+    anyhow::bail!("vosk engine requested but screenpipe-audio was built without the `vosk` feature")
+}
+
+/// The configured local engine, if any: Vosk when a Vosk model is set, whisper.cpp when a GGML
+/// model is, candle otherwise.
+fn create_local_engine(config: &SttEngineConfig) -> Result<Option<Box<dyn SttEngine + Send + Sync>>> {
+    if let Some(ref model_dir) = config.vosk_model {
+        return Ok(Some(create_vosk_engine(model_dir, config)?));
+    }
+    if let Some(ref model_path) = config.whisper_cpp_model {
+        return Ok(Some(create_whisper_cpp_engine(model_path, config)?));
+    }
+    match config.local_model {
+        Some(ref local_model) => Ok(Some(create_local_whisper_engine(local_model, config)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn initialize_stt_engines(
+    config: SttEngineConfig,
+) -> Result<(Box<dyn SttEngine + Send + Sync>, Vec<Box<dyn SttEngine + Send + Sync>>)> {
+    if let Some(ref language) = config.language {
+        if !multilingual::is_supported_language(language) {
+            anyhow::bail!("Unknown language code {:?}, expected a whisper language such as \"en\"", language);
+        }
+    }
+
+    let mut engines: Vec<Box<dyn SttEngine + Send + Sync>> = Vec::new();
+    if let Some(ref api_key) = config.deepgram_api_key {
+        engines.push(Box::new(DeepgramEngine::new(api_key.clone(), config.deepgram_config.clone(), config.retry_policy)?));
+    }
+    if let Some(ref api_key) = config.openai_api_key {
+        engines.push(Box::new(OpenAiWhisperEngine::new(api_key.clone(), config.language.clone(), config.retry_policy)?));
+    }
+    if let Some(ref api_key) = config.assemblyai_api_key {
+        engines.push(Box::new(AssemblyAiEngine::new(api_key.clone(), config.assemblyai_config.clone(), config.retry_policy)?));
+    }
+    if let Some(ref key) = config.azure_key {
+        let region = config
+            .azure_region
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("An Azure Speech key needs a region"))?;
+        engines.push(Box::new(AzureSpeechEngine::new(key.clone(), region, config.azure_language.clone(), config.resample_quality, config.retry_policy)?));
+    }
+    if let Some(ref url) = config.api_url {
+        let api_headers = parse_api_headers(&config.api_headers);
+        // TODO: File payload field has tobe configurable
+        engines.push(Box::new(RestPipeEngine::new(url.clone(), api_headers, config.restpipe_config.clone(), config.resample_quality, config.retry_policy)?));
+    }
+    match create_local_engine(&config)? {
+        Some(engine) => engines.push(engine),
+        // Without any engine configured local whisper tiny runs alone
+        None if engines.is_empty() => engines.push(create_local_whisper_engine(&CandleWhisperModel::Tiny, &config)?),
+        None => {}
+    }
+
+    let primary_engine = engines.remove(0);
+    Ok((primary_engine, engines))
+}
+
+/// Progress of the STT worker started by `create_comm_channel_with_status`, for a host UI to
+/// show activity and notice a backlog.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SttStatus {
+    /// Chunks the worker has taken in and not transcribed yet, not counting chunks discarded by
+    /// the drop policy
+    pub inputs_queued: usize,
+    /// Chunks transcribed so far, including the ones that failed
+    pub inputs_processed: u64,
+    /// How long the last chunk took to transcribe, VAD included
+    pub last_latency: Option<Duration>,
+    /// Engine of the last transcription, the primary engine's name before the first one
+    pub engine: String,
+}
+
+/// Stops a `create_comm_channel` session from code, e.g. a GUI "Stop" button. Made from the
+/// state sender the channel returns; cloning it is cheap.
+#[derive(Clone)]
+pub struct StopHandle {
+    state_tx: watch::Sender<RecordingState>,
+}
+
+impl StopHandle {
+    pub fn new(state_tx: watch::Sender<RecordingState>) -> Self {
+        Self { state_tx }
+    }
+
+    /// Moves the session to `RecordingState::Stopping` right away: the recorders stop, chunks
+    /// being transcribed are abandoned and queued ones dropped, and the result receiver closes
+    /// once the workers are gone.
+    pub fn stop(&self) {
+        self.state_tx.send_modify(|state| *state = RecordingState::Stopping);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.state_tx.borrow() == RecordingState::Stopping
+    }
+}
+
+/// Resolves once the state is `Stopping`, never if the state sender is dropped first.
+async fn stopped(mut state_rx: watch::Receiver<RecordingState>) {
+    while *state_rx.borrow() != RecordingState::Stopping {
+        if state_rx.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
 }
 
 pub fn create_comm_channel(
     primary_whisper_engine: Box<dyn SttEngine + Send + Sync>,
-    fallback_whisper_engine: Option<Box<dyn SttEngine + Send + Sync>>,
+    fallback_whisper_engines: Vec<Box<dyn SttEngine + Send + Sync>>,
     vad_engine: VadEngineEnum,
+    vad_sensitivity: Option<VadSensitivity>,
     output_path: &Option<PathBuf>,
+    stt_options: SttOptions,
 ) -> Result<(
-    UnboundedSender<AudioInput>,
+    AudioInputSender,
     UnboundedReceiver<TranscriptionResult>,
     watch::Sender<RecordingState>,
     watch::Receiver<RecordingState>
 )> {
-    let (input_sender, mut input_receiver): (
-        UnboundedSender<AudioInput>,
-        UnboundedReceiver<AudioInput>,
-    ) = unbounded_channel();
+    let (input_sender, output_receiver, state_tx, state_rx, _) = create_comm_channel_with_status(
+        primary_whisper_engine,
+        fallback_whisper_engines,
+        vad_engine,
+        vad_sensitivity,
+        output_path,
+        stt_options,
+    )?;
+    Ok((input_sender, output_receiver, state_tx, state_rx))
+}
+
+/// Like `create_comm_channel`, also returning a receiver of the worker's `SttStatus`.
+pub fn create_comm_channel_with_status(
+    primary_whisper_engine: Box<dyn SttEngine + Send + Sync>,
+    fallback_whisper_engines: Vec<Box<dyn SttEngine + Send + Sync>>,
+    vad_engine: VadEngineEnum,
+    vad_sensitivity: Option<VadSensitivity>,
+    output_path: &Option<PathBuf>,
+    stt_options: SttOptions,
+) -> Result<(
+    AudioInputSender,
+    UnboundedReceiver<TranscriptionResult>,
+    watch::Sender<RecordingState>,
+    watch::Receiver<RecordingState>,
+    watch::Receiver<SttStatus>,
+)> {
+    let (input_sender, mut input_receiver) = audio_queue(stt_options.input_queue_capacity, stt_options.drop_policy);
     let (output_sender, output_receiver): (
         UnboundedSender<TranscriptionResult>,
         UnboundedReceiver<TranscriptionResult>,
     ) = unbounded_channel();
 
     let (state_tx, state_rx) = watch::channel(RecordingState::Initializing);
+    let (status_tx, status_rx) = watch::channel(SttStatus {
+        engine: primary_whisper_engine.name().to_string(),
+        ..Default::default()
+    });
+    let status_tx = Arc::new(status_tx);
 
-    let mut vad_engine: Box<dyn VadEngine + Send> = match vad_engine {
-        VadEngineEnum::WebRtc => Box::new(WebRtcVad::new()),
-        VadEngineEnum::Silero => Box::new(SileroVad::new()?),
-    };
+    // Created up front so a VAD that can't load fails here rather than on the first chunk; the
+    // first device to send audio gets it. This also downloads the Silero model, so the engines of
+    // later devices, created in the task below, only load it from disk.
+    let mut first_vad_engine = Some(new_vad_engine(vad_engine, vad_sensitivity)?);
 
+    let primary_whisper_engine: Arc<dyn SttEngine + Send + Sync> = Arc::from(primary_whisper_engine);
+    let fallback_whisper_engines: Arc<[Arc<dyn SttEngine + Send + Sync>]> =
+        fallback_whisper_engines.into_iter().map(Arc::from).collect();
+    let permits = Arc::new(Semaphore::new(stt_options.max_concurrent_transcriptions.max(1)));
     let output_path = output_path.clone();
-    let state_rx_clone = state_rx.clone();
+    let silence_tracker = stt_options.max_silence.map(|max_silence| Arc::new(SilenceTracker::new(max_silence)));
+    let stt_options = Arc::new(stt_options);
+    let mut state_rx_clone = state_rx.clone();
     let state_tx_clone = state_tx.clone();
 
     tokio::spawn(async move {
+        // One serial queue per device keeps each device's results in order, while the semaphore
+        // lets chunks of different devices be transcribed at the same time. The device queues
+        // share the capacity and drop policy of the input queue, and each is fed by its own
+        // forwarding task, so a slow device fills its own queue instead of holding up the others.
+        let mut device_queues: HashMap<String, UnboundedSender<AudioInput>> = HashMap::new();
         loop {
             if state_rx_clone.has_changed().unwrap_or(false) {
                 let state = *state_rx_clone.borrow();
@@ -122,34 +455,53 @@ pub fn create_comm_channel(
             tokio::select! {
                 Some(input) = input_receiver.recv() => {
                     debug!("Received input from input_receiver");
-                    let timestamp = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("Time went backwards")
-                        .as_secs();
-
-                    let transcription_result = if cfg!(target_os = "macos") {
-                        #[cfg(target_os = "macos")]
-                        {
-                            autoreleasepool(|| {
-                                handle_stt(&input, &*primary_whisper_engine, fallback_whisper_engine.as_deref(), &mut *vad_engine, &output_path, timestamp, &state_tx_clone).await
-                            })
-                        }
-                        #[cfg(not(target_os = "macos"))]
-                        {
-                            unreachable!("This code should not be reached on non-macOS platforms")
-                        }
-                    } else {
-                        handle_stt(&input, &*primary_whisper_engine, fallback_whisper_engine.as_deref(), &mut *vad_engine, &output_path, timestamp, &state_tx_clone).await
-                    };
-
-                    if output_sender.send(transcription_result).is_err() {
+                    if output_sender.is_closed() {
                         break;
                     }
+                    status_tx.send_modify(|status| status.inputs_queued += 1);
+                    if !device_queues.contains_key(&input.device) {
+                        let vad_engine = match first_vad_engine.take() {
+                            Some(vad_engine) => vad_engine,
+                            None => match new_vad_engine(vad_engine, vad_sensitivity) {
+                                Ok(vad_engine) => vad_engine,
+                                Err(e) => {
+                                    error!("Failed to create VAD engine for {}: {:?}", input.device, e);
+                                    status_tx.send_modify(|status| status.inputs_queued -= 1);
+                                    continue;
+                                }
+                            },
+                        };
+                        let worker = DeviceWorker {
+                            primary_whisper_engine: primary_whisper_engine.clone(),
+                            fallback_whisper_engines: fallback_whisper_engines.clone(),
+                            vad_engine,
+                            output_path: output_path.clone(),
+                            stt_options: stt_options.clone(),
+                            permits: permits.clone(),
+                            silence_tracker: silence_tracker.clone(),
+                            status_tx: status_tx.clone(),
+                            state_tx: state_tx_clone.clone(),
+                            output_sender: output_sender.clone(),
+                        };
+                        let queue = worker.spawn(stt_options.input_queue_capacity, stt_options.drop_policy);
+                        device_queues.insert(input.device.clone(), forward_to_worker(queue, status_tx.clone()));
+                    }
+                    let device = input.device.clone();
+                    if device_queues[&device].send(input).is_err() {
+                        status_tx.send_modify(|status| status.inputs_queued -= 1);
+                        device_queues.remove(&device);
+                    }
 
                     // if RecordingState::RecordingFinished == *state_rx_clone.borrow() {
                     //     break;
                     // }
                 }
+                Ok(()) = state_rx_clone.changed() => {
+                    if *state_rx_clone.borrow() == RecordingState::Stopping {
+                        info!("Whisper channel shutting down");
+                        break;
+                    }
+                }
                 else => {
                     break
                 },
@@ -157,41 +509,261 @@ pub fn create_comm_channel(
         }
     });
 
-    Ok((input_sender, output_receiver, state_tx, state_rx))
+    Ok((input_sender, output_receiver, state_tx, state_rx, status_rx))
+}
+
+fn new_vad_engine(kind: VadEngineEnum, sensitivity: Option<VadSensitivity>) -> Result<Box<dyn VadEngine + Send>> {
+    let mut vad_engine = create_vad_engine(kind)?;
+    // Without an explicit sensitivity each engine keeps its own default
+    if let Some(sensitivity) = sensitivity {
+        vad_engine.set_sensitivity(sensitivity);
+    }
+    Ok(vad_engine)
+}
+
+/// Consecutive audio without voice frames of each device, for `SttOptions::max_silence`.
+struct SilenceTracker {
+    max_silence: Duration,
+    silent_for: Mutex<HashMap<String, Duration>>,
+}
+
+impl SilenceTracker {
+    fn new(max_silence: Duration) -> Self {
+        Self { max_silence, silent_for: Mutex::new(HashMap::new()) }
+    }
+
+    fn speech(&self, device: &str) {
+        self.silent_for.lock().unwrap().insert(device.to_string(), Duration::ZERO);
+    }
+
+    /// Adds a silent chunk of `device`; true once every device seen has been silent for
+    /// `max_silence`, so one quiet device doesn't stop the others.
+    fn silence(&self, device: &str, chunk: Duration) -> bool {
+        let mut silent_for = self.silent_for.lock().unwrap();
+        *silent_for.entry(device.to_string()).or_default() += chunk;
+        silent_for.values().all(|silent| *silent >= self.max_silence)
+    }
+}
+
+fn chunk_duration(input: &AudioInput) -> Duration {
+    let samples_per_sec = input.sample_rate as u64 * input.channels.max(1) as u64;
+    if samples_per_sec == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(input.data.len() as f64 / samples_per_sec as f64)
 }
 
+/// Transcribes the chunks of one device in the order they were recorded.
+struct DeviceWorker {
+    primary_whisper_engine: Arc<dyn SttEngine + Send + Sync>,
+    /// Tried in order after the primary fails
+    fallback_whisper_engines: Arc<[Arc<dyn SttEngine + Send + Sync>]>,
+    vad_engine: Box<dyn VadEngine + Send>,
+    output_path: Option<PathBuf>,
+    stt_options: Arc<SttOptions>,
+    permits: Arc<Semaphore>,
+    silence_tracker: Option<Arc<SilenceTracker>>,
+    status_tx: Arc<watch::Sender<SttStatus>>,
+    state_tx: watch::Sender<RecordingState>,
+    output_sender: UnboundedSender<TranscriptionResult>,
+}
+
+/// Feeds a device worker's queue from a task of its own, so waiting on a full queue under
+/// `DropPolicy::Block` only holds up that device. Ends once the worker is gone.
+fn forward_to_worker(queue: AudioInputSender, status_tx: Arc<watch::Sender<SttStatus>>) -> UnboundedSender<AudioInput> {
+    let (forward_tx, mut forward_rx) = unbounded_channel::<AudioInput>();
+    tokio::spawn(async move {
+        while let Some(input) = forward_rx.recv().await {
+            match queue.send(input).await {
+                Ok(None) => {}
+                Ok(Some(_dropped)) => status_tx.send_modify(|status| status.inputs_queued -= 1),
+                Err(_) => {
+                    status_tx.send_modify(|status| status.inputs_queued -= 1);
+                    break;
+                }
+            }
+        }
+        forward_rx.close();
+        while forward_rx.try_recv().is_ok() {
+            status_tx.send_modify(|status| status.inputs_queued -= 1);
+        }
+    });
+    forward_tx
+}
+
+tokio::task_local! {
+    /// The semaphore permit of the chunk a device worker is transcribing.
+    static TRANSCRIPTION_PERMIT: Arc<Mutex<Option<OwnedSemaphorePermit>>>;
+}
+
+/// Takes the permit of the chunk being transcribed, for an engine whose work outlives an
+/// abandoned transcription (a blocking decode keeps running after a timeout or a stop) to hold
+/// until that work really ends. `None` outside a device worker or once taken.
+pub(crate) fn take_transcription_permit() -> Option<OwnedSemaphorePermit> {
+    TRANSCRIPTION_PERMIT
+        .try_with(|permit| permit.lock().unwrap().take())
+        .ok()
+        .flatten()
+}
+
+impl DeviceWorker {
+    fn spawn(mut self, capacity: usize, drop_policy: DropPolicy) -> AudioInputSender {
+        let (queue_tx, mut queue_rx) = audio_queue(capacity, drop_policy);
+        tokio::spawn(async move {
+            while let Some(input) = queue_rx.recv().await {
+                if *self.state_tx.borrow() == RecordingState::Stopping {
+                    break;
+                }
+                // Released at the end of the chunk, unless an engine took it along
+                let permit = match self.permits.clone().acquire_owned().await {
+                    Ok(permit) => Arc::new(Mutex::new(Some(permit))),
+                    Err(_) => break,
+                };
+                let started = std::time::Instant::now();
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Time went backwards")
+                    .as_secs();
+                // Not wrapped in an autorelease pool: a pool can't span an await, the engines
+                // drain their own around their synchronous inference (see `WhisperEngine`)
+                let fallback_whisper_engines: Vec<&(dyn SttEngine + Send + Sync)> =
+                    self.fallback_whisper_engines.iter().map(|engine| &**engine).collect();
+                let transcription = TRANSCRIPTION_PERMIT.scope(permit, handle_stt(&input, &*self.primary_whisper_engine, &fallback_whisper_engines, &mut *self.vad_engine, &self.output_path, &self.stt_options, timestamp, self.silence_tracker.as_deref(), &self.state_tx, &self.output_sender));
+                let transcription_result = tokio::select! {
+                    result = transcription => result,
+                    _ = stopped(self.state_tx.subscribe()) => {
+                        debug!("Stopping, abandoning the chunk of {}", input.device);
+                        break;
+                    }
+                };
+
+                self.status_tx.send_modify(|status| {
+                    status.inputs_queued = status.inputs_queued.saturating_sub(1);
+                    status.inputs_processed += 1;
+                    status.last_latency = Some(started.elapsed());
+                    if !transcription_result.engine.is_empty() {
+                        status.engine = transcription_result.engine.clone();
+                    }
+                });
+                if self.output_sender.send(transcription_result).is_err() {
+                    break;
+                }
+            }
+        });
+        queue_tx
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_stt(
     input: &AudioInput,
     primary_whisper_engine: &(dyn SttEngine + Send + Sync),
-    fallback_whisper_engine: Option<&(dyn SttEngine + Send + Sync)>,
+    fallback_whisper_engines: &[&(dyn SttEngine + Send + Sync)],
     vad_engine: &mut (dyn VadEngine + Send),
     output_path: &Option<PathBuf>,
+    stt_options: &SttOptions,
     timestamp: u64,
+    silence_tracker: Option<&SilenceTracker>,
     state_tx: &watch::Sender<RecordingState>,
+    output_sender: &UnboundedSender<TranscriptionResult>,
 ) -> TranscriptionResult {
-    match perform_stt(input, primary_whisper_engine, fallback_whisper_engine, vad_engine, output_path).await {
-        Ok((transcription, path)) => TranscriptionResult {
-            input: input.clone(),
-            transcription: Some(transcription),
-            path: path.unwrap_or("".to_string()),
-            timestamp,
-            error: None,
+    let result = if stt_options.partials {
+        let (partials_tx, mut partials_rx) = unbounded_channel::<String>();
+        let forward_partials = async {
+            while let Some(text) = partials_rx.recv().await {
+                let partial = TranscriptionResult {
+                    // partials don't carry the audio, the final result has it
+                    input: AudioInput {
+                        data: Vec::new(),
+                        sample_rate: input.sample_rate,
+                        channels: input.channels,
+                        device: input.device.clone(),
+                        peak: input.peak,
+                        rms: input.rms,
+                    },
+                    transcription: Some(text),
+                    words: Vec::new(),
+                    path: "".to_string(),
+                    timestamp,
+                    error: None,
+                    is_final: false,
+                    engine: primary_whisper_engine.name().to_string(),
+                    used_fallback: false,
+                    no_speech: false,
+                    vad_timeline: None,
+                    language: None,
+                };
+                if output_sender.send(partial).is_err() {
+                    break;
+                }
+            }
+        };
+        let (result, _) = tokio::join!(
+            perform_stt(input, primary_whisper_engine, fallback_whisper_engines, vad_engine, output_path, stt_options, Some(partials_tx)),
+            forward_partials
+        );
+        result
+    } else {
+        perform_stt(input, primary_whisper_engine, fallback_whisper_engines, vad_engine, output_path, stt_options, None).await
+    };
+
+    match result {
+        Ok(output) => {
+            if let Some(tracker) = silence_tracker {
+                tracker.speech(&input.device);
+            }
+            TranscriptionResult {
+                input: input.clone(),
+                transcription: Some(output.text),
+                words: output.words,
+                path: output.path.unwrap_or("".to_string()),
+                timestamp,
+                error: None,
+                is_final: true,
+                engine: output.engine,
+                used_fallback: output.used_fallback,
+                no_speech: false,
+                vad_timeline: output.vad_timeline,
+                language: output.language,
+            }
         },
         Err(e) => {
-            if let Some(SttErrorKind::NoSpeech) = e.downcast_ref::<SttErrorKind>() {
-                debug!("No speech detected for input {}: {:?}. Finishing recording", input.device, e);
-                if let Err(send_err) = state_tx.send(RecordingState::RecordingFinished) {
-                    error!("Failed to send RecordingState::Stopping: {:?}", send_err);
+            match &e {
+                SttError::NoSpeech => {
+                    let finished = match silence_tracker {
+                        Some(tracker) => tracker.silence(&input.device, chunk_duration(input)),
+                        None => true,
+                    };
+                    if finished {
+                        debug!("No speech detected for input {}. Finishing recording", input.device);
+                        if let Err(send_err) = state_tx.send(RecordingState::RecordingFinished) {
+                            error!("Failed to send RecordingState::Stopping: {:?}", send_err);
+                        }
+                    } else {
+                        debug!("No speech detected for input {}", input.device);
+                    }
+                }
+                SttError::EngineFailed { engine, source } => {
+                    error!("STT error for input {}: {} failed: {:?}", input.device, engine, source);
+                }
+                SttError::ResampleFailed(_) | SttError::EncodeFailed(_) => {
+                    error!("STT error for input {}: {:?}", input.device, e);
                 }
-            } else {
-                error!("STT error for input {}: {:?}", input.device, e);
             }
+            let no_speech = matches!(e, SttError::NoSpeech);
             TranscriptionResult {
                 input: input.clone(),
                 transcription: None,
+                words: Vec::new(),
                 path: "".to_string(),
                 timestamp,
                 error: Some(e.to_string()),
+                is_final: true,
+                engine: String::new(),
+                used_fallback: false,
+                no_speech,
+                vad_timeline: None,
+                language: None,
             }
         },
     }
@@ -0,0 +1,94 @@
+use reqwest::Client;
+use anyhow::{Result, anyhow};
+use log::{debug, error, info};
+use crate::stt::{create_wav, WavSampleFormat, EngineOutput, SttEngine};
+use std::{future::Future, pin::Pin};
+
+use super::{http_client, send_with_retry, RetryPolicy, HOSTED_API_TIMEOUT};
+
+const OPENAI_TRANSCRIPTIONS_URL: &str = "https://api.openai.com/v1/audio/transcriptions";
+
+pub struct OpenAiWhisperEngine {
+    client: Client,
+    api_key: String,
+    language: Option<String>,
+    retry_policy: RetryPolicy,
+}
+
+impl OpenAiWhisperEngine {
+    pub fn new(api_key: String, language: Option<String>, retry_policy: RetryPolicy) -> Result<Self> {
+        let client = http_client(HOSTED_API_TIMEOUT)?;
+        Ok(Self { client, api_key, language, retry_policy })
+    }
+
+    async fn transcribe_with_openai(
+        &self,
+        wav_data: Vec<u8>,
+        device: &str,
+    ) -> Result<String> {
+        debug!("Sending request to OpenAI transcription API");
+        let response = send_with_retry(&self.retry_policy, || {
+            let mut form = reqwest::multipart::Form::new()
+                .text("model", "whisper-1")
+                .part(
+                    "file",
+                    reqwest::multipart::Part::bytes(wav_data.clone())
+                        .file_name("file.wav")
+                        .mime_str("audio/wav")?,
+                );
+            if let Some(language) = &self.language {
+                form = form.text("language", language.clone());
+            }
+            Ok(self
+                .client
+                .post(OPENAI_TRANSCRIPTIONS_URL)
+                .bearer_auth(&self.api_key)
+                .multipart(form))
+        })
+        .await
+        .map_err(|e| {
+            error!("Failed to send request to OpenAI API: {:?}", e);
+            anyhow!("Failed to send request to OpenAI API: {:?}", e)
+        })?;
+
+        let status = response.status();
+        let json: serde_json::Value = response.json().await?;
+        if !status.is_success() {
+            error!("OpenAI API error: HTTP {}, response: {:?}", status, json);
+            return Err(anyhow!("OpenAI API error: HTTP {}: {}", status, json["error"]["message"]));
+        }
+
+        let transcription = json["text"].as_str().unwrap_or("").to_string();
+        if transcription.is_empty() {
+            info!("device: {}, transcription is empty", device);
+        } else {
+            info!(
+                "device: {}, transcription successful. length: {} characters",
+                device,
+                transcription.len()
+            );
+        }
+
+        Ok(transcription)
+    }
+}
+
+impl SttEngine for OpenAiWhisperEngine {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        audio_data: &'a [f32],
+        sample_rate: u32,
+        channels: u16,
+        device_name: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        Box::pin(async move {
+            debug!("Starting OpenAI transcription for device: {}", device_name);
+            let wav_data = create_wav(audio_data, sample_rate, channels, WavSampleFormat::I16)?;
+            self.transcribe_with_openai(wav_data, device_name).await.map(EngineOutput::from)
+        })
+    }
+}
@@ -1,46 +1,145 @@
 use reqwest::Client;
 use anyhow::{Result, anyhow};
-use log::{debug, error, info};
-use crate::stt::{create_wav, resample, SttEngine};
-use std::{collections::HashMap, future::Future, pin::Pin};
+use log::{debug, error, info, warn};
+use crate::stt::{create_wav, encode_pcm, WavSampleFormat, resample_with_mode, ChannelMode, EngineOutput, ResampleQuality, SttEngine};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use super::{http_client, send_with_retry, RetryPolicy};
+
+/// Container of the audio sent to a RestPipe endpoint.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RestPipeUpload {
+    #[default]
+    Wav,
+    /// Headerless little-endian samples; the rate and channels are only in the content type
+    RawPcm,
+}
+
+/// e.g. `audio/pcm;rate=16000;channels=1;encoding=s16le`
+fn raw_pcm_content_type(sample_format: WavSampleFormat, sample_rate: u32, channels: u16) -> String {
+    let encoding = match sample_format {
+        WavSampleFormat::U8 => "u8",
+        WavSampleFormat::I16 => "s16le",
+        WavSampleFormat::I24 => "s24le",
+        WavSampleFormat::F32 => "f32le",
+    };
+    format!("audio/pcm;rate={};channels={};encoding={}", sample_rate, channels, encoding)
+}
+
+/// How audio is sent to a RestPipe endpoint and where the text is read from its response.
+#[derive(Clone, Debug)]
+pub struct RestPipeConfig {
+    /// Multipart field holding the WAV file; the WAV is the raw request body when `None`
+    pub payload_field: Option<String>,
+    /// Where the transcription is in a JSON response: a dotted path such as `result.transcript`
+    /// or a JSON pointer such as `/results/0/text`
+    pub response_field: String,
+    /// Rate the audio is resampled to before upload, one of `SUPPORTED_SAMPLE_RATES`; the
+    /// audio goes out at the rate it arrives in when `None`
+    pub resample_to_rate: Option<u32>,
+    pub channel_mode: ChannelMode,
+    /// Sent as `Authorization: Bearer <token>`
+    pub bearer_token: Option<String>,
+    /// Limit for a whole request, including reading the response
+    pub timeout: Duration,
+    pub upload: RestPipeUpload,
+    /// Sample encoding of the WAV or raw PCM upload. `F32` avoids a quantization round-trip
+    /// for backends that work on floats.
+    pub sample_format: WavSampleFormat,
+    /// Content type of raw PCM uploads; describes the samples when `None`
+    pub raw_content_type: Option<String>,
+}
+
+impl Default for RestPipeConfig {
+    fn default() -> Self {
+        Self {
+            payload_field: Some("file".to_string()),
+            response_field: "text".to_string(),
+            resample_to_rate: Some(16000),
+            channel_mode: ChannelMode::Downmix,
+            bearer_token: None,
+            timeout: Duration::from_secs(60),
+            upload: RestPipeUpload::Wav,
+            sample_format: WavSampleFormat::I16,
+            raw_content_type: None,
+        }
+    }
+}
+
+/// Rates `RestPipeConfig::resample_to_rate` accepts
+pub const SUPPORTED_SAMPLE_RATES: [u32; 5] = [8000, 16000, 22050, 44100, 48000];
 
 pub struct RestPipeEngine {
+    client: Client,
     url: String,
     headers: HashMap<String, String>,
-    payload_field: Option<String>,
-    resample_to_rate: Option<u32>,
+    config: RestPipeConfig,
+    /// Of the resampling to `RestPipeConfig::resample_to_rate`
+    resample_quality: ResampleQuality,
+    retry_policy: RetryPolicy,
+    warned_upsampling: AtomicBool,
 }
 
 impl RestPipeEngine {
-    pub fn new(url: String, headers: HashMap<String, String>, payload_field: Option<String>, resample_to_rate: Option<u32>) -> Self {
-        Self { url, headers, payload_field, resample_to_rate}
+    pub fn new(
+        url: String,
+        headers: HashMap<String, String>,
+        config: RestPipeConfig,
+        resample_quality: ResampleQuality,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        if let Some(rate) = config.resample_to_rate {
+            if !SUPPORTED_SAMPLE_RATES.contains(&rate) {
+                return Err(anyhow!(
+                    "unsupported RestPipe sample rate {} Hz, expected one of {:?}",
+                    rate,
+                    SUPPORTED_SAMPLE_RATES
+                ));
+            }
+        }
+        let client = http_client(config.timeout)?;
+        Ok(Self { client, url, headers, config, resample_quality, retry_policy, warned_upsampling: AtomicBool::new(false) })
     }
 
     async fn transcribe_with_restpipe(
-        wav_file: &[u8],
+        &self,
+        body: Vec<u8>,
+        content_type: &str,
         device: &str,
-        url: &str,
-        headers: &HashMap<String, String>,
-        payload_field: &Option<String>
     ) -> Result<String, anyhow::Error> {
-        let client = Client::new();
-        
-        debug!("Sending request to RestPipe API {}, with wav_file lenght: {}", url, wav_file.len());
-        let mut request = client.post(url);
-
-        if let Some(payload_field) = payload_field {
-            request = request.multipart(reqwest::multipart::Form::new()
-                .part(payload_field.to_owned(),
-                 reqwest::multipart::Part::bytes(wav_file.to_vec()).file_name("file.wav").mime_str("audio/wav")?));
-        } else {
-            request = request.body(wav_file.to_vec()).header("Content-Type", "audio/wav");
-        }
+        let (client, url, headers, config) = (&self.client, &self.url, &self.headers, &self.config);
+        let file_name = match config.upload {
+            RestPipeUpload::Wav => "file.wav",
+            RestPipeUpload::RawPcm => "file.pcm",
+        };
+        debug!("Sending request to RestPipe API {}, with {} bytes of {}", url, body.len(), content_type);
         for (key, value) in headers {
             debug!("Request header: {} = {}", key, value);
-            request = request.header(key, value);
         }
 
-        let response = request.send().await;
+        let response = send_with_retry(&self.retry_policy, || {
+            let mut request = client.post(url);
+            if let Some(payload_field) = &config.payload_field {
+                request = request.multipart(reqwest::multipart::Form::new()
+                    .part(payload_field.to_owned(),
+                     reqwest::multipart::Part::bytes(body.clone()).file_name(file_name).mime_str(content_type)?));
+            } else {
+                request = request.body(body.clone()).header("Content-Type", content_type);
+            }
+            for (key, value) in headers {
+                request = request.header(key, value);
+            }
+            if let Some(token) = &config.bearer_token {
+                request = request.bearer_auth(token);
+            }
+            Ok(request)
+        }).await;
 
         match response {
             Err(e) => {
@@ -59,9 +158,7 @@ impl RestPipeEngine {
                             if content_type.to_str().unwrap_or("").starts_with("application/json") {
                                 debug!("Response is JSON, parsing accordingly");
                                 let json: serde_json::Value = response.json().await?;
-                                // Assuming the transcription is in a "text" field, adjust as needed
-                                let transcription = json["text"].as_str().unwrap_or("").to_string();
-                                transcription
+                                response_text(&json, &config.response_field)?
                             } else {
                                 debug!("Response is not JSON, treating as plain text");
                                 response.text().await?
@@ -97,31 +194,65 @@ impl RestPipeEngine {
     }
 }
 
+/// Reads the string at `field`, a JSON pointer when it starts with `/` and a dotted path
+/// (with numeric segments indexing arrays) otherwise.
+fn response_text(json: &serde_json::Value, field: &str) -> Result<String> {
+    let value = if field.starts_with('/') {
+        json.pointer(field)
+    } else {
+        field.split('.').try_fold(json, |value, key| match key.parse::<usize>() {
+            Ok(index) if value.is_array() => value.get(index),
+            _ => value.get(key),
+        })
+    };
+    match value {
+        Some(serde_json::Value::String(text)) => Ok(text.clone()),
+        Some(other) => Err(anyhow!("RestPipe response field {:?} is not a string: {}", field, other)),
+        None => Err(anyhow!("RestPipe response has no field {:?}", field)),
+    }
+}
+
 impl SttEngine for RestPipeEngine {
+    fn name(&self) -> &'static str {
+        "restpipe"
+    }
+
     fn transcribe<'a>(
         &'a self,
         audio_data: &'a [f32],
         sample_rate: u32,
         channels: u16,
         device_name: &'a str,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
-        let mut new_channels = channels;
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
         Box::pin(async move {
             debug!("Starting RestPipe transcription for device: {}, incoming sample rate: {}", device_name, sample_rate);
-            let data = if self.resample_to_rate.is_some(){
-                if self.resample_to_rate.unwrap() != sample_rate {
-                    debug!("Resampling audio data from {} to {} Hz", sample_rate, self.resample_to_rate.unwrap());
-                    new_channels = 1; // the resumpled audio is mono
-                    resample(audio_data.to_vec(), channels, sample_rate, self.resample_to_rate.unwrap())?
-                } else {
-                    audio_data.to_vec()
+            let (data, new_sample_rate, new_channels) = match self.config.resample_to_rate {
+                Some(rate) if rate != sample_rate => {
+                    if rate > sample_rate && !self.warned_upsampling.swap(true, Ordering::Relaxed) {
+                        warn!(
+                            "RestPipe sample rate {} Hz is above the {} Hz input, upsampling only makes the uploads bigger",
+                            rate, sample_rate
+                        );
+                    }
+                    debug!("Resampling audio data from {} to {} Hz ({:?})", sample_rate, rate, self.config.channel_mode);
+                    let (data, new_channels) = resample_with_mode(audio_data.to_vec(), channels, sample_rate, rate, self.config.channel_mode, self.resample_quality)?;
+                    (data, rate, new_channels)
                 }
-            } else {
-                audio_data.to_vec()
+                _ => (audio_data.to_vec(), sample_rate, channels),
+            };
+            let (body, content_type) = match self.config.upload {
+                RestPipeUpload::Wav => (
+                    create_wav(&data, new_sample_rate, new_channels, self.config.sample_format)?,
+                    "audio/wav".to_string(),
+                ),
+                RestPipeUpload::RawPcm => (
+                    encode_pcm(&data, self.config.sample_format),
+                    self.config.raw_content_type.clone().unwrap_or_else(|| {
+                        raw_pcm_content_type(self.config.sample_format, new_sample_rate, new_channels)
+                    }),
+                ),
             };
-            let wav_data = create_wav(&data, sample_rate, new_channels, cpal::SampleFormat::I16)?;
-            Self::transcribe_with_restpipe(&wav_data, device_name, &self.url, 
-                &self.headers, &self.payload_field).await
+            self.transcribe_with_restpipe(body, &content_type, device_name).await.map(EngineOutput::from)
         })
     }}
 
@@ -0,0 +1,110 @@
+use std::{future::Future, path::Path, pin::Pin};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use tokio::sync::mpsc::UnboundedSender;
+use vosk::{DecodingState, Model, Recognizer};
+
+use crate::stt::{EngineOutput, SttEngine, Word};
+
+/// Samples fed to the recognizer at a time when streaming partials, 200ms at 16kHz
+const STREAMING_BLOCK: usize = 3200;
+
+/// Local engine backed by Vosk (Kaldi), light enough for a Raspberry Pi. Loads an unpacked model
+/// directory such as `vosk-model-small-en-us-0.15`; the language comes from the model.
+pub struct VoskEngine {
+    model: Model,
+}
+
+impl VoskEngine {
+    pub fn new(model_dir: &Path) -> Result<Self> {
+        info!("loading vosk model from {}", model_dir.display());
+        let model_dir = model_dir
+            .to_str()
+            .ok_or_else(|| anyhow!("model path is not valid utf-8: {:?}", model_dir))?;
+        let model = Model::new(model_dir).ok_or_else(|| anyhow!("failed to load vosk model from {}", model_dir))?;
+        Ok(Self { model })
+    }
+
+    /// Runs `audio_data` through a new recognizer, sending the partial text after every block
+    /// when `partials` is set.
+    fn recognize(
+        &self,
+        audio_data: &[f32],
+        sample_rate: u32,
+        device_name: &str,
+        partials: Option<&UnboundedSender<String>>,
+    ) -> Result<EngineOutput> {
+        let mut recognizer = Recognizer::new(&self.model, sample_rate as f32)
+            .ok_or_else(|| anyhow!("failed to create a vosk recognizer at {} Hz", sample_rate))?;
+        recognizer.set_words(true);
+
+        // Vosk takes 16-bit PCM
+        let samples: Vec<i16> = audio_data
+            .iter()
+            .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+            .collect();
+        debug!("device: {}, running vosk on {} samples", device_name, samples.len());
+
+        let block_len = if partials.is_some() { STREAMING_BLOCK } else { samples.len().max(1) };
+        for block in samples.chunks(block_len) {
+            let state = recognizer
+                .accept_waveform(block)
+                .map_err(|e| anyhow!("vosk failed to accept audio: {:?}", e))?;
+            if let (Some(partials), DecodingState::Running) = (partials, state) {
+                let partial = recognizer.partial_result().partial.trim().to_string();
+                if !partial.is_empty() {
+                    partials.send(partial).ok();
+                }
+            }
+        }
+
+        let result = recognizer.final_result();
+        let result = result
+            .single()
+            .ok_or_else(|| anyhow!("vosk returned alternatives instead of a single result"))?;
+        let words = result
+            .result
+            .iter()
+            .map(|word| Word {
+                text: word.word.to_string(),
+                start_ms: (word.start * 1000.0) as u64,
+                end_ms: (word.end * 1000.0) as u64,
+                confidence: word.conf,
+                speaker: None,
+            })
+            .collect();
+        Ok(EngineOutput {
+            text: result.text.trim().to_string(),
+            words,
+            ..Default::default()
+        })
+    }
+}
+
+impl SttEngine for VoskEngine {
+    fn name(&self) -> &'static str {
+        "vosk"
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        audio_data: &'a [f32],
+        sample_rate: u32,
+        _channels: u16,
+        device_name: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        Box::pin(async move { self.recognize(audio_data, sample_rate, device_name, None) })
+    }
+
+    fn transcribe_streaming<'a>(
+        &'a self,
+        audio_data: &'a [f32],
+        sample_rate: u32,
+        _channels: u16,
+        device_name: &'a str,
+        partials: UnboundedSender<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        Box::pin(async move { self.recognize(audio_data, sample_rate, device_name, Some(&partials)) })
+    }
+}
@@ -6,11 +6,11 @@ use std::{
 use anyhow::Result;
 #[cfg(target_os = "macos")]
 use objc::rc::autoreleasepool;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::mpsc::UnboundedReceiver;
 
 
 use crate::{
-    stt::RecordingState, vad_engine::VadEngineEnum, AudioInput, AudioTranscriptionEngine, TranscriptionResult
+    stt::{RecordingState, SttOptions, Task}, vad_engine::VadEngineEnum, AudioInputSender, AudioTranscriptionEngine, TranscriptionResult
 };
 
 
@@ -19,10 +19,13 @@ mod whisper_model;
 mod model;
 
 pub use whisper_engine::WhisperEngine;
-pub use whisper_model::WhisperModel;
-pub use model::{Model, token_id};
+pub use whisper_model::{
+    default_model_repo, download_model_files, download_repo_files, DevicePreference, WhisperModel,
+    DEFAULT_MODEL_REVISION,
+};
+pub use model::{token_id, HallucinationFilter, Model, WhisperDecodeParams, DEFAULT_HALLUCINATIONS};
 
-use super::create_comm_channel;
+use super::{create_comm_channel, SttEngineConfig};
 
 #[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
 pub enum CandleWhisperModel {
@@ -35,23 +38,32 @@ pub fn create_whisper_channel(
     vad_engine: VadEngineEnum,
     deepgram_api_key: Option<String>,
     output_path: &PathBuf,
+    task: Task,
 ) -> Result<(
-    UnboundedSender<AudioInput>,
+    AudioInputSender,
     UnboundedReceiver<TranscriptionResult>,
     Arc<AtomicBool>, // Shutdown flag
 )> {
-    let (primary_engine, fallback_engine) = super::initialize_stt_engines(
-        match (*audio_transcription_engine).clone() {
+    let (primary_engine, fallback_engines) = super::initialize_stt_engines(SttEngineConfig {
+        local_model: match (*audio_transcription_engine).clone() {
             AudioTranscriptionEngine::WhisperTiny => Some(CandleWhisperModel::Tiny),
             AudioTranscriptionEngine::WhisperDistilLargeV3 => Some(CandleWhisperModel::DistillLarge),
             _ => None,
         },
-        None,
-        None,
+        whisper_cpp_model: match audio_transcription_engine.as_ref() {
+            AudioTranscriptionEngine::WhisperCpp(model_path) => Some(model_path.clone()),
+            _ => None,
+        },
+        vosk_model: match audio_transcription_engine.as_ref() {
+            AudioTranscriptionEngine::Vosk(model_dir) => Some(model_dir.clone()),
+            _ => None,
+        },
         deepgram_api_key,
-    ).expect("Failed to initialize engines");
+        task,
+        ..Default::default()
+    }).expect("Failed to initialize engines");
 
-    let (sender, receiver, _, state_rx) = create_comm_channel(primary_engine, fallback_engine, vad_engine, &Some(output_path.to_owned()))?;
+    let (sender, receiver, _, state_rx) = create_comm_channel(primary_engine, fallback_engines, vad_engine, None, &Some(output_path.to_owned()), SttOptions::default())?;
 
     let shutdown_flag = Arc::new(AtomicBool::new(false));
     let shutdown_flag_clone = Arc::clone(&shutdown_flag);
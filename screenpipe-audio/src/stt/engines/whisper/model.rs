@@ -1,9 +1,9 @@
-use crate::stt::Task;
+use crate::stt::{Task, TranscriptSegment, Word};
 
 use anyhow::{Error as E, Result};
 use candle::{Device, IndexOp, Tensor};
 use candle_nn::ops::softmax;
-use log::{error, info};
+use log::{debug, error, info};
 #[cfg(target_os = "macos")]
 use objc::rc::autoreleasepool;
 use rand::{distributions::Distribution, SeedableRng};
@@ -11,7 +11,8 @@ use tokenizers::Tokenizer;
 
 use candle_transformers::models::whisper::{self as m, Config};
 
-
+/// Precedes the previous-context tokens the decoder conditions on
+const SOT_PREV_TOKEN: &str = "<|startofprev|>";
 
 #[derive(Debug, Clone)]
 pub enum Model {
@@ -57,6 +58,7 @@ impl Model {
 #[derive(Debug, Clone)]
 pub struct DecodingResult {
     tokens: Vec<u32>,
+    token_probs: Vec<f32>,
     pub text: String,
     avg_logprob: f64,
     no_speech_prob: f64,
@@ -72,6 +74,117 @@ pub struct Segment {
     pub dr: DecodingResult,
 }
 
+impl Segment {
+    pub fn transcript_segment(&self) -> TranscriptSegment {
+        TranscriptSegment {
+            text: self.dr.text.clone(),
+            start_ms: (self.start * 1000.) as u64,
+            end_ms: ((self.start + self.duration) * 1000.) as u64,
+            avg_logprob: self.dr.avg_logprob,
+            no_speech_prob: self.dr.no_speech_prob,
+        }
+    }
+}
+
+/// Phrases whisper tends to produce on silence or music, compared after lowercasing and
+/// stripping punctuation.
+pub const DEFAULT_HALLUCINATIONS: [&str; 10] = [
+    "thank you",
+    "thanks for watching",
+    "thank you for watching",
+    "please subscribe",
+    "subscribe to my channel",
+    "like and subscribe",
+    "bye",
+    "you",
+    "music",
+    "subtitles by the amara org community",
+];
+
+/// Drops decoded segments that are likely not speech: those with a high no-speech probability,
+/// and those made up only of known hallucination phrases.
+#[derive(Debug, Clone)]
+pub struct HallucinationFilter {
+    phrases: Vec<Vec<String>>,
+    max_no_speech_prob: f64,
+}
+
+impl Default for HallucinationFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_HALLUCINATIONS, 0.8)
+    }
+}
+
+impl HallucinationFilter {
+    /// `phrases` replace the default blocklist; segments whose no-speech probability is above
+    /// `max_no_speech_prob` are dropped whatever their text.
+    pub fn new<I, S>(phrases: I, max_no_speech_prob: f64) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            phrases: phrases
+                .into_iter()
+                .map(|p| normalized_words(p.as_ref()))
+                .filter(|p| !p.is_empty())
+                .collect(),
+            max_no_speech_prob,
+        }
+    }
+
+    pub fn is_hallucination(&self, text: &str, no_speech_prob: f64) -> bool {
+        if no_speech_prob > self.max_no_speech_prob {
+            return true;
+        }
+        let words = normalized_words(text);
+        !words.is_empty() && self.phrases.iter().any(|phrase| is_repetition_of(&words, phrase))
+    }
+}
+
+fn normalized_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(|w| w.trim_matches('\'').to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+/// Whether `words` is `phrase` said one or more times, e.g. "Thank you. Thank you."
+fn is_repetition_of(words: &[String], phrase: &[String]) -> bool {
+    words.len() % phrase.len() == 0 && words.chunks(phrase.len()).all(|chunk| chunk == phrase)
+}
+
+/// How whisper decodes each 30s window. The defaults are greedy decoding with the reference
+/// implementation's temperature fallback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WhisperDecodeParams {
+    /// Tried in order until a decoding passes both thresholds; 0 decodes greedily (or with
+    /// `beam_size` beams), higher values sample
+    pub temperatures: Vec<f64>,
+    /// Samples drawn at each temperature above 0, the one with the best average log-probability
+    /// is kept
+    pub best_of: usize,
+    /// Beams searched at temperature 0; 1 decodes greedily
+    pub beam_size: usize,
+    /// A decoding more compressible than this is treated as repetitive and retried at the next
+    /// temperature
+    pub compression_ratio_threshold: f64,
+    /// A decoding whose average log-probability is below this is retried at the next temperature
+    pub logprob_threshold: f64,
+}
+
+impl Default for WhisperDecodeParams {
+    fn default() -> Self {
+        Self {
+            temperatures: m::TEMPERATURES.to_vec(),
+            best_of: 1,
+            beam_size: 1,
+            compression_ratio_threshold: m::COMPRESSION_RATIO_THRESHOLD,
+            logprob_threshold: m::LOGPROB_THRESHOLD,
+        }
+    }
+}
+
 pub struct Decoder<'a> {
     model: &'a mut Model,
     rng: rand::rngs::StdRng,
@@ -87,6 +200,10 @@ pub struct Decoder<'a> {
     no_speech_token: u32,
     no_timestamps_token: u32,
     language_token: Option<u32>,
+    hallucination_filter: Option<&'a HallucinationFilter>,
+    params: WhisperDecodeParams,
+    /// `<|startofprev|>` and the initial prompt, empty without one
+    context_tokens: Vec<u32>,
 }
 
 impl<'a> Decoder<'a> {
@@ -140,17 +257,57 @@ impl<'a> Decoder<'a> {
             no_speech_token,
             language_token,
             no_timestamps_token,
+            hallucination_filter: None,
+            params: WhisperDecodeParams::default(),
+            context_tokens: Vec::new(),
         })
     }
 
-    fn decode(&mut self, mel: &Tensor, t: f64) -> Result<DecodingResult> {
-        let audio_features = self.model.encoder_forward(mel, true)?;
-        if self.verbose {
-            info!("audio features: {:?}", audio_features.dims());
+    /// Conditions every decoding on `prompt`, e.g. names and jargon the audio contains. The
+    /// prompt shares the text context with the decoded tokens, so only its last
+    /// `max_target_positions / 2 - 1` tokens are kept, as in the reference implementation.
+    pub fn with_initial_prompt(mut self, prompt: &str) -> Result<Self> {
+        let prompt = prompt.trim();
+        if prompt.is_empty() {
+            return Ok(self);
         }
-        let sample_len = self.model.config().max_target_positions / 2;
-        let mut no_speech_prob = f64::NAN;
-        let mut tokens = vec![self.sot_token];
+        let encoding = self
+            .tokenizer
+            .encode(format!(" {prompt}"), false)
+            .map_err(E::msg)?;
+        let prompt_tokens = encoding.get_ids();
+        let max_len = self.model.config().max_target_positions / 2 - 1;
+        if prompt_tokens.len() > max_len {
+            debug!(
+                "initial prompt is {} tokens, keeping the last {}",
+                prompt_tokens.len(),
+                max_len
+            );
+        }
+        let prompt_tokens = &prompt_tokens[prompt_tokens.len().saturating_sub(max_len)..];
+        self.context_tokens = std::iter::once(token_id(self.tokenizer, SOT_PREV_TOKEN)?)
+            .chain(prompt_tokens.iter().copied())
+            .collect();
+        Ok(self)
+    }
+
+    /// Replaces the default `WhisperDecodeParams`.
+    pub fn with_decode_params(mut self, params: WhisperDecodeParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Makes `run` skip the segments `filter` flags.
+    pub fn with_hallucination_filter(mut self, filter: &'a HallucinationFilter) -> Self {
+        self.hallucination_filter = Some(filter);
+        self
+    }
+
+    /// Initial prompt, start-of-transcript, language, task and timestamp tokens every decoding
+    /// starts from.
+    fn prompt_tokens(&self) -> Vec<u32> {
+        let mut tokens = self.context_tokens.clone();
+        tokens.push(self.sot_token);
         if let Some(language_token) = self.language_token {
             tokens.push(language_token);
         }
@@ -161,33 +318,56 @@ impl<'a> Decoder<'a> {
         if !self.timestamps {
             tokens.push(self.no_timestamps_token);
         }
+        tokens
+    }
 
+    /// Runs the decoder on `tokens` and returns the logits of the next token with the
+    /// suppressed tokens masked. `first` resets the cross-attention cache for new audio features
+    /// and also returns the no-speech probability.
+    fn step(&mut self, tokens: &[u32], audio_features: &Tensor, first: bool) -> Result<(Tensor, Option<f64>)> {
+        let tokens_t = Tensor::new(tokens, audio_features.device())?;
+        let tokens_t = tokens_t.unsqueeze(0)?;
+        let ys = self
+            .model
+            .decoder_forward(&tokens_t, audio_features, first)?;
+
+        let no_speech_prob = if first {
+            let logits = self.model.decoder_final_linear(&ys.i(..1)?)?.i(0)?.i(0)?;
+            Some(
+                softmax(&logits, 0)?
+                    .i(self.no_speech_token as usize)?
+                    .to_scalar::<f32>()? as f64,
+            )
+        } else {
+            None
+        };
+
+        let (_, seq_len, _) = ys.dims3()?;
+        let logits = self
+            .model
+            .decoder_final_linear(&ys.i((..1, seq_len - 1..))?)?
+            .i(0)?
+            .i(0)?;
+
+        Ok((logits.broadcast_add(&self.suppress_tokens)?, no_speech_prob))
+    }
+
+    fn decode(&mut self, audio_features: &Tensor, t: f64) -> Result<DecodingResult> {
+        let sample_len = self.model.config().max_target_positions / 2;
+        let mut no_speech_prob = f64::NAN;
+        let mut tokens = self.prompt_tokens();
+
+        // The prompt tokens were not sampled, keep them aligned with `tokens` at full probability
+        let mut token_probs = vec![1f32; tokens.len()];
         let mut sum_logprob = 0f64;
         let mut last_token_was_timestamp = false;
 
         for i in 0..sample_len {
-            let tokens_t = Tensor::new(tokens.as_slice(), mel.device())?;
-            let tokens_t = tokens_t.unsqueeze(0)?;
-            let ys = self
-                .model
-                .decoder_forward(&tokens_t, &audio_features, i == 0)?;
-
-            if i == 0 {
-                let logits = self.model.decoder_final_linear(&ys.i(..1)?)?.i(0)?.i(0)?;
-                no_speech_prob = softmax(&logits, 0)?
-                    .i(self.no_speech_token as usize)?
-                    .to_scalar::<f32>()? as f64;
+            let (logits, first_no_speech_prob) = self.step(&tokens, audio_features, i == 0)?;
+            if let Some(prob) = first_no_speech_prob {
+                no_speech_prob = prob;
             }
 
-            let (_, seq_len, _) = ys.dims3()?;
-            let logits = self
-                .model
-                .decoder_final_linear(&ys.i((..1, seq_len - 1..))?)?
-                .i(0)?
-                .i(0)?;
-
-            let logits = logits.broadcast_add(&self.suppress_tokens)?;
-
             let logits = if last_token_was_timestamp {
                 let mask = Tensor::zeros_like(&logits)?;
                 let eot_mask = mask.get(self.eot_token as usize)?;
@@ -215,6 +395,7 @@ impl<'a> Decoder<'a> {
                 .i(next_token as usize)?
                 .to_scalar::<f32>()? as f64;
 
+            token_probs.push(prob as f32);
             sum_logprob += prob.ln();
 
             if next_token == self.eot_token
@@ -226,11 +407,15 @@ impl<'a> Decoder<'a> {
             last_token_was_timestamp = next_token > self.no_timestamps_token;
         }
 
+        // The initial prompt isn't part of the transcription
+        tokens.drain(..self.context_tokens.len());
+        token_probs.drain(..self.context_tokens.len());
         let text = self.tokenizer.decode(&tokens, true).map_err(E::msg)?;
         let avg_logprob = sum_logprob / tokens.len() as f64;
 
         Ok(DecodingResult {
             tokens,
+            token_probs,
             text,
             avg_logprob,
             no_speech_prob,
@@ -239,16 +424,130 @@ impl<'a> Decoder<'a> {
         })
     }
 
+    /// Beam search at temperature 0: keeps the `beam_size` most likely sequences at every step
+    /// and returns the finished one with the best average log-probability.
+    fn decode_beam(&mut self, audio_features: &Tensor, beam_size: usize) -> Result<DecodingResult> {
+        struct Beam {
+            tokens: Vec<u32>,
+            token_probs: Vec<f32>,
+            sum_logprob: f64,
+        }
+
+        let sample_len = self.model.config().max_target_positions / 2;
+        let max_tokens = self.model.config().max_target_positions;
+        let tokens = self.prompt_tokens();
+        let (first_logits, no_speech_prob) = self.step(&tokens, audio_features, true)?;
+        let no_speech_prob = no_speech_prob.unwrap_or(f64::NAN);
+
+        let mut live = vec![Beam {
+            token_probs: vec![1f32; tokens.len()],
+            tokens,
+            sum_logprob: 0f64,
+        }];
+        let mut finished: Vec<Beam> = Vec::new();
+        let context_len = self.context_tokens.len();
+        let average =
+            |beam: &Beam| beam.sum_logprob / (beam.tokens.len() - context_len) as f64;
+        let mut first_logits = Some(first_logits);
+
+        for _ in 0..sample_len {
+            // (beam, token, sum of log-probs with the token, token probability)
+            let mut candidates: Vec<(usize, u32, f64, f32)> = Vec::new();
+            for (index, beam) in live.iter().enumerate() {
+                let logits = match first_logits.take() {
+                    Some(logits) => logits,
+                    None => self.step(&beam.tokens, audio_features, false)?.0,
+                };
+                let mut probs: Vec<(usize, f32)> = softmax(&logits, 0)?
+                    .to_vec1::<f32>()?
+                    .into_iter()
+                    .enumerate()
+                    .collect();
+                let top = beam_size.min(probs.len());
+                probs.select_nth_unstable_by(top - 1, |a, b| b.1.total_cmp(&a.1));
+                for &(token, prob) in &probs[..top] {
+                    if prob > 0f32 {
+                        candidates.push((index, token as u32, beam.sum_logprob + (prob as f64).ln(), prob));
+                    }
+                }
+            }
+            candidates.sort_unstable_by(|a, b| b.2.total_cmp(&a.2));
+
+            let mut next = Vec::new();
+            for (index, token, sum_logprob, prob) in candidates.into_iter().take(beam_size) {
+                let parent = &live[index];
+                let mut tokens = parent.tokens.clone();
+                tokens.push(token);
+                let mut token_probs = parent.token_probs.clone();
+                token_probs.push(prob);
+                let beam = Beam { tokens, token_probs, sum_logprob };
+                if token == self.eot_token || beam.tokens.len() > max_tokens {
+                    finished.push(beam);
+                } else {
+                    next.push(beam);
+                }
+            }
+            live = next;
+            if live.is_empty() || finished.len() >= beam_size {
+                break;
+            }
+        }
+
+        let mut best = finished
+            .into_iter()
+            .chain(live)
+            .max_by(|a, b| average(a).total_cmp(&average(b)))
+            .ok_or_else(|| anyhow::anyhow!("beam search produced no sequence"))?;
+
+        let avg_logprob = average(&best);
+        // The initial prompt isn't part of the transcription
+        best.tokens.drain(..context_len);
+        best.token_probs.drain(..context_len);
+        let text = self.tokenizer.decode(&best.tokens, true).map_err(E::msg)?;
+        Ok(DecodingResult {
+            avg_logprob,
+            tokens: best.tokens,
+            token_probs: best.token_probs,
+            text,
+            no_speech_prob,
+            temperature: 0f64,
+            compression_ratio: f64::NAN,
+        })
+    }
+
+    /// One decoding at temperature `t`: `best_of` samples above 0, beam search or greedy at 0.
+    fn decode_at(&mut self, audio_features: &Tensor, t: f64) -> Result<DecodingResult> {
+        if t > 0f64 {
+            let mut best = self.decode(audio_features, t)?;
+            for _ in 1..self.params.best_of {
+                let dr = self.decode(audio_features, t)?;
+                if dr.avg_logprob > best.avg_logprob {
+                    best = dr;
+                }
+            }
+            Ok(best)
+        } else if self.params.beam_size > 1 {
+            self.decode_beam(audio_features, self.params.beam_size)
+        } else {
+            self.decode(audio_features, t)
+        }
+    }
+
     fn decode_with_fallback(&mut self, segment: &Tensor) -> Result<DecodingResult> {
-        for (i, &t) in m::TEMPERATURES.iter().enumerate() {
-            let dr: Result<DecodingResult> = self.decode(segment, t);
-            if i == m::TEMPERATURES.len() - 1 {
+        let audio_features = self.model.encoder_forward(segment, true)?;
+        if self.verbose {
+            info!("audio features: {:?}", audio_features.dims());
+        }
+        let temperatures = self.params.temperatures.clone();
+        for (i, &t) in temperatures.iter().enumerate() {
+            let dr: Result<DecodingResult> = self.decode_at(&audio_features, t);
+            if i == temperatures.len() - 1 {
                 return dr;
             }
             match dr {
                 Ok(dr) => {
-                    let needs_fallback = dr.compression_ratio > m::COMPRESSION_RATIO_THRESHOLD
-                        || dr.avg_logprob < m::LOGPROB_THRESHOLD;
+                    let needs_fallback = dr.compression_ratio > self.params.compression_ratio_threshold
+                        || dr.avg_logprob < self.params.logprob_threshold;
                     if !needs_fallback || dr.no_speech_prob > m::NO_SPEECH_THRESHOLD {
                         return Ok(dr);
                     }
@@ -258,7 +557,8 @@ impl<'a> Decoder<'a> {
                 }
             }
         }
-        unreachable!()
+        // No temperatures configured
+        self.decode_at(&audio_features, 0f64)
     }
 
     pub fn run(&mut self, mel: &Tensor) -> Result<Vec<Segment>> {
@@ -273,10 +573,16 @@ impl<'a> Decoder<'a> {
             let segment_duration = (segment_size * m::HOP_LENGTH) as f64 / m::SAMPLE_RATE as f64;
             let dr = self.decode_with_fallback(&mel_segment)?;
             seek += segment_size;
-            if dr.no_speech_prob > m::NO_SPEECH_THRESHOLD && dr.avg_logprob < m::LOGPROB_THRESHOLD {
+            if dr.no_speech_prob > m::NO_SPEECH_THRESHOLD && dr.avg_logprob < self.params.logprob_threshold {
                 info!("no speech detected, skipping {seek} {dr:?}");
                 continue;
             }
+            if let Some(filter) = self.hallucination_filter {
+                if filter.is_hallucination(&dr.text, dr.no_speech_prob) {
+                    info!("likely hallucination, skipping {seek}: {:?}", dr.text);
+                    continue;
+                }
+            }
             let segment = Segment {
                 start: time_offset,
                 duration: segment_duration,
@@ -334,6 +640,82 @@ impl<'a> Decoder<'a> {
         }
         Ok(segments)
     }
+
+    /// Splits a decoded segment into words.
+    ///
+    /// Whisper only emits timestamps between phrases, so the words inside a timestamped span
+    /// get an even share of its duration. The confidence is the mean probability of the word's tokens.
+    pub fn segment_words(&self, segment: &Segment) -> Result<Vec<Word>> {
+        let mut words = Vec::new();
+        let mut span_start = 0f64;
+        let mut span: Vec<(u32, f32)> = vec![];
+        for (&token, &prob) in segment.dr.tokens.iter().zip(segment.dr.token_probs.iter()) {
+            if token > self.no_timestamps_token {
+                let timestamp_s = (token - self.no_timestamps_token + 1) as f64 / 50.;
+                self.push_span_words(
+                    &mut words,
+                    &span,
+                    segment.start + span_start,
+                    segment.start + timestamp_s,
+                )?;
+                span.clear();
+                span_start = timestamp_s;
+            } else if token < self.eot_token {
+                span.push((token, prob));
+            }
+        }
+        self.push_span_words(
+            &mut words,
+            &span,
+            segment.start + span_start,
+            segment.start + segment.duration,
+        )?;
+        Ok(words)
+    }
+
+    fn push_span_words(
+        &self,
+        words: &mut Vec<Word>,
+        span: &[(u32, f32)],
+        start_s: f64,
+        end_s: f64,
+    ) -> Result<()> {
+        if span.is_empty() {
+            return Ok(());
+        }
+        let token_duration = (end_s - start_s).max(0.) / span.len() as f64;
+
+        // A token decoding to leading whitespace starts a new word
+        let mut groups: Vec<Vec<usize>> = vec![];
+        for (i, &(token, _)) in span.iter().enumerate() {
+            let piece = self.tokenizer.decode(&[token], false).map_err(E::msg)?;
+            if groups.is_empty() || piece.starts_with(char::is_whitespace) {
+                groups.push(vec![i]);
+            } else if let Some(group) = groups.last_mut() {
+                group.push(i);
+            }
+        }
+
+        for group in groups {
+            let ids: Vec<u32> = group.iter().map(|&i| span[i].0).collect();
+            let text = self.tokenizer.decode(&ids, true).map_err(E::msg)?;
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let first = group[0];
+            let last = group[group.len() - 1];
+            let confidence = group.iter().map(|&i| span[i].1).sum::<f32>() / group.len() as f32;
+            words.push(Word {
+                text: text.to_string(),
+                start_ms: ((start_s + first as f64 * token_duration) * 1000.) as u64,
+                end_ms: ((start_s + (last + 1) as f64 * token_duration) * 1000.) as u64,
+                confidence,
+                speaker: None,
+            });
+        }
+        Ok(())
+    }
 }
 
 pub fn token_id(tokenizer: &Tokenizer, token: &str) -> candle::Result<u32> {
@@ -342,3 +724,29 @@ pub fn token_id(tokenizer: &Tokenizer, token: &str) -> candle::Result<u32> {
         Some(id) => Ok(id),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_repetition_of() {
+        let phrase = normalized_words("Thank you.");
+        assert!(is_repetition_of(&normalized_words("thank you"), &phrase));
+        assert!(is_repetition_of(&normalized_words("Thank you. Thank you!"), &phrase));
+        assert!(!is_repetition_of(&normalized_words("Thank you. Thank"), &phrase));
+        assert!(!is_repetition_of(&normalized_words("Thank you for coming"), &phrase));
+    }
+
+    #[test]
+    fn test_is_hallucination() {
+        let filter = HallucinationFilter::new(["Thanks for watching!"], 0.6);
+        assert!(filter.is_hallucination(" Thanks for watching.", 0.1));
+        assert!(filter.is_hallucination(" Thanks for watching. Thanks for watching.", 0.1));
+        // Confident it's silence, whatever the text
+        assert!(filter.is_hallucination(" Let's get started.", 0.7));
+        assert!(!filter.is_hallucination(" Let's get started.", 0.1));
+        assert!(!filter.is_hallucination(" Thanks for watching the demo.", 0.1));
+        assert!(!filter.is_hallucination("", 0.1));
+    }
+}
@@ -1,7 +1,6 @@
+use std::{future::Future, pin::Pin, sync::Arc};
 
-use std::{future::Future, pin::Pin};
-
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use candle::Tensor;
 use log::debug;
 #[cfg(target_os = "macos")]
@@ -10,19 +9,49 @@ use objc::rc::autoreleasepool;
 use candle_transformers::models::whisper::audio;
 
 use crate::{
-    multilingual, stt::{engines::whisper::model::Decoder, SttEngine, Task}
+    multilingual, stt::{engines::{take_transcription_permit, whisper::model::{Decoder, HallucinationFilter, WhisperDecodeParams}}, EngineOutput, SttEngine, Task}
 };
 
-
 use super::WhisperModel;
 
+/// Local whisper through candle. Inference runs on tokio's blocking thread pool, so a chunk
+/// that takes seconds to decode doesn't hold up the async tasks sharing the runtime (network
+/// engines, the server).
 pub struct WhisperEngine {
+    inner: Arc<WhisperInference>,
+}
+
+/// Everything `decode` needs, shared with the blocking task of each chunk. Every chunk decodes
+/// with its own clone of the model, so concurrent chunks don't share decoder state.
+#[derive(Clone)]
+struct WhisperInference {
     whisper_model: WhisperModel,
     mel_filters: Vec<f32>,
+    task: Task,
+    /// Language code to decode with; detected per chunk when `None`
+    language: Option<String>,
+    hallucination_filter: HallucinationFilter,
+    decode_params: WhisperDecodeParams,
+    /// Text every chunk is decoded after, e.g. names and jargon the audio contains
+    initial_prompt: Option<String>,
 }
 
 impl WhisperEngine {
-    pub fn new(whisper_model: WhisperModel) -> Result<Self, anyhow::Error> {
+    pub fn new(
+        whisper_model: WhisperModel,
+        task: Task,
+        language: Option<String>,
+        decode_params: WhisperDecodeParams,
+        initial_prompt: Option<String>,
+    ) -> Result<Self, anyhow::Error> {
+        if decode_params.best_of == 0 || decode_params.beam_size == 0 {
+            anyhow::bail!("whisper best_of and beam_size must be at least 1");
+        }
+        if let Some(code) = &language {
+            if !multilingual::is_supported_language(code) {
+                anyhow::bail!("unsupported whisper language code: {code}");
+            }
+        }
         let model = &whisper_model.model;
         
         debug!("Loading mel filters");
@@ -35,66 +64,130 @@ impl WhisperEngine {
         <byteorder::LittleEndian as byteorder::ByteOrder>::read_f32_into(mel_bytes, &mut mel_filters);
 
         Ok(Self {
-            whisper_model,
-            mel_filters,
+            inner: Arc::new(WhisperInference {
+                whisper_model,
+                mel_filters,
+                task,
+                language,
+                hallucination_filter: HallucinationFilter::default(),
+                decode_params,
+                initial_prompt,
+            }),
+        })
+    }
+
+    /// Replaces the default `HallucinationFilter` applied to every decoded segment.
+    pub fn with_hallucination_filter(mut self, filter: HallucinationFilter) -> Self {
+        // Not shared until the engine transcribes, so this doesn't copy the model
+        Arc::make_mut(&mut self.inner).hallucination_filter = filter;
+        self
+    }
+}
+
+impl WhisperInference {
+    /// Whisper inference, entirely synchronous: candle's Metal backend allocates autoreleased
+    /// Objective-C objects (command buffers, encoders) on every op, and they're only freed when
+    /// the enclosing pool drains.
+    fn decode(&self, audio_data: &[f32], device_name: &str) -> Result<EngineOutput> {
+        let model = &self.whisper_model.model;
+        let tokenizer = &self.whisper_model.tokenizer;
+        let device = &self.whisper_model.device;
+
+        debug!("device: {}, converting pcm to mel spectrogram", device_name);
+        let mel = audio::pcm_to_mel(model.config(), audio_data, &self.mel_filters);
+        let mel_len = mel.len();
+        debug!("device: {}, creating tensor from mel spectrogram", device_name);
+        let mel = Tensor::from_vec(
+            mel,
+            (
+                1,
+                model.config().num_mel_bins,
+                mel_len / model.config().num_mel_bins,
+            ),
+            device,
+        )?;
+
+        // Translation also needs the source language, so detection runs for both tasks
+        let (language_token, language) = match &self.language {
+            Some(code) => (multilingual::language_token(tokenizer, code)?, code.clone()),
+            None => {
+                debug!("device: {}, detecting language", device_name);
+                let (token, code) = multilingual::detect_language(&mut model.clone(), tokenizer, &mel)?;
+                (token, code.to_string())
+            }
+        };
+        let language_token = Some(language_token);
+        let mut model = model.clone();
+        debug!("device: {}, initializing decoder", device_name);
+        let mut dc = Decoder::new(
+            &mut model,
+            tokenizer,
+            42,
+            device,
+            language_token,
+            Some(self.task),
+            true,
+            false,
+        )?
+        .with_hallucination_filter(&self.hallucination_filter)
+        .with_decode_params(self.decode_params.clone());
+        if let Some(prompt) = &self.initial_prompt {
+            dc = dc.with_initial_prompt(prompt)?;
+        }
+        debug!("device: {}, starting decoding process", device_name);
+        let segments = dc.run(&mel)?;
+        debug!("device: {}, decoding complete", device_name);
+        let mut words = Vec::new();
+        for segment in segments.iter() {
+            words.extend(dc.segment_words(segment)?);
+        }
+        Ok(EngineOutput {
+            text: segments
+                .iter()
+                .map(|s| s.dr.text.clone())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            words,
+            segments: segments.iter().map(|s| s.transcript_segment()).collect(),
+            language: Some(language),
         })
     }
 }
 
 impl SttEngine for WhisperEngine {
+    fn name(&self) -> &'static str {
+        "whisper"
+    }
+
     fn transcribe<'a>(
         &'a self,
         audio_data: &'a [f32],
         _sample_rate: u32,
         _channels: u16,
         device_name: &'a str,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        let inner = self.inner.clone();
+        let audio_data = audio_data.to_vec();
+        let device_name = device_name.to_string();
         Box::pin(async move {
-            let model = &self.whisper_model.model;
-            let tokenizer = &self.whisper_model.tokenizer;
-            let device = &self.whisper_model.device;
+            // A chunk abandoned on timeout or stop keeps its blocking thread until the decode
+            // finishes, and its permit with it so the next chunk doesn't decode alongside
+            let permit = take_transcription_permit();
+            tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                // The pool wraps only the synchronous decode, with no await inside, so everything
+                // autoreleased during inference is freed when the chunk is done instead of piling
+                // up in the thread's outer pool. To check for leaks on macOS, record for a few
+                // minutes with `--local-model` and compare `footprint <pid>` or Instruments'
+                // Allocations over time: memory should plateau after the first chunks.
+                #[cfg(target_os = "macos")]
+                return autoreleasepool(|| inner.decode(&audio_data, &device_name));
 
-
-            debug!("device: {}, converting pcm to mel spectrogram", device_name);
-            let mel = audio::pcm_to_mel(model.config(), audio_data, &self.mel_filters);
-            let mel_len = mel.len();
-            debug!("device: {}, creating tensor from mel spectrogram", device_name);
-            let mel = Tensor::from_vec(
-                mel,
-                (
-                    1,
-                    model.config().num_mel_bins,
-                    mel_len / model.config().num_mel_bins,
-                ),
-                device,
-            )?;
-
-            debug!("device: {}, detecting language", device_name);
-            let language_token = Some(multilingual::detect_language(
-                &mut model.clone(),
-                tokenizer,
-                &mel,
-            )?);
-            let mut model = model.clone();
-            debug!("device: {}, initializing decoder", device_name);
-            let mut dc = Decoder::new(
-                &mut model,
-                tokenizer,
-                42,
-                device,
-                language_token,
-                Some(Task::Transcribe),
-                true,
-                false,
-            )?;
-            debug!("device: {}, starting decoding process", device_name);
-            let segments = dc.run(&mel)?;
-            debug!("device: {}, decoding complete", device_name);
-            Ok(segments
-                .iter()
-                .map(|s| s.dr.text.clone())
-                .collect::<Vec<String>>()
-                .join("\n"))
+                #[cfg(not(target_os = "macos"))]
+                inner.decode(&audio_data, &device_name)
+            })
+            .await
+            .map_err(|e| anyhow!("whisper inference task failed: {}", e))?
         })
     }
-}
\ No newline at end of file
+}
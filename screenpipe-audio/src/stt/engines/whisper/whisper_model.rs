@@ -1,10 +1,12 @@
 
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 
 use anyhow::{Error as E, Result};
 use candle::Device;
 use candle_nn::VarBuilder;
-use hf_hub::{api::sync::Api, Repo, RepoType};
+use hf_hub::{api::sync::ApiBuilder, Cache, Repo, RepoType};
 use log::{debug, info};
 #[cfg(target_os = "macos")]
 use objc::rc::autoreleasepool;
@@ -16,6 +18,103 @@ use crate::AudioTranscriptionEngine;
 
 use super::Model;
 
+/// Where whisper inference runs. `Auto` tries Metal, then CUDA, then falls back to CPU.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DevicePreference {
+    #[default]
+    Auto,
+    Cpu,
+    Cuda(usize),
+    Metal(usize),
+}
+
+impl DevicePreference {
+    fn device(self) -> Result<Device> {
+        Ok(match self {
+            DevicePreference::Auto => {
+                Device::new_metal(0).unwrap_or(Device::new_cuda(0).unwrap_or(Device::Cpu))
+            }
+            DevicePreference::Cpu => Device::Cpu,
+            DevicePreference::Cuda(ordinal) => Device::new_cuda(ordinal)?,
+            DevicePreference::Metal(ordinal) => Device::new_metal(ordinal)?,
+        })
+    }
+}
+
+impl FromStr for DevicePreference {
+    type Err = anyhow::Error;
+
+    /// Parses `auto`, `cpu`, `cuda`, `cuda:<n>`, `metal` or `metal:<n>`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, ordinal) = match s.split_once(':') {
+            Some((kind, ordinal)) => (kind, Some(ordinal.parse::<usize>()?)),
+            None => (s, None),
+        };
+        match (kind.to_lowercase().as_str(), ordinal) {
+            ("auto", None) => Ok(DevicePreference::Auto),
+            ("cpu", None) => Ok(DevicePreference::Cpu),
+            ("cuda", ordinal) => Ok(DevicePreference::Cuda(ordinal.unwrap_or(0))),
+            ("metal", ordinal) => Ok(DevicePreference::Metal(ordinal.unwrap_or(0))),
+            _ => Err(anyhow::anyhow!("unknown compute device: {}", s)),
+        }
+    }
+}
+
+/// Revision the built-in models are downloaded at
+pub const DEFAULT_MODEL_REVISION: &str = "main";
+
+/// Hugging Face repo with the candle weights of `engine`
+pub fn default_model_repo(engine: &AudioTranscriptionEngine) -> &'static str {
+    match engine {
+        AudioTranscriptionEngine::WhisperDistilLargeV3 => "distil-whisper/distil-large-v3",
+        _ => "openai/whisper-tiny",
+    }
+}
+
+/// Fetches `config.json`, `tokenizer.json` and `model.safetensors` of `engine` into `cache_dir`
+/// (see `download_repo_files`).
+pub fn download_model_files(
+    engine: &AudioTranscriptionEngine,
+    cache_dir: Option<&Path>,
+) -> Result<(PathBuf, PathBuf, PathBuf)> {
+    download_repo_files(default_model_repo(engine), DEFAULT_MODEL_REVISION, cache_dir)
+}
+
+/// Fetches `config.json`, `tokenizer.json` and `model.safetensors` of the HF repo `repo_id` at
+/// `revision` (a branch, tag or commit) into `cache_dir`, the hf-hub cache (`$HF_HOME/hub`,
+/// `~/.cache/huggingface/hub`) when `None`, and returns their paths. Files already cached aren't
+/// downloaded again; the others are logged and show a progress bar on the terminal while
+/// downloading.
+pub fn download_repo_files(
+    repo_id: &str,
+    revision: &str,
+    cache_dir: Option<&Path>,
+) -> Result<(PathBuf, PathBuf, PathBuf)> {
+    let cache = match cache_dir {
+        Some(dir) => Cache::new(dir.to_path_buf()),
+        None => Cache::default(),
+    };
+    let repo = Repo::with_revision(repo_id.to_string(), RepoType::Model, revision.to_string());
+    let cached = cache.repo(repo.clone());
+    let api = ApiBuilder::new()
+        .with_cache_dir(cache.path().clone())
+        .with_progress(true)
+        .build()?;
+    let api_repo = api.repo(repo.clone());
+
+    let fetch = |filename: &str| -> Result<PathBuf> {
+        if let Some(path) = cached.get(filename) {
+            debug!("{} of {} is cached at {:?}", filename, repo.url(), path);
+            return Ok(path);
+        }
+        info!("Downloading {} of {} into {:?}", filename, repo.url(), cache.path());
+        let path = api_repo.get(filename)?;
+        info!("Downloaded {:?}", path);
+        Ok(path)
+    };
+    Ok((fetch("config.json")?, fetch("tokenizer.json")?, fetch("model.safetensors")?))
+}
+
 #[derive(Clone)]
 pub struct WhisperModel {
     pub model: Model,
@@ -24,41 +123,70 @@ pub struct WhisperModel {
 }
 
 impl WhisperModel {
-    pub fn new(engine: Arc<AudioTranscriptionEngine>) -> Result<Self> {
-        debug!("Initializing WhisperModel");
-        let device = Device::new_metal(0).unwrap_or(Device::new_cuda(0).unwrap_or(Device::Cpu));
+    /// Loads the weights of `engine` from the HF hub, cached in `cache_dir` (see
+    /// `download_model_files`).
+    pub fn new(
+        engine: Arc<AudioTranscriptionEngine>,
+        device: DevicePreference,
+        cache_dir: Option<&Path>,
+    ) -> Result<Self> {
+        Self::from_repo(
+            default_model_repo(&engine).to_string(),
+            DEFAULT_MODEL_REVISION.to_string(),
+            device,
+            cache_dir,
+        )
+    }
+
+    /// Loads the weights of any whisper repo on the HF hub in the transformers layout, e.g. a
+    /// fine-tune or a pinned commit of a built-in model, cached in `cache_dir` (see
+    /// `download_repo_files`).
+    pub fn from_repo(
+        repo_id: String,
+        revision: String,
+        device: DevicePreference,
+        cache_dir: Option<&Path>,
+    ) -> Result<Self> {
+        debug!("Initializing WhisperModel from {}@{}", repo_id, revision);
+        let device = device.device()?;
         info!("device = {:?}", device);
 
         debug!("Fetching model files");
-        let (config_filename, tokenizer_filename, weights_filename) = {
-            let api = Api::new()?;
-            let repo = match engine.as_ref() {
-                AudioTranscriptionEngine::WhisperTiny => Repo::with_revision(
-                    "openai/whisper-tiny".to_string(),
-                    RepoType::Model,
-                    "main".to_string(),
-                ),
-                AudioTranscriptionEngine::WhisperDistilLargeV3 => Repo::with_revision(
-                    "distil-whisper/distil-large-v3".to_string(),
-                    RepoType::Model,
-                    "main".to_string(),
-                ),
-                _ => Repo::with_revision(
-                    "openai/whisper-tiny".to_string(),
-                    RepoType::Model,
-                    "main".to_string(),
-                ),
-                // ... other engine options ...
-            };
-            let api_repo = api.repo(repo);
-            let config = api_repo.get("config.json")?;
-            let tokenizer = api_repo.get("tokenizer.json")?;
-            let model = api_repo.get("model.safetensors")?;
-            (config, tokenizer, model)
-        };
+        let (config_filename, tokenizer_filename, weights_filename) =
+            download_repo_files(&repo_id, &revision, cache_dir)?;
+
+        Self::load(&config_filename, &tokenizer_filename, &weights_filename, device)
+            .map_err(|e| e.context(format!("failed to load whisper model {}@{}", repo_id, revision)))
+    }
+
+    /// Loads a model from files on disk, without going through the HF hub.
+    pub fn from_files(config: PathBuf, tokenizer: PathBuf, weights: PathBuf, device: DevicePreference) -> Result<Self> {
+        debug!("Initializing WhisperModel from {:?}", weights);
+        let device = device.device()?;
+        info!("device = {:?}", device);
+        Self::load(&config, &tokenizer, &weights, device)
+    }
+
+    /// Loads `config.json`, `tokenizer.json` and `model.safetensors` from `dir`.
+    pub fn from_dir(dir: &Path, device: DevicePreference) -> Result<Self> {
+        Self::from_files(
+            dir.join("config.json"),
+            dir.join("tokenizer.json"),
+            dir.join("model.safetensors"),
+            device,
+        )
+    }
 
+    fn load(config_filename: &Path, tokenizer_filename: &Path, weights_filename: &Path, device: Device) -> Result<Self> {
         debug!("Parsing config and tokenizer");
         let config: Config = serde_json::from_str(&std::fs::read_to_string(config_filename)?)?;
+        // WhisperEngine only bundles mel filters for these sizes
+        if !matches!(config.num_mel_bins, 80 | 128) {
+            anyhow::bail!(
+                "unsupported num_mel_bins {}, only the 80 and 128 bin mel filters are bundled",
+                config.num_mel_bins
+            );
+        }
         let tokenizer = Tokenizer::from_file(tokenizer_filename).map_err(E::msg)?;
 
         debug!("Loading model weights");
@@ -0,0 +1,92 @@
+use std::{future::Future, path::Path, pin::Pin};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+use crate::stt::{EngineOutput, SttEngine, Task};
+
+/// Local engine backed by whisper.cpp, loading a GGML `.bin` model from disk.
+/// Expects the same 16kHz mono input as `WhisperEngine`.
+pub struct WhisperCppEngine {
+    context: WhisperContext,
+    task: Task,
+    language: Option<String>,
+}
+
+impl WhisperCppEngine {
+    pub fn new(model_path: &Path, task: Task, language: Option<String>) -> Result<Self> {
+        info!("loading whisper.cpp model from {}", model_path.display());
+        let model_path = model_path
+            .to_str()
+            .ok_or_else(|| anyhow!("model path is not valid utf-8: {:?}", model_path))?;
+        let context = WhisperContext::new_with_params(model_path, WhisperContextParameters::default())
+            .map_err(|e| anyhow!("failed to load whisper.cpp model: {:?}", e))?;
+        Ok(Self {
+            context,
+            task,
+            language,
+        })
+    }
+}
+
+impl SttEngine for WhisperCppEngine {
+    fn name(&self) -> &'static str {
+        "whisper-cpp"
+    }
+
+    fn transcribe<'a>(
+        &'a self,
+        audio_data: &'a [f32],
+        _sample_rate: u32,
+        _channels: u16,
+        device_name: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut state = self
+                .context
+                .create_state()
+                .map_err(|e| anyhow!("failed to create whisper.cpp state: {:?}", e))?;
+
+            let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+            params.set_translate(self.task == Task::Translate);
+            params.set_language(Some(self.language.as_deref().unwrap_or("auto")));
+            params.set_print_special(false);
+            params.set_print_progress(false);
+            params.set_print_realtime(false);
+            params.set_print_timestamps(false);
+
+            debug!("device: {}, running whisper.cpp on {} samples", device_name, audio_data.len());
+            state
+                .full(params, audio_data)
+                .map_err(|e| anyhow!("whisper.cpp inference failed: {:?}", e))?;
+
+            let num_segments = state
+                .full_n_segments()
+                .map_err(|e| anyhow!("failed to read whisper.cpp segments: {:?}", e))?;
+            let mut text = String::new();
+            for i in 0..num_segments {
+                let segment = state
+                    .full_get_segment_text(i)
+                    .map_err(|e| anyhow!("failed to read whisper.cpp segment {}: {:?}", i, e))?;
+                text.push_str(&segment);
+            }
+
+            // The configured language, or the one whisper.cpp detected with "auto"
+            let language = match &self.language {
+                Some(code) => Some(code.clone()),
+                None => state
+                    .full_lang_id_from_state()
+                    .ok()
+                    .and_then(whisper_rs::get_lang_str)
+                    .map(str::to_string),
+            };
+
+            Ok(EngineOutput {
+                text: text.trim().to_string(),
+                language,
+                ..Default::default()
+            })
+        })
+    }
+}
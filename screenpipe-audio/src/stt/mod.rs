@@ -1,11 +1,12 @@
-use std::{future::Future, io::Cursor, path::PathBuf, pin::Pin};
+use std::{future::Future, io::Cursor, path::PathBuf, pin::Pin, time::Duration};
 
 use thiserror::Error;
 use anyhow::{Result, anyhow};
 use chrono::Utc;
-use cpal::SampleFormat;
 use hound::{WavSpec, WavWriter};
 use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
 #[cfg(target_os = "macos")]
 use objc::rc::autoreleasepool;
 
@@ -15,40 +16,260 @@ use rubato::{
 };
 
 use crate::{
+    audio_queue::DropPolicy,
     encode_single_audio,
-    vad_engine::VadEngine,
+    encode::{AudioFormat, FfmpegNotFound, FfmpegOptions},
+    preprocess::{Normalization, SpeechFilter},
+    vad_engine::{VadEngine, VadTimeline},
 };
 
 
 pub mod engines;
 pub trait SttEngine {
-    fn transcribe<'a>(&'a self, audio_data: &'a [f32], sample_rate: u32, channels: u16, device_name: &'a str) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+    /// Short name identifying the engine in results, e.g. "deepgram"
+    fn name(&self) -> &'static str;
+
+    fn transcribe<'a>(&'a self, audio_data: &'a [f32], sample_rate: u32, channels: u16, device_name: &'a str) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>>;
+
+    /// Like `transcribe`, but also sends interim text on `partials` while the audio is being
+    /// recognized. Each partial replaces the previous one. Engines without streaming support
+    /// only return the final result.
+    fn transcribe_streaming<'a>(
+        &'a self,
+        audio_data: &'a [f32],
+        sample_rate: u32,
+        channels: u16,
+        device_name: &'a str,
+        _partials: UnboundedSender<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<EngineOutput>> + Send + 'a>> {
+        self.transcribe(audio_data, sample_rate, channels, device_name)
+    }
+}
+
+/// A single recognized word. Times are in milliseconds from the start of the audio chunk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Word {
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub confidence: f32,
+    /// Speaker label from engines that diarize (Deepgram with `diarize=true`); `None` otherwise.
+    pub speaker: Option<u32>,
+}
+
+/// A decoded segment with whisper's quality estimates. Times are in milliseconds from the start
+/// of the audio chunk.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TranscriptSegment {
+    pub text: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    /// Mean log-probability of the decoded tokens
+    pub avg_logprob: f64,
+    /// Probability the model gave to the segment containing no speech
+    pub no_speech_prob: f64,
+}
+
+impl TranscriptSegment {
+    /// Mean token probability of the segment, in `0.0..=1.0`.
+    pub fn confidence(&self) -> f32 {
+        self.avg_logprob.exp().clamp(0.0, 1.0) as f32
+    }
+}
+
+/// What an `SttEngine` returns for a piece of audio. Engines that can't provide
+/// word timing leave `words` empty, and only the local whisper engine fills `segments`.
+#[derive(Debug, Clone, Default)]
+pub struct EngineOutput {
+    pub text: String,
+    pub words: Vec<Word>,
+    pub segments: Vec<TranscriptSegment>,
+    /// Language code of the speech, e.g. `en`, from engines that detect or report it
+    pub language: Option<String>,
 }
 
+impl From<String> for EngineOutput {
+    fn from(text: String) -> Self {
+        Self { text, ..Default::default() }
+    }
+}
+
+impl EngineOutput {
+    /// Drops the segments whose confidence is below `min_confidence`, along with their words,
+    /// and rebuilds the text from the remaining ones. Outputs without segments are left as is.
+    fn retain_confident_segments(&mut self, min_confidence: f32) {
+        if self.segments.is_empty() {
+            return;
+        }
+        let (kept, dropped): (Vec<_>, Vec<_>) = std::mem::take(&mut self.segments)
+            .into_iter()
+            .partition(|segment| segment.confidence() >= min_confidence);
+        if dropped.is_empty() {
+            self.segments = kept;
+            return;
+        }
+        for segment in &dropped {
+            debug!("dropping segment with confidence {:.2}: {}", segment.confidence(), segment.text);
+        }
+        self.words.retain(|word| {
+            !dropped
+                .iter()
+                .any(|s| word.start_ms >= s.start_ms && word.end_ms <= s.end_ms)
+        });
+        self.text = kept.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join("\n");
+        self.segments = kept;
+    }
+}
+
+/// Result of `perform_stt`: the transcription, its words and the path of the saved recording, if any.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptionOutput {
+    pub text: String,
+    pub words: Vec<Word>,
+    pub segments: Vec<TranscriptSegment>,
+    pub path: Option<String>,
+    /// `SttEngine::name` of the engine that produced the text
+    pub engine: String,
+    /// Whether the primary engine failed and a fallback engine produced the text
+    pub used_fallback: bool,
+    /// Where VAD heard voice, with `SttOptions::vad_timeline`
+    pub vad_timeline: Option<VadTimeline>,
+    /// `EngineOutput::language` of the engine that produced the text
+    pub language: Option<String>,
+}
+
+/// Why `perform_stt` produced no transcription.
 #[derive(Error, Debug)]
-enum SttErrorKind {
+pub enum SttError {
+    /// VAD found no speech in the audio, no engine was called
     #[error("No speech detected in the audio")]
     NoSpeech,
+    #[error("failed to resample the audio: {0}")]
+    ResampleFailed(#[source] anyhow::Error),
+    /// Every engine failed or timed out; `engine` is the last one tried, the last fallback
+    /// when there are any, the primary otherwise
+    #[error("{engine} engine failed: {source}")]
+    EngineFailed {
+        engine: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// The transcription succeeded but the recording couldn't be saved to `output_path`
+    #[error("failed to encode the recording: {0}")]
+    EncodeFailed(#[source] anyhow::Error),
+}
+
+/// Whether `perform_stt` failed only because VAD found no speech in the audio, for errors that
+/// went through `anyhow`.
+pub fn is_no_speech(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<SttError>(), Some(SttError::NoSpeech))
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Task {
+/// Whisper decoding task. `Translate` outputs English regardless of the spoken language.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Task {
+    #[default]
     Transcribe,
-    #[allow(dead_code)]
     Translate,
 }
 
-// Main STT function
+/// Tuning for `perform_stt`, passed once to `create_comm_channel`.
+#[derive(Debug, Clone)]
+pub struct SttOptions {
+    /// Audio kept before and after every run of voice frames so word edges aren't clipped by VAD.
+    pub speech_padding: Duration,
+    /// Emit interim `TranscriptionResult`s (`is_final: false`) from engines that stream.
+    pub partials: bool,
+    /// Format of the recordings saved to `output_path`
+    pub output_format: AudioFormat,
+    /// Bitrate for lossy recording formats; `None` keeps the 64 kbps default
+    pub output_bitrate_kbps: Option<u32>,
+    /// ffmpeg binary and extra arguments the recordings are encoded with
+    pub ffmpeg: FfmpegOptions,
+    /// When set, the resampled and the VAD-filtered 16kHz audio of every chunk are saved here as WAV
+    pub debug_audio_dir: Option<PathBuf>,
+    /// Segments whose `TranscriptSegment::confidence` is below this are dropped from the
+    /// transcription. Only applies to engines that report segments.
+    pub min_confidence: Option<f32>,
+    /// Longest a single engine call may take; a call that runs over counts as a failed attempt
+    /// and falls through to the next fallback engine
+    pub transcribe_timeout: Option<Duration>,
+    /// Chunks `create_comm_channel` transcribes at the same time. Chunks of one device are
+    /// always transcribed in order, so this only helps with several devices. Each concurrent
+    /// local whisper call works on its own copy of the decoder state, so GPU memory use grows
+    /// with this value; keep it at 1 unless the model fits several times.
+    pub max_concurrent_transcriptions: usize,
+    /// Recorded chunks waiting for transcription before `drop_policy` kicks in
+    pub input_queue_capacity: usize,
+    pub drop_policy: DropPolicy,
+    /// Finish the recording once every device has sent this much audio in a row without voice
+    /// frames. `None` finishes it on the first chunk without speech.
+    pub max_silence: Option<Duration>,
+    /// Report the per-frame VAD decisions of every chunk in `TranscriptionOutput::vad_timeline`
+    pub vad_timeline: bool,
+    /// Boost quiet chunks before VAD and STT. Runs on the recorded audio, before resampling;
+    /// saved recordings keep the original level.
+    pub normalize: Option<Normalization>,
+    /// High-pass and low-pass filtering of the resampled audio, before VAD
+    pub filter: SpeechFilter,
+    /// Resampler used to bring recordings to 16kHz
+    pub resample_quality: ResampleQuality,
+}
+
+impl Default for SttOptions {
+    fn default() -> Self {
+        Self {
+            speech_padding: Duration::from_millis(200),
+            partials: false,
+            output_format: AudioFormat::default(),
+            output_bitrate_kbps: None,
+            ffmpeg: FfmpegOptions::default(),
+            debug_audio_dir: None,
+            min_confidence: None,
+            transcribe_timeout: None,
+            max_concurrent_transcriptions: 1,
+            input_queue_capacity: 32,
+            drop_policy: DropPolicy::Block,
+            max_silence: None,
+            vad_timeline: false,
+            normalize: None,
+            filter: SpeechFilter::default(),
+            resample_quality: ResampleQuality::default(),
+        }
+    }
+}
+
+async fn with_timeout<T>(limit: Option<Duration>, future: impl Future<Output = Result<T>>) -> Result<T> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, future)
+            .await
+            .map_err(|_| anyhow!("transcription timed out after {:?}", limit))?,
+        None => future.await,
+    }
+}
+
+// Main STT function. When the primary engine fails the fallback engines are tried in order,
+// the first one to succeed produces the transcription.
 pub async fn perform_stt(
     audio_input: &AudioInput,
     primary_engine: &(dyn SttEngine + Send + Sync),
-    fallback_engine: Option<&(dyn SttEngine + Send + Sync)>,
+    fallback_engines: &[&(dyn SttEngine + Send + Sync)],
     vad_engine: &mut (dyn VadEngine + Send),
     output_path: &Option<PathBuf>,
-) -> Result<(String, Option<String>)> {
+    options: &SttOptions,
+    partials: Option<UnboundedSender<String>>,
+) -> Result<TranscriptionOutput, SttError> {
     let mut audio_data = audio_input.data.clone();
-    //save_wav(PathBuf::from("/tmp/incoming_audio_f32.wav"), &mut audio_data, audio_input.sample_rate, audio_input.channels, SampleFormat::F32)?;
-    
+    let new_file_name = Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let sanitized_device_name = audio_input.device.to_string().replace([' ', ':', '/', '\\'], "_");
+
+    if let Some(normalization) = &options.normalize {
+        let gain = normalization.apply(&mut audio_data);
+        if gain > 1.0 {
+            debug!("device: {}, boosted quiet audio by {:.1}x", audio_input.device, gain);
+        }
+    }
+
     let sample_rate = m::SAMPLE_RATE as u32;
     let mut new_channels = audio_input.channels;
     if audio_input.sample_rate != sample_rate {
@@ -58,11 +279,15 @@ pub async fn perform_stt(
             audio_input.sample_rate,
             sample_rate
         );
-        audio_data = resample(audio_data, audio_input.channels, audio_input.sample_rate, sample_rate)?;
+        audio_data = resample(audio_data, audio_input.channels, audio_input.sample_rate, sample_rate, options.resample_quality)
+            .map_err(SttError::ResampleFailed)?;
         info!("device: {}, resampling complete. Resampled into {} samples", audio_input.device, audio_data.len());
         new_channels = 1; // the resampled audio is mono
     }
-    //save_wav(PathBuf::from("/tmp/resampled_audio_s16.wav"), &mut audio_data, sample_rate, new_channels, SampleFormat::I16)?;
+    if !options.filter.is_empty() {
+        options.filter.apply(&mut audio_data, new_channels);
+    }
+    save_debug_audio(options, &sanitized_device_name, &new_file_name, "resampled", &audio_data, sample_rate, new_channels);
 
     // Filter out non-speech segments using Silero VAD
     debug!(
@@ -70,34 +295,51 @@ pub async fn perform_stt(
         audio_input.device
     );
     let frame_size = 160; // 10ms frame size for 16kHz audio
-    let mut speech_frames = Vec::new();
-    for (frame_index, chunk) in audio_data.chunks(frame_size).enumerate() {
-        match vad_engine.is_voice_segment(chunk) {
-            Ok(is_voice) => {
-                if is_voice {
-                    speech_frames.extend_from_slice(chunk);
-                }
-            }
+    let voice_flags: Vec<bool> = audio_data
+        .chunks(frame_size)
+        .enumerate()
+        .map(|(frame_index, chunk)| match vad_engine.is_voice_segment(chunk) {
+            Ok(is_voice) => is_voice,
             Err(e) => {
                 debug!("VAD failed for frame {}: {:?}", frame_index, e);
+                false
             }
-        }
-    }
+        })
+        .collect();
 
     info!(
         "device: {}, total audio frames processed: {}, frames that include speech: {}",
         audio_input.device,
-        audio_data.len() / frame_size,
-        speech_frames.len() / frame_size
+        voice_flags.len(),
+        voice_flags.iter().filter(|&&is_voice| is_voice).count()
     );
 
+    let vad_timeline = options
+        .vad_timeline
+        .then(|| VadTimeline::from_flags(&voice_flags, (frame_size * 1000 / sample_rate as usize) as u64));
+
+    let padding_frames = (options.speech_padding.as_secs_f64() * sample_rate as f64
+        / frame_size as f64)
+        .ceil() as usize;
+    let keep_flags = pad_voice_frames(&voice_flags, padding_frames);
+
+    let mut speech_frames = Vec::new();
+    // Index of every frame kept, used to map engine timestamps back onto the original chunk
+    let mut kept_frames = Vec::new();
+    for (frame_index, chunk) in audio_data.chunks(frame_size).enumerate() {
+        if keep_flags[frame_index] {
+            speech_frames.extend_from_slice(chunk);
+            kept_frames.push(frame_index);
+        }
+    }
+
     // If no speech frames detected, skip processing
     if speech_frames.is_empty() {
         debug!(
             "device: {}, no speech detected using VAD, skipping audio processing",
             audio_input.device
         );
-        return Err(anyhow!("No speech detected in the audio").context(SttErrorKind::NoSpeech));
+        return Err(SttError::NoSpeech);
     }
 
     debug!(
@@ -107,57 +349,278 @@ pub async fn perform_stt(
         audio_data.len() / frame_size
     );
 
-    //save_wav(PathBuf::from("/tmp/vad_audio_s16.wav"), &mut speech_frames, sample_rate, new_channels, SampleFormat::I16)?;
-    let transcription = match primary_engine.transcribe(&speech_frames, sample_rate, new_channels, &audio_input.device).await {
-        Ok(result) => result,
-        Err(e) if fallback_engine.is_some() => {
-            warn!(
-                "device: {}, primary engine failed, falling back: {:?}",
-                audio_input.device, e
-            );
-            fallback_engine.as_ref().unwrap().transcribe(&speech_frames, sample_rate, new_channels,&audio_input.device).await?
-        } 
-        Err(e) => return Err(anyhow::anyhow!("Primary engine failed and no fallback configured: {:?}", e)),
+    save_debug_audio(options, &sanitized_device_name, &new_file_name, "vad", &speech_frames, sample_rate, new_channels);
+    let primary_result = match partials {
+        Some(partials) => with_timeout(options.transcribe_timeout, primary_engine.transcribe_streaming(&speech_frames, sample_rate, new_channels, &audio_input.device, partials)).await,
+        None => with_timeout(options.transcribe_timeout, primary_engine.transcribe(&speech_frames, sample_rate, new_channels, &audio_input.device)).await,
+    };
+    let (mut transcription, engine, used_fallback) = match primary_result {
+        Ok(result) => (result, primary_engine.name(), false),
+        Err(mut error) => {
+            let mut failed = primary_engine;
+            let mut succeeded = None;
+            for &fallback_engine in fallback_engines {
+                warn!(
+                    "device: {}, engine {} failed, falling back to {}: {:?}",
+                    audio_input.device, failed.name(), fallback_engine.name(), error
+                );
+                match with_timeout(
+                    options.transcribe_timeout,
+                    fallback_engine.transcribe(&speech_frames, sample_rate, new_channels, &audio_input.device),
+                )
+                .await
+                {
+                    Ok(result) => {
+                        succeeded = Some((result, fallback_engine.name()));
+                        break;
+                    }
+                    Err(e) => {
+                        failed = fallback_engine;
+                        error = e;
+                    }
+                }
+            }
+            match succeeded {
+                Some((result, engine)) => {
+                    info!("device: {}, fallback engine {} transcribed the chunk", audio_input.device, engine);
+                    (result, engine, true)
+                }
+                None => {
+                    return Err(SttError::EngineFailed {
+                        engine: failed.name().to_string(),
+                        source: error,
+                    })
+                }
+            }
+        }
     };
 
-    debug!("device: {}, transcription: {}", audio_input.device, transcription);
+    debug!("device: {}, transcription: {}", audio_input.device, transcription.text);
+
+    if let Some(min_confidence) = options.min_confidence {
+        transcription.retain_confident_segments(min_confidence);
+    }
+
+    // The engine only saw the speech frames, shift its timings back to where they were in the chunk
+    for word in transcription.words.iter_mut() {
+        word.start_ms = remap_to_source_ms(word.start_ms, &kept_frames, frame_size, sample_rate);
+        word.end_ms = remap_to_source_ms(word.end_ms, &kept_frames, frame_size, sample_rate);
+    }
+    for segment in transcription.segments.iter_mut() {
+        segment.start_ms = remap_to_source_ms(segment.start_ms, &kept_frames, frame_size, sample_rate);
+        segment.end_ms = remap_to_source_ms(segment.end_ms, &kept_frames, frame_size, sample_rate);
+    }
 
-    let new_file_name = Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string();
-    let sanitized_device_name = audio_input.device.to_string().replace([' ', ':', '/', '\\'], "_");
     let file_path_clone = if let Some(output_path) = output_path {
         let file_path = PathBuf::from(output_path)
-            .join(format!("{}_{}.mp4", sanitized_device_name, new_file_name))
+            .join(format!(
+                "{}_{}.{}",
+                sanitized_device_name,
+                new_file_name,
+                options.output_format.extension()
+            ))
             .to_str()
             .expect("Failed to create valid path")
             .to_string();
         debug!("Saving transcription to {}", file_path);
-        let file_path_clone = file_path.clone();
-        // Run FFmpeg in a separate task
-        encode_single_audio(
+        let encoded = encode_single_audio(
             bytemuck::cast_slice(&audio_input.data),
             audio_input.sample_rate,
             audio_input.channels,
-            &file_path.into(),
-        )?;
-        debug!("Saved transcription to {}", file_path_clone);
-        Some(file_path_clone)
+            &PathBuf::from(&file_path),
+            options.output_format,
+            options.output_bitrate_kbps,
+            &options.ffmpeg,
+        );
+        let file_path = match encoded {
+            Ok(()) => file_path,
+            // Keep the recording on installs without ffmpeg, just uncompressed
+            Err(e) if e.is::<FfmpegNotFound>() => {
+                let wav_path = PathBuf::from(&file_path).with_extension("wav");
+                warn!("{}; skipping compression, saving the recording as {:?}", e, wav_path);
+                save_wav(wav_path.clone(), &audio_input.data, audio_input.sample_rate, audio_input.channels, WavSampleFormat::I16)
+                    .map_err(SttError::EncodeFailed)?;
+                wav_path.to_string_lossy().into_owned()
+            }
+            Err(e) => return Err(SttError::EncodeFailed(e)),
+        };
+        debug!("Saved transcription to {}", file_path);
+        Some(file_path)
     } else {
         None
     };
 
-    Ok((transcription, file_path_clone))
+    Ok(TranscriptionOutput {
+        text: transcription.text,
+        words: transcription.words,
+        segments: transcription.segments,
+        path: file_path_clone,
+        engine: engine.to_string(),
+        used_fallback,
+        vad_timeline,
+        language: transcription.language,
+    })
 }
 
-fn get_wav_format(sample_format: SampleFormat) -> Result<(u16, hound::SampleFormat)> {
+/// Saves an intermediate stage of `perform_stt` to `options.debug_audio_dir`, if set. Failures
+/// are logged and don't affect transcription.
+fn save_debug_audio(
+    options: &SttOptions,
+    device_name: &str,
+    file_name: &str,
+    stage: &str,
+    audio_data: &[f32],
+    sample_rate: u32,
+    channels: u16,
+) {
+    let Some(dir) = &options.debug_audio_dir else {
+        return;
+    };
+    let path = dir.join(format!("{}_{}_{}.wav", device_name, file_name, stage));
+    match save_wav(path.clone(), audio_data, sample_rate, channels, WavSampleFormat::I16) {
+        Ok(()) => debug!("Saved {} audio to {:?}", stage, path),
+        Err(e) => warn!("Failed to save {} audio to {:?}: {:?}", stage, path, e),
+    }
+}
+
+/// Extends every contiguous run of voice frames by `padding_frames` on both sides.
+fn pad_voice_frames(voice_flags: &[bool], padding_frames: usize) -> Vec<bool> {
+    let mut keep_flags = vec![false; voice_flags.len()];
+    let mut frame_index = 0;
+    while frame_index < voice_flags.len() {
+        if !voice_flags[frame_index] {
+            frame_index += 1;
+            continue;
+        }
+        let run_start = frame_index;
+        while frame_index < voice_flags.len() && voice_flags[frame_index] {
+            frame_index += 1;
+        }
+        let start = run_start.saturating_sub(padding_frames);
+        let end = (frame_index + padding_frames).min(voice_flags.len());
+        keep_flags[start..end].iter_mut().for_each(|keep| *keep = true);
+    }
+    keep_flags
+}
+
+/// Maps a time in the VAD-filtered stream onto the same instant in the unfiltered stream.
+fn remap_to_source_ms(ms: u64, kept_frames: &[usize], frame_size: usize, sample_rate: u32) -> u64 {
+    if kept_frames.is_empty() {
+        return ms;
+    }
+    let sample = (ms * sample_rate as u64 / 1000) as usize;
+    let frame = (sample / frame_size).min(kept_frames.len() - 1);
+    let offset = sample - frame * frame_size;
+    let source_sample = kept_frames[frame] * frame_size + offset.min(frame_size);
+    source_sample as u64 * 1000 / sample_rate as u64
+}
+
+/// Longest run of words that can be repeated at the start of an overlapping chunk
+const MAX_OVERLAP_WORDS: usize = 12;
+
+/// Removes the words at the start of `current` that repeat the end of `previous`, which happens
+/// when consecutive chunks share an overlap window. Words are compared case-insensitively,
+/// ignoring punctuation. Returns the trimmed text and how many words were dropped.
+pub fn trim_overlap(previous: &str, current: &str) -> (String, usize) {
+    fn normalize(word: &str) -> String {
+        word.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+
+    let previous: Vec<String> = previous.split_whitespace().map(normalize).collect();
+    let current_words: Vec<&str> = current.split_whitespace().collect();
+    let normalized: Vec<String> = current_words.iter().map(|w| normalize(w)).collect();
+
+    let max_len = MAX_OVERLAP_WORDS.min(previous.len()).min(normalized.len());
+    let overlap = (1..=max_len)
+        .rev()
+        .find(|&len| previous[previous.len() - len..] == normalized[..len])
+        .unwrap_or(0);
+
+    if overlap == 0 {
+        return (current.to_string(), 0);
+    }
+    (current_words[overlap..].join(" "), overlap)
+}
+
+/// Merges each run of consecutive final results of one device into a single result, so chunked
+/// speech reads as one passage. Texts are trimmed and joined with single spaces after dropping
+/// the words a chunk repeats from the end of the run (see `trim_overlap`), and word times are
+/// shifted to count from the first chunk of the run. A result without text, such as a chunk
+/// without speech, ends the run and is kept as is; the merged result keeps the `input`, `path`
+/// and `timestamp` of its first chunk.
+pub fn coalesce_transcriptions(results: Vec<TranscriptionResult>) -> Vec<TranscriptionResult> {
+    let mut coalesced: Vec<TranscriptionResult> = Vec::with_capacity(results.len());
+    // Whether the last entry of `coalesced` is text that later chunks may join
+    let mut can_extend = false;
+    for result in results {
+        let text = match result.transcription.as_deref().map(str::trim) {
+            Some(text) if result.is_final && !text.is_empty() => text.to_string(),
+            _ => {
+                coalesced.push(result);
+                can_extend = false;
+                continue;
+            }
+        };
+        let run = coalesced
+            .last_mut()
+            .filter(|last| can_extend && last.input.device == result.input.device);
+        let Some(run) = run else {
+            coalesced.push(TranscriptionResult {
+                transcription: Some(text),
+                ..result
+            });
+            can_extend = true;
+            continue;
+        };
+
+        let previous = run.transcription.get_or_insert_with(String::new);
+        let (trimmed, dropped) = trim_overlap(previous, &text);
+        if !trimmed.is_empty() {
+            previous.push(' ');
+            previous.push_str(&trimmed);
+        }
+        let offset_ms = result.timestamp.saturating_sub(run.timestamp) * 1000;
+        run.words.extend(result.words.into_iter().skip(dropped).map(|word| Word {
+            start_ms: word.start_ms + offset_ms,
+            end_ms: word.end_ms + offset_ms,
+            ..word
+        }));
+        run.used_fallback |= result.used_fallback;
+        if run.engine != result.engine {
+            debug!("device: {}, coalescing text of engines {} and {}", run.input.device, run.engine, result.engine);
+        }
+    }
+    coalesced
+}
+
+/// PCM encoding used when writing WAV data.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum WavSampleFormat {
+    /// 8-bit unsigned PCM
+    U8,
+    /// 16-bit signed PCM
+    #[default]
+    I16,
+    /// 24-bit signed PCM
+    I24,
+    /// 32-bit float
+    F32,
+}
+
+fn get_wav_format(sample_format: WavSampleFormat) -> (u16, hound::SampleFormat) {
     match sample_format {
-        SampleFormat::I16 => Ok((16, hound::SampleFormat::Int)),
-        SampleFormat::F32 => Ok((32, hound::SampleFormat::Float)),
-        _ => Err(anyhow::anyhow!("Unsupported sample format")),
+        WavSampleFormat::U8 => (8, hound::SampleFormat::Int),
+        WavSampleFormat::I16 => (16, hound::SampleFormat::Int),
+        WavSampleFormat::I24 => (24, hound::SampleFormat::Int),
+        WavSampleFormat::F32 => (32, hound::SampleFormat::Float),
     }
 }
 
-fn create_wav(audio_data: &[f32], sample_rate: u32, channels: u16, sample_format: SampleFormat) -> Result<Vec<u8>> {
-    let (bits_per_sample, wav_sample_format) = get_wav_format(sample_format)?;
+pub fn create_wav(audio_data: &[f32], sample_rate: u32, channels: u16, sample_format: WavSampleFormat) -> Result<Vec<u8>> {
+    let (bits_per_sample, wav_sample_format) = get_wav_format(sample_format);
 
     let spec: WavSpec = WavSpec {
         channels,
@@ -169,15 +632,15 @@ fn create_wav(audio_data: &[f32], sample_rate: u32, channels: u16, sample_format
     let mut cursor = Cursor::new(Vec::new());
     {
         let mut writer = WavWriter::new(&mut cursor, spec)?;
-        write_samples(&mut writer, audio_data, wav_sample_format)?;
+        write_samples(&mut writer, audio_data, sample_format)?;
         writer.finalize()?;
     }
 
     Ok(cursor.into_inner())
 }
 
-fn save_wav(file_path: PathBuf, audio_data: &[f32], sample_rate: u32, channels: u16, sample_format: SampleFormat) -> Result<()> {
-    let (bits_per_sample, wav_sample_format) = get_wav_format(sample_format)?;
+fn save_wav(file_path: PathBuf, audio_data: &[f32], sample_rate: u32, channels: u16, sample_format: WavSampleFormat) -> Result<()> {
+    let (bits_per_sample, wav_sample_format) = get_wav_format(sample_format);
 
     let spec: WavSpec = WavSpec {
         channels,
@@ -189,52 +652,227 @@ fn save_wav(file_path: PathBuf, audio_data: &[f32], sample_rate: u32, channels:
     let file = std::fs::File::create(file_path)?;
     let mut writer = WavWriter::new(file, spec)?;
 
-    write_samples(&mut writer, audio_data, wav_sample_format)?;
+    write_samples(&mut writer, audio_data, sample_format)?;
 
     writer.finalize()?;
 
     Ok(())
 }
 
+/// Encodes samples as headerless little-endian PCM, scaled like `create_wav`. 8-bit samples
+/// are unsigned with silence at 128, as in WAV.
+pub fn encode_pcm(audio_data: &[f32], sample_format: WavSampleFormat) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(audio_data.len() * 4);
+    for &sample in audio_data {
+        match sample_format {
+            WavSampleFormat::U8 => bytes.push((scale_i8(sample) as i16 + 128) as u8),
+            WavSampleFormat::I16 => bytes.extend_from_slice(&scale_i16(sample).to_le_bytes()),
+            WavSampleFormat::I24 => bytes.extend_from_slice(&scale_i24(sample).to_le_bytes()[..3]),
+            WavSampleFormat::F32 => bytes.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+    bytes
+}
+
+fn scale_i8(sample: f32) -> i8 {
+    (sample * 127.0).clamp(-128.0, 127.0) as i8
+}
+
+fn scale_i16(sample: f32) -> i16 {
+    (sample * 32767.0).clamp(-32768.0, 32767.0) as i16
+}
+
+/// 24-bit, in the low bytes of an `i32`
+fn scale_i24(sample: f32) -> i32 {
+    (sample * 8_388_607.0).clamp(-8_388_608.0, 8_388_607.0) as i32
+}
+
+/// Writes `f32` samples in -1.0..=1.0, scaled and clamped to the integer range of the format.
+/// hound stores 8-bit samples unsigned, offsetting the `i8` values by 128.
 fn write_samples<W: std::io::Write + std::io::Seek>(
     writer: &mut WavWriter<W>,
-audio_data: &[f32],
-    wav_sample_format: hound::SampleFormat,
+    audio_data: &[f32],
+    sample_format: WavSampleFormat,
 ) -> Result<()> {
-    if wav_sample_format == hound::SampleFormat::Int {
-        let resampled_audio_i16: Vec<i16> = audio_data
-            .iter()
-            .map(|&sample| (sample * 32767.0).clamp(-32768.0, 32767.0) as i16)
-            .collect();
+    match sample_format {
+        WavSampleFormat::U8 => {
+            for &sample in audio_data {
+                writer.write_sample(scale_i8(sample))?;
+            }
+        }
+        WavSampleFormat::I16 => {
+            for &sample in audio_data {
+                writer.write_sample(scale_i16(sample))?;
+            }
+        }
+        WavSampleFormat::I24 => {
+            for &sample in audio_data {
+                writer.write_sample(scale_i24(sample))?;
+            }
+        }
+        WavSampleFormat::F32 => {
+            for &sample in audio_data {
+                writer.write_sample(sample)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// How multichannel audio is handled when resampling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ChannelMode {
+    /// Average all channels into mono.
+    #[default]
+    Downmix,
+    /// Resample each channel on its own and keep the interleaved layout.
+    Preserve,
+}
+
+/// Sinc resampler settings. The work per output sample grows with the sinc length and the
+/// interpolation tables with sinc length times oversampling, so `Fast` does about an eighth of
+/// the work of `High`; compare them on your machine with `cargo bench --bench resample_benchmark`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ResampleQuality {
+    /// 32-tap sinc, for real-time use on weak machines. Rolls off from about 90% of the new
+    /// Nyquist frequency, inaudible for speech recognition.
+    Fast,
+    /// 128-tap sinc
+    Balanced,
+    /// 256-tap sinc with the sharpest cutoff
+    #[default]
+    High,
+}
+
+fn sinc_params(quality: ResampleQuality) -> SincInterpolationParameters {
+    match quality {
+        ResampleQuality::Fast => SincInterpolationParameters {
+            sinc_len: 32,
+            f_cutoff: 0.9,
+            interpolation: SincInterpolationType::Linear,
+            oversampling_factor: 64,
+            window: WindowFunction::Blackman2,
+        },
+        ResampleQuality::Balanced => SincInterpolationParameters {
+            sinc_len: 128,
+            f_cutoff: 0.925,
+            interpolation: SincInterpolationType::Linear,
+            oversampling_factor: 128,
+            window: WindowFunction::BlackmanHarris2,
+        },
+        ResampleQuality::High => SincInterpolationParameters {
+            sinc_len: 256,
+            f_cutoff: 0.95,
+            interpolation: SincInterpolationType::Linear,
+            oversampling_factor: 256,
+            window: WindowFunction::BlackmanHarris2,
+        },
+    }
+}
+
+/// Resamples according to `channel_mode`, returning the samples and their channel count.
+fn resample_with_mode(
+    input: Vec<f32>,
+    input_channels: u16,
+    from_sample_rate: u32,
+    to_sample_rate: u32,
+    channel_mode: ChannelMode,
+    quality: ResampleQuality,
+) -> Result<(Vec<f32>, u16)> {
+    match channel_mode {
+        ChannelMode::Downmix => Ok((
+            resample(input, input_channels, from_sample_rate, to_sample_rate, quality)?,
+            1,
+        )),
+        ChannelMode::Preserve => Ok((
+            resample_multichannel(input, input_channels, from_sample_rate, to_sample_rate, quality)?,
+            input_channels,
+        )),
+    }
+}
 
-        for sample in resampled_audio_i16 {
-            writer.write_sample(sample)?;
+fn resample_multichannel(
+    input: Vec<f32>,
+    input_channels: u16,
+    from_sample_rate: u32,
+    to_sample_rate: u32,
+    quality: ResampleQuality,
+) -> Result<Vec<f32>> {
+    let channels = input_channels.max(1) as usize;
+    debug!("Resampling {} channels: {} -> {}, {} len", channels, from_sample_rate, to_sample_rate, input.len());
+
+    // De-interleave into one buffer per channel
+    let frames = input.len() / channels;
+    let mut waves_in: Vec<Vec<f32>> = vec![Vec::with_capacity(frames); channels];
+    for frame in input.chunks_exact(channels) {
+        for (channel, &sample) in frame.iter().enumerate() {
+            waves_in[channel].push(sample);
         }
+    }
+
+    let params = sinc_params(quality);
+    let waves_out = if frames < params.sinc_len {
+        waves_in
+            .iter()
+            .map(|wave| resample_linear(wave, from_sample_rate, to_sample_rate))
+            .collect()
     } else {
-        for &sample in audio_data {
-            writer.write_sample(sample)?;
+        let mut resampler = SincFixedIn::<f32>::new(
+            to_sample_rate as f64 / from_sample_rate as f64,
+            2.0,
+            params,
+            frames,
+            channels,
+        )?;
+        resampler.process(&waves_in, None)?
+    };
+
+    // Interleave again
+    let out_frames = waves_out.iter().map(|wave| wave.len()).min().unwrap_or(0);
+    let mut output = Vec::with_capacity(out_frames * channels);
+    for frame in 0..out_frames {
+        for wave in waves_out.iter() {
+            output.push(wave[frame]);
         }
     }
+    debug!("Multichannel resampling complete: {} len", output.len());
+    Ok(output)
+}
 
-    Ok(())
+/// Linear interpolation of a mono `input`, for chunks too short for the sinc resampler. Empty
+/// input gives empty output.
+fn resample_linear(input: &[f32], from_sample_rate: u32, to_sample_rate: u32) -> Vec<f32> {
+    let Some(&last) = input.last() else {
+        return Vec::new();
+    };
+    let output_len = ((input.len() as f64 * to_sample_rate as f64 / from_sample_rate as f64).round() as usize).max(1);
+    let step = from_sample_rate as f64 / to_sample_rate as f64;
+    (0..output_len)
+        .map(|index| {
+            let position = index as f64 * step;
+            let before = position.floor() as usize;
+            let fraction = (position - before as f64) as f32;
+            let a = input.get(before).copied().unwrap_or(last);
+            let b = input.get(before + 1).copied().unwrap_or(last);
+            a + (b - a) * fraction
+        })
+        .collect()
 }
 
-fn resample(
+/// Downmixes interleaved `input` to mono and resamples it to `to_sample_rate`. Chunks shorter
+/// than the sinc filter are interpolated linearly, empty ones stay empty.
+pub fn resample(
     input: Vec<f32>,
     input_channels: u16,
     from_sample_rate: u32,
-    to_sample_rate: u32
+    to_sample_rate: u32,
+    quality: ResampleQuality,
 ) -> Result<Vec<f32>> {
-    debug!("Resampling audio: {} -> {}, {} len", from_sample_rate, to_sample_rate, input.len());
+    debug!("Resampling audio: {} -> {}, {} len ({:?})", from_sample_rate, to_sample_rate, input.len(), quality);
     
     // Resampler parameters
-    let params = SincInterpolationParameters {
-        sinc_len: 256,
-        f_cutoff: 0.95,
-        interpolation: SincInterpolationType::Linear,
-        oversampling_factor: 256,
-        window: WindowFunction::BlackmanHarris2,
-    };
+    let params = sinc_params(quality);
 
     // If we have multiple channels, mix them down into mono by averaging interleaved data
     let mono_input: Vec<f32> = if input_channels > 1 {
@@ -250,6 +888,12 @@ fn resample(
         input  
     };
 
+    // The sinc resampler needs at least one filter length, e.g. for the last samples of a recording
+    if mono_input.len() < params.sinc_len {
+        debug!("{} samples are too few for the sinc resampler, interpolating linearly", mono_input.len());
+        return Ok(resample_linear(&mono_input, from_sample_rate, to_sample_rate));
+    }
+
     // Set up the resampler
     let mut resampler = SincFixedIn::<f32>::new(
         to_sample_rate as f64 / from_sample_rate as f64, // Resampling ratio
@@ -279,6 +923,10 @@ pub struct AudioInput {
     pub sample_rate: u32,
     pub channels: u16,
     pub device: String,
+    /// Largest absolute sample value in `data`, 0.0 to 1.0 for full scale
+    pub peak: f32,
+    /// Root mean square level of `data`
+    pub rms: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -286,8 +934,21 @@ pub struct TranscriptionResult {
     pub path: String,
     pub input: AudioInput,
     pub transcription: Option<String>,
+    pub words: Vec<Word>,
     pub timestamp: u64,
     pub error: Option<String>,
+    /// `false` for interim results of a streaming engine; a final result for the same input follows.
+    pub is_final: bool,
+    /// `SttEngine::name` of the engine that produced the transcription; empty when none did
+    pub engine: String,
+    /// Whether the transcription comes from the fallback engine
+    pub used_fallback: bool,
+    /// VAD found no speech in the chunk, `error` says so; not an engine failure
+    pub no_speech: bool,
+    /// Where VAD heard voice, with `SttOptions::vad_timeline`
+    pub vad_timeline: Option<VadTimeline>,
+    /// Language code the engine detected or decoded with, e.g. `en`; `None` when it doesn't say
+    pub language: Option<String>,
 }
 
 #[derive(Clone, PartialEq, Debug, Copy)]
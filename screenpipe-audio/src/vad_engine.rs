@@ -1,19 +1,133 @@
 use anyhow;
 use log::debug;
+use serde::Serialize;
+use std::fmt;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 use vad_rs::Vad;
 
+/// A stretch of audio VAD classified as voice, in milliseconds from the start of the chunk
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct VoiceRegion {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// Where VAD heard voice in a chunk, before speech padding, to tell whether missed words were
+/// dropped by VAD or by the engine. `Display` draws it as one line, `#` for voice.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct VadTimeline {
+    pub duration_ms: u64,
+    pub regions: Vec<VoiceRegion>,
+}
+
+impl VadTimeline {
+    /// Run-length encodes per-frame decisions of `frame_ms` each.
+    pub fn from_flags(voice_flags: &[bool], frame_ms: u64) -> Self {
+        let mut regions: Vec<VoiceRegion> = Vec::new();
+        for (frame_index, _) in voice_flags.iter().enumerate().filter(|(_, &is_voice)| is_voice) {
+            let start_ms = frame_index as u64 * frame_ms;
+            match regions.last_mut() {
+                Some(region) if region.end_ms == start_ms => region.end_ms += frame_ms,
+                _ => regions.push(VoiceRegion {
+                    start_ms,
+                    end_ms: start_ms + frame_ms,
+                }),
+            }
+        }
+        Self {
+            duration_ms: voice_flags.len() as u64 * frame_ms,
+            regions,
+        }
+    }
+
+    pub fn voice_ms(&self) -> u64 {
+        self.regions.iter().map(|r| r.end_ms - r.start_ms).sum()
+    }
+}
+
+/// Width of the `VadTimeline` bar; each character covers at least 100ms
+const TIMELINE_WIDTH: u64 = 60;
+
+impl fmt::Display for VadTimeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cell_ms = (self.duration_ms.div_ceil(TIMELINE_WIDTH)).max(100);
+        let cells = self.duration_ms.div_ceil(cell_ms);
+        let bar: String = (0..cells)
+            .map(|cell| {
+                let (start, end) = (cell * cell_ms, (cell + 1) * cell_ms);
+                let voiced = self.regions.iter().any(|r| r.start_ms < end && r.end_ms > start);
+                if voiced { '#' } else { '.' }
+            })
+            .collect();
+        write!(
+            f,
+            "[{}] {:.1}s of voice in {:.1}s",
+            bar,
+            self.voice_ms() as f64 / 1000.0,
+            self.duration_ms as f64 / 1000.0
+        )?;
+        for region in &self.regions {
+            write!(f, " {:.2}-{:.2}s", region.start_ms as f64 / 1000.0, region.end_ms as f64 / 1000.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
 pub enum VadEngineEnum {
-    WebRtc,
+    /// `aggressiveness` is the WebRTC mode, 0-3; `None` keeps the default (3)
+    WebRtc { aggressiveness: Option<u8> },
     Silero,
+    /// No VAD, every frame counts as speech
+    None,
+}
+
+/// How readily a VAD engine classifies audio as speech. `High` keeps more audio
+/// (quiet rooms, soft speakers), `Low` discards more (noisy environments).
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VadSensitivity {
+    Low,
+    Medium,
+    High,
+}
+
+impl VadSensitivity {
+    /// Minimum Silero speech probability (0.0-1.0) for a window to count as voice.
+    pub fn silero_threshold(&self) -> f32 {
+        match self {
+            VadSensitivity::Low => 0.7,
+            VadSensitivity::Medium => 0.5,
+            VadSensitivity::High => 0.3,
+        }
+    }
+
+    /// Whether a window with Silero speech probability `prob` counts as voice.
+    pub fn is_silero_speech(&self, prob: f32) -> bool {
+        prob > self.silero_threshold()
+    }
+
+    /// WebRTC has no threshold, only aggressiveness modes; lower sensitivity means a more aggressive mode.
+    pub fn webrtc_mode(&self) -> webrtc_vad::VadMode {
+        match self {
+            VadSensitivity::Low => webrtc_vad::VadMode::VeryAggressive,
+            VadSensitivity::Medium => webrtc_vad::VadMode::Aggressive,
+            VadSensitivity::High => webrtc_vad::VadMode::LowBitrate,
+        }
+    }
 }
 
 pub trait VadEngine {
     fn is_voice_segment(&mut self, audio_chunk: &[f32]) -> anyhow::Result<bool>;
+    fn set_sensitivity(&mut self, level: VadSensitivity);
 }
 
-pub struct WebRtcVad(webrtc_vad::Vad);
+pub struct WebRtcVad {
+    vad: webrtc_vad::Vad,
+    /// Set with `with_mode`, which `set_sensitivity` then leaves alone
+    explicit_mode: bool,
+}
 
 impl Default for WebRtcVad {
     fn default() -> Self {
@@ -25,7 +139,28 @@ impl WebRtcVad {
     pub fn new() -> Self {
         let mut vad = webrtc_vad::Vad::new();
         vad.set_mode(webrtc_vad::VadMode::VeryAggressive);
-        Self(vad)
+        Self {
+            vad,
+            explicit_mode: false,
+        }
+    }
+
+    /// Creates the VAD with aggressiveness `mode` from 0 (least) to 3 (most). Higher modes drop
+    /// more non-speech but are more likely to clip quiet speech.
+    pub fn with_mode(mode: u8) -> anyhow::Result<Self> {
+        let vad_mode = match mode {
+            0 => webrtc_vad::VadMode::Quality,
+            1 => webrtc_vad::VadMode::LowBitrate,
+            2 => webrtc_vad::VadMode::Aggressive,
+            3 => webrtc_vad::VadMode::VeryAggressive,
+            _ => anyhow::bail!("WebRTC VAD aggressiveness must be 0-3, got {}", mode),
+        };
+        let mut vad = webrtc_vad::Vad::new();
+        vad.set_mode(vad_mode);
+        Ok(Self {
+            vad,
+            explicit_mode: true,
+        })
     }
 }
 
@@ -35,7 +170,7 @@ impl VadEngine for WebRtcVad {
         let i16_chunk: Vec<i16> = audio_chunk.iter().map(|&x| (x * 32767.0) as i16).collect();
 
         let result = self
-            .0
+            .vad
             .is_voice_segment(&i16_chunk)
             .map_err(|e| anyhow::anyhow!("WebRTC VAD error: {:?}", e))?;
 
@@ -43,23 +178,50 @@ impl VadEngine for WebRtcVad {
 
         Ok(result)
     }
+
+    fn set_sensitivity(&mut self, level: VadSensitivity) {
+        // An explicit aggressiveness is more specific than a sensitivity level
+        if self.explicit_mode {
+            debug!("WebRTC VAD aggressiveness set explicitly, ignoring sensitivity {:?}", level);
+            return;
+        }
+        self.vad.set_mode(level.webrtc_mode());
+    }
 }
 
 pub struct SileroVad {
     vad: Vad,
+    sensitivity: VadSensitivity,
 }
 
 impl SileroVad {
     pub fn new() -> anyhow::Result<Self> {
         debug!("Initializing SileroVad...");
-        let model_path = Self::download_model()?;
-        debug!("SileroVad Model downloaded to: {:?}", model_path);
+        let model_path = Self::model_path()?;
+        debug!("SileroVad Model at: {:?}", model_path);
         let vad = Vad::new(model_path, 16000).map_err(|e| {
             debug!("SileroVad Error creating Vad: {}", e);
             anyhow::anyhow!("Vad creation error: {}", e)
         })?;
         debug!("SileroVad initialized successfully");
-        Ok(Self { vad })
+        Ok(Self {
+            vad,
+            sensitivity: VadSensitivity::Medium,
+        })
+    }
+
+    /// Downloads the model on the first call of the process; later calls reuse the file.
+    pub fn model_path() -> anyhow::Result<PathBuf> {
+        static MODEL_PATH: OnceLock<PathBuf> = OnceLock::new();
+        if let Some(path) = MODEL_PATH.get() {
+            return Ok(path.clone());
+        }
+        // reqwest's blocking client panics when called from a tokio runtime, so the download
+        // gets a thread of its own
+        let path = std::thread::spawn(Self::download_model)
+            .join()
+            .map_err(|_| anyhow::anyhow!("SileroVad model download panicked"))??;
+        Ok(MODEL_PATH.get_or_init(|| path).clone())
     }
 
     fn download_model() -> anyhow::Result<PathBuf> {
@@ -91,22 +253,42 @@ impl VadEngine for SileroVad {
                 anyhow::anyhow!("Vad compute error: {}", e)
             })?;
 
-            if result.prob < 0.3 {
+            // `prob` is the model's speech probability for the window
+            if self.sensitivity.is_silero_speech(result.prob) {
                 return Ok(true);
             }
         }
 
         Ok(false)
     }
+
+    fn set_sensitivity(&mut self, level: VadSensitivity) {
+        self.sensitivity = level;
+    }
+}
+
+/// Treats all audio as speech so the whole chunk reaches the STT engine, for clean recordings
+/// or to rule out VAD when debugging missing transcriptions.
+#[derive(Default)]
+pub struct PassthroughVad;
+
+impl VadEngine for PassthroughVad {
+    fn is_voice_segment(&mut self, _audio_chunk: &[f32]) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+
+    fn set_sensitivity(&mut self, _level: VadSensitivity) {}
 }
 
-pub fn create_vad_engine(engine: VadEngineEnum) -> anyhow::Result<Box<dyn VadEngine>> {
+pub fn create_vad_engine(engine: VadEngineEnum) -> anyhow::Result<Box<dyn VadEngine + Send>> {
     match engine {
-        VadEngineEnum::WebRtc => Ok(Box::new(WebRtcVad::new())),
+        VadEngineEnum::WebRtc { aggressiveness: None } => Ok(Box::new(WebRtcVad::new())),
+        VadEngineEnum::WebRtc { aggressiveness: Some(mode) } => Ok(Box::new(WebRtcVad::with_mode(mode)?)),
         VadEngineEnum::Silero => {
             let silero_vad = SileroVad::new()?;
             Ok(Box::new(silero_vad))
         }
+        VadEngineEnum::None => Ok(Box::new(PassthroughVad)),
     }
 }
 
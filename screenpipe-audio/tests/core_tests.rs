@@ -39,6 +39,18 @@ mod tests {
         assert_eq!(spec.to_string(), "Test Device (input)");
     }
 
+    #[test]
+    fn test_should_include_output_device() {
+        use screenpipe_audio::should_include_output_device;
+
+        assert!(should_include_output_device("Display 1"));
+        assert!(should_include_output_device("BlackHole 2ch"));
+        assert!(should_include_output_device("LG UltraFine Display Audio"));
+        assert!(!should_include_output_device("MacBook Pro Speakers"));
+        assert!(!should_include_output_device("Louis's AirPods Pro"));
+        assert!(!should_include_output_device("AIRPODS"));
+    }
+
     #[tokio::test]
     #[ignore] // Add this if you want to skip this test in regular test runs
     async fn test_record_and_transcribe() {
@@ -194,7 +206,7 @@ mod tests {
         let output_path_2 = output_path.clone();
         let (whisper_sender, mut whisper_receiver, _) = create_whisper_channel(
             Arc::new(AudioTranscriptionEngine::WhisperTiny),
-            VadEngineEnum::WebRtc,
+            VadEngineEnum::WebRtc { aggressiveness: None },
             None,
             &output_path_2.clone(),
         )
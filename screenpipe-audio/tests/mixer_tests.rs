@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_audio::{
+        audio_queue, spawn_device_mixer, spawn_stereo_capture, AudioInput, DropPolicy, ResampleQuality, MIXED_DEVICE,
+        STEREO_DEVICE,
+    };
+    use std::time::Duration;
+
+    fn chunk(device: &str, value: f32, samples: usize) -> AudioInput {
+        AudioInput {
+            data: vec![value; samples],
+            sample_rate: 16000,
+            channels: 1,
+            device: device.to_string(),
+            peak: value,
+            rms: value,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mixer_sums_devices_into_one_stream() {
+        let (output, mut mixed) = audio_queue(8, DropPolicy::Block);
+        let input = spawn_device_mixer(
+            vec!["mic".to_string(), "system".to_string()],
+            Duration::from_secs(1),
+            ResampleQuality::default(),
+            8,
+            DropPolicy::Block,
+            output,
+        );
+
+        // The system audio arrives in two halves, nothing is mixed until both devices have a chunk
+        input.send(chunk("mic", 0.25, 16000)).await.unwrap();
+        input.send(chunk("system", 0.5, 8000)).await.unwrap();
+        input.send(chunk("system", 0.5, 8000)).await.unwrap();
+        input.send(chunk("mic", 0.25, 8000)).await.unwrap();
+        drop(input);
+
+        let first = tokio::time::timeout(Duration::from_secs(5), mixed.recv()).await.unwrap().unwrap();
+        assert_eq!(first.device, MIXED_DEVICE);
+        assert_eq!(first.data.len(), 16000);
+        assert!(first.data.iter().all(|&s| (s - 0.75).abs() < 1e-6));
+
+        // The rest is flushed when the recorders are gone, the missing system audio as silence
+        let rest = tokio::time::timeout(Duration::from_secs(5), mixed.recv()).await.unwrap().unwrap();
+        assert_eq!(rest.data.len(), 8000);
+        assert!(rest.data.iter().all(|&s| (s - 0.25).abs() < 1e-6));
+        assert!(mixed.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stereo_capture_puts_each_device_on_its_channel() {
+        let (output, mut stereo) = audio_queue(8, DropPolicy::Block);
+        let input = spawn_stereo_capture(
+            "mic".to_string(),
+            "system".to_string(),
+            Duration::from_secs(1),
+            ResampleQuality::default(),
+            8,
+            DropPolicy::Block,
+            output,
+        );
+
+        input.send(chunk("system", 0.5, 16000)).await.unwrap();
+        input.send(chunk("mic", 0.25, 16000)).await.unwrap();
+        drop(input);
+
+        let frames = tokio::time::timeout(Duration::from_secs(5), stereo.recv()).await.unwrap().unwrap();
+        assert_eq!(frames.device, STEREO_DEVICE);
+        assert_eq!(frames.channels, 2);
+        assert_eq!(frames.data.len(), 32000);
+        assert!(frames.data.chunks(2).all(|frame| frame == [0.25, 0.5]));
+        assert!(stereo.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mixer_drops_audio_from_unknown_devices() {
+        let (output, mut stereo) = audio_queue(8, DropPolicy::Block);
+        let input = spawn_stereo_capture(
+            "mic".to_string(),
+            "system".to_string(),
+            Duration::from_secs(1),
+            ResampleQuality::default(),
+            8,
+            DropPolicy::Block,
+            output,
+        );
+
+        // Far enough ahead to have the others padded with silence, were it buffered
+        input.send(chunk("headset", 0.9, 64000)).await.unwrap();
+        input.send(chunk("mic", 0.25, 16000)).await.unwrap();
+        input.send(chunk("system", 0.5, 16000)).await.unwrap();
+        drop(input);
+
+        let frames = tokio::time::timeout(Duration::from_secs(5), stereo.recv()).await.unwrap().unwrap();
+        assert_eq!(frames.data.len(), 32000);
+        assert!(frames.data.chunks(2).all(|frame| frame == [0.25, 0.5]));
+        let rest = tokio::time::timeout(Duration::from_secs(5), stereo.recv()).await.unwrap();
+        assert!(rest.is_none());
+    }
+}
@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_audio::{audio_levels, Normalization, SpeechFilter};
+
+    fn tone(hz: f32) -> Vec<f32> {
+        (0..16000)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * hz * i as f32 / 16000.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_normalization_boosts_quiet_audio_to_target() {
+        let normalization = Normalization::default();
+        let mut samples: Vec<f32> = (0..16000).map(|i| if i % 2 == 0 { 0.01 } else { -0.01 }).collect();
+
+        let gain = normalization.apply(&mut samples);
+        assert!((gain - 10.0).abs() < 1e-3);
+        let (peak, rms) = audio_levels(&samples);
+        assert!((rms - normalization.target_rms).abs() < 1e-4);
+        assert!(peak <= normalization.max_peak);
+    }
+
+    #[test]
+    fn test_normalization_leaves_silence_and_loud_audio_alone() {
+        let normalization = Normalization::default();
+
+        let mut silence = vec![0.0001f32; 16000];
+        assert_eq!(normalization.apply(&mut silence), 1.0);
+        assert!(silence.iter().all(|&s| s == 0.0001));
+
+        let mut loud = vec![0.5f32; 16000];
+        assert_eq!(normalization.apply(&mut loud), 1.0);
+        assert!(loud.iter().all(|&s| s == 0.5));
+    }
+
+    #[test]
+    fn test_highpass_removes_mains_hum() {
+        let filter = SpeechFilter::new(Some(80.0), None).unwrap();
+
+        let mut hum = tone(50.0);
+        filter.apply(&mut hum, 1);
+        let mut voice = tone(1000.0);
+        filter.apply(&mut voice, 1);
+
+        // Skip the first 100ms while the filter settles
+        let (_, hum_rms) = audio_levels(&hum[1600..]);
+        let (_, voice_rms) = audio_levels(&voice[1600..]);
+        assert!(hum_rms < 0.2, "hum rms {}", hum_rms);
+        assert!((voice_rms - 0.5 / std::f32::consts::SQRT_2).abs() < 0.01, "voice rms {}", voice_rms);
+    }
+
+    #[test]
+    fn test_filter_cutoffs_are_validated() {
+        assert!(SpeechFilter::new(Some(80.0), Some(7000.0)).is_ok());
+        assert!(SpeechFilter::new(None, Some(9000.0)).is_err());
+        assert!(SpeechFilter::new(Some(0.0), None).is_err());
+        assert!(SpeechFilter::new(Some(3000.0), Some(1000.0)).is_err());
+        assert!(SpeechFilter::default().is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_audio::srt::{build_cues, render};
+    use screenpipe_audio::{AudioInput, TranscriptionResult, Word};
+
+    fn result(timestamp: u64, text: &str, words: Vec<Word>) -> TranscriptionResult {
+        TranscriptionResult {
+            path: String::new(),
+            input: AudioInput {
+                data: vec![0.0; 16000 * 2],
+                sample_rate: 16000,
+                channels: 1,
+                device: "test".to_string(),
+                peak: 0.0,
+                rms: 0.0,
+            },
+            transcription: Some(text.to_string()),
+            words,
+            timestamp,
+            error: None,
+            is_final: true,
+            engine: "test".to_string(),
+            used_fallback: false,
+            no_speech: false,
+            vad_timeline: None,
+            language: None,
+        }
+    }
+
+    fn word(text: &str, start_ms: u64, end_ms: u64, speaker: Option<u32>) -> Word {
+        Word {
+            text: text.to_string(),
+            start_ms,
+            end_ms,
+            confidence: 1.0,
+            speaker,
+        }
+    }
+
+    #[test]
+    fn test_cue_per_result_without_words() {
+        let cues = build_cues(&[result(100, "hello", vec![]), result(105, "world", vec![])]);
+        let srt = render(&cues);
+        assert_eq!(
+            srt,
+            "1\n00:00:00,000 --> 00:00:02,000\nhello\n\n2\n00:00:05,000 --> 00:00:07,000\nworld\n\n"
+        );
+    }
+
+    #[test]
+    fn test_words_split_on_pause_and_speaker() {
+        let words = vec![
+            word("hi", 0, 300, Some(0)),
+            word("there", 300, 600, Some(0)),
+            word("hey", 700, 900, Some(1)),
+            word("later", 3000, 3400, Some(1)),
+        ];
+        let cues = build_cues(&[result(10, "hi there hey later", words)]);
+        let texts: Vec<_> = cues.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, vec!["hi there", "hey", "later"]);
+        assert_eq!((cues[2].start_ms, cues[2].end_ms), (3000, 3400));
+    }
+
+    #[test]
+    fn test_empty_transcriptions_are_skipped() {
+        let mut empty = result(0, "", vec![]);
+        empty.transcription = None;
+        assert!(build_cues(&[empty]).is_empty());
+    }
+}
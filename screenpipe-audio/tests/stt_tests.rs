@@ -0,0 +1,549 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_audio::stt::engines::whisper::WhisperEngine;
+    use screenpipe_audio::stt::{RecordingState, SttEngine, Task};
+    use screenpipe_audio::{
+        coalesce_transcriptions, create_comm_channel, create_comm_channel_with_status, create_wav, encode_pcm, perform_stt, resample, trim_overlap,
+        AudioFormat, AudioInput, AudioTranscriptionEngine, DevicePreference, EngineOutput, FfmpegOptions, HallucinationFilter, ResampleQuality, StopHandle, SttError, SttOptions, TranscriptionResult,
+        VadEngineEnum, WavSampleFormat, WhisperDecodeParams, WhisperModel, Word,
+    };
+    use screenpipe_audio::vad_engine::{create_vad_engine, VadSensitivity, VadTimeline, VoiceRegion};
+    use std::future::Future;
+    use std::io::Cursor;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const SAMPLES: [f32; 5] = [0.0, 0.5, -0.5, 1.0, -1.0];
+
+    fn read_back<S: hound::Sample>(format: WavSampleFormat) -> (hound::WavSpec, Vec<S>) {
+        let wav = create_wav(&SAMPLES, 16000, 1, format).unwrap();
+        let mut reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+        let samples = reader.samples::<S>().map(|s| s.unwrap()).collect();
+        (reader.spec(), samples)
+    }
+
+    #[test]
+    fn test_wav_u8_round_trip() {
+        let (spec, samples) = read_back::<i8>(WavSampleFormat::U8);
+        assert_eq!(spec.bits_per_sample, 8);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Int);
+        assert_eq!(samples, vec![0, 63, -63, 127, -127]);
+    }
+
+    #[test]
+    fn test_wav_i16_round_trip() {
+        let (spec, samples) = read_back::<i16>(WavSampleFormat::I16);
+        assert_eq!(spec.bits_per_sample, 16);
+        assert_eq!(samples, vec![0, 16383, -16383, 32767, -32767]);
+    }
+
+    #[test]
+    fn test_wav_i24_round_trip() {
+        let (spec, samples) = read_back::<i32>(WavSampleFormat::I24);
+        assert_eq!(spec.bits_per_sample, 24);
+        assert_eq!(samples, vec![0, 4_194_303, -4_194_303, 8_388_607, -8_388_607]);
+    }
+
+    #[test]
+    fn test_wav_f32_round_trip() {
+        let (spec, samples) = read_back::<f32>(WavSampleFormat::F32);
+        assert_eq!(spec.bits_per_sample, 32);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+        assert_eq!(samples, SAMPLES.to_vec());
+    }
+
+    #[test]
+    fn test_raw_pcm_matches_wav_samples() {
+        let pcm = encode_pcm(&SAMPLES, WavSampleFormat::I16);
+        let samples: Vec<i16> = pcm.chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert_eq!(samples, read_back::<i16>(WavSampleFormat::I16).1);
+
+        assert_eq!(encode_pcm(&SAMPLES, WavSampleFormat::U8), vec![128, 191, 65, 255, 1]);
+        assert_eq!(encode_pcm(&SAMPLES, WavSampleFormat::F32).len(), SAMPLES.len() * 4);
+        assert_eq!(encode_pcm(&SAMPLES, WavSampleFormat::I24).len(), SAMPLES.len() * 3);
+    }
+
+    #[test]
+    fn test_resample_handles_tiny_chunks() {
+        assert!(resample(Vec::new(), 1, 48000, 16000, ResampleQuality::High).unwrap().is_empty());
+        assert!(resample(Vec::new(), 2, 44100, 16000, ResampleQuality::Fast).unwrap().is_empty());
+
+        assert_eq!(resample(vec![0.5], 1, 48000, 16000, ResampleQuality::High).unwrap(), vec![0.5]);
+
+        let resampled = resample(vec![0.25; 159], 1, 48000, 16000, ResampleQuality::High).unwrap();
+        assert_eq!(resampled.len(), 53);
+        assert!(resampled.iter().all(|&s| (s - 0.25).abs() < 1e-6));
+
+        // Downmixed first: 159 stereo frames
+        let resampled = resample(vec![0.5, 0.0].repeat(159), 2, 48000, 16000, ResampleQuality::Balanced).unwrap();
+        assert_eq!(resampled.len(), 53);
+    }
+
+    #[test]
+    fn test_trim_overlap_drops_repeated_words() {
+        let (text, dropped) = trim_overlap("we should meet on", "Meet on Tuesday at noon.");
+        assert_eq!(text, "Tuesday at noon.");
+        assert_eq!(dropped, 2);
+    }
+
+    #[test]
+    fn test_trim_overlap_keeps_unrelated_text() {
+        let (text, dropped) = trim_overlap("see you tomorrow", "Thanks for joining.");
+        assert_eq!(text, "Thanks for joining.");
+        assert_eq!(dropped, 0);
+    }
+
+    #[test]
+    fn test_trim_overlap_with_empty_previous() {
+        let (text, dropped) = trim_overlap("", "hello there");
+        assert_eq!(text, "hello there");
+        assert_eq!(dropped, 0);
+    }
+
+    // Phrases and no-speech probabilities taken from whisper output on recorded silence and music
+    fn transcribed(device: &str, timestamp: u64, text: Option<&str>) -> TranscriptionResult {
+        TranscriptionResult {
+            path: String::new(),
+            input: AudioInput {
+                data: Vec::new(),
+                sample_rate: 16000,
+                channels: 1,
+                device: device.to_string(),
+                peak: 0.0,
+                rms: 0.0,
+            },
+            transcription: text.map(str::to_string),
+            words: text
+                .unwrap_or_default()
+                .split_whitespace()
+                .enumerate()
+                .map(|(i, word)| Word {
+                    text: word.to_string(),
+                    start_ms: i as u64 * 100,
+                    end_ms: i as u64 * 100 + 100,
+                    confidence: 1.0,
+                    speaker: None,
+                })
+                .collect(),
+            timestamp,
+            error: None,
+            is_final: true,
+            engine: "test".to_string(),
+            used_fallback: false,
+            no_speech: text.is_none(),
+            vad_timeline: None,
+            language: None,
+        }
+    }
+
+    #[test]
+    fn test_coalesce_merges_consecutive_chunks_of_a_device() {
+        let results = vec![
+            transcribed("mic", 10, Some(" so the plan is")),
+            transcribed("mic", 12, Some("plan is to ship friday ")),
+            transcribed("system", 13, Some("sounds good")),
+            transcribed("mic", 14, None),
+            transcribed("mic", 16, Some("thanks")),
+        ];
+        let coalesced = coalesce_transcriptions(results);
+
+        let texts: Vec<_> = coalesced.iter().map(|r| r.transcription.as_deref()).collect();
+        assert_eq!(
+            texts,
+            [Some("so the plan is to ship friday"), Some("sounds good"), None, Some("thanks")]
+        );
+        // The repeated "plan is" is gone and the rest is shifted by the 2s between the chunks
+        let words = &coalesced[0].words;
+        assert_eq!(words.len(), 7);
+        assert_eq!(words[4].text, "to");
+        assert_eq!(words[4].start_ms, 2200);
+    }
+
+    #[test]
+    fn test_vad_timeline_run_length_encodes_voice_frames() {
+        let flags = [false, true, true, false, false, true, false];
+        let timeline = VadTimeline::from_flags(&flags, 10);
+        assert_eq!(timeline.duration_ms, 70);
+        assert_eq!(
+            timeline.regions,
+            [
+                VoiceRegion { start_ms: 10, end_ms: 30 },
+                VoiceRegion { start_ms: 50, end_ms: 60 },
+            ]
+        );
+        assert_eq!(timeline.voice_ms(), 30);
+    }
+
+    #[test]
+    fn test_silero_speech_is_above_the_threshold() {
+        // Likely speech counts as voice, unlikely speech doesn't
+        assert!(VadSensitivity::Medium.is_silero_speech(0.9));
+        assert!(!VadSensitivity::Medium.is_silero_speech(0.1));
+        assert!(!VadSensitivity::Medium.is_silero_speech(0.5));
+
+        // Higher sensitivity keeps less certain speech
+        assert!(VadSensitivity::High.is_silero_speech(0.4));
+        assert!(!VadSensitivity::Low.is_silero_speech(0.6));
+    }
+
+    #[test]
+    fn test_hallucination_filter_drops_phantom_text() {
+        let filter = HallucinationFilter::default();
+        assert!(filter.is_hallucination(" Thank you.", 0.12));
+        assert!(filter.is_hallucination(" Thank you. Thank you. Thank you.", 0.3));
+        assert!(filter.is_hallucination("[Music]", 0.05));
+        assert!(filter.is_hallucination(" Thanks for watching!", 0.41));
+        assert!(filter.is_hallucination(" So we can start with the next item.", 0.93));
+    }
+
+    #[test]
+    fn test_hallucination_filter_keeps_real_speech() {
+        let filter = HallucinationFilter::default();
+        assert!(!filter.is_hallucination(" Thank you for the update, let's ship it.", 0.02));
+        assert!(!filter.is_hallucination(" Did you see the review?", 0.1));
+        assert!(!filter.is_hallucination("", 0.1));
+    }
+
+    #[test]
+    fn test_hallucination_filter_custom_blocklist() {
+        let filter = HallucinationFilter::new(["Okay."], 1.0);
+        assert!(filter.is_hallucination("okay okay", 0.99));
+        assert!(!filter.is_hallucination(" Thank you.", 0.99));
+    }
+
+    #[tokio::test]
+    #[ignore] // Downloads whisper tiny
+    async fn test_whisper_transcribes_silence_as_nothing() {
+        let model = WhisperModel::new(Arc::new(AudioTranscriptionEngine::WhisperTiny), DevicePreference::Cpu, None).unwrap();
+        let engine = WhisperEngine::new(model, Task::Transcribe, Some("en".to_string()), WhisperDecodeParams::default(), None)
+            .unwrap()
+            .with_hallucination_filter(HallucinationFilter::default());
+
+        // Digital silence and faint room tone, where whisper is prone to answer "Thank you."
+        let silence = vec![0.0; 16000 * 10];
+        let room_tone: Vec<f32> = (0..16000 * 10).map(|i| ((i * 7919 % 1000) as f32 / 1000.0 - 0.5) * 0.002).collect();
+        for clip in [silence, room_tone] {
+            let output = engine.transcribe(&clip, 16000, 1, "test").await.unwrap();
+            assert!(output.text.trim().is_empty(), "phantom text: {:?}", output.text);
+        }
+    }
+
+    /// Never finishes, like a network engine whose server stopped answering
+    struct HangingEngine;
+
+    impl SttEngine for HangingEngine {
+        fn name(&self) -> &'static str {
+            "hanging"
+        }
+
+        fn transcribe<'a>(
+            &'a self,
+            _audio_data: &'a [f32],
+            _sample_rate: u32,
+            _channels: u16,
+            _device_name: &'a str,
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<EngineOutput>> + Send + 'a>> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_handle_abandons_in_flight_transcription() {
+        let (sender, mut receiver, state_tx, _) =
+            create_comm_channel(Box::new(HangingEngine), Vec::new(), VadEngineEnum::None, None, &None, SttOptions::default()).unwrap();
+        let stop = StopHandle::new(state_tx);
+
+        sender
+            .send(AudioInput {
+                data: vec![0.1; 16000],
+                sample_rate: 16000,
+                channels: 1,
+                device: "test".to_string(),
+                peak: 0.1,
+                rms: 0.1,
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stop.stop();
+        assert!(stop.is_stopped());
+
+        // The hanging engine never returns, the channel closes without a result anyway
+        let closed = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("worker kept waiting on the engine");
+        assert!(closed.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_worker_keeps_draining_after_timeout() {
+        let options = SttOptions {
+            transcribe_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let (sender, mut receiver, _, _) =
+            create_comm_channel(Box::new(HangingEngine), Vec::new(), VadEngineEnum::None, None, &None, options).unwrap();
+
+        for _ in 0..2 {
+            sender
+                .send(AudioInput {
+                    data: vec![0.1; 16000],
+                    sample_rate: 16000,
+                    channels: 1,
+                    device: "test".to_string(),
+                    peak: 0.1,
+                    rms: 0.1,
+                })
+                .await
+                .unwrap();
+        }
+
+        for _ in 0..2 {
+            let result = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+                .await
+                .expect("worker stalled")
+                .unwrap();
+            assert!(result.error.unwrap().contains("timed out"));
+        }
+    }
+
+    /// Answers every chunk with the same text right away
+    struct FixedEngine;
+
+    impl SttEngine for FixedEngine {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn transcribe<'a>(
+            &'a self,
+            _audio_data: &'a [f32],
+            _sample_rate: u32,
+            _channels: u16,
+            _device_name: &'a str,
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<EngineOutput>> + Send + 'a>> {
+            Box::pin(async { Ok(EngineOutput::from("hello".to_string())) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_processed_chunks() {
+        let (sender, mut receiver, _, _, status_rx) = create_comm_channel_with_status(
+            Box::new(FixedEngine),
+            Vec::new(),
+            VadEngineEnum::None,
+            None,
+            &None,
+            SttOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(status_rx.borrow().engine, "fixed");
+
+        for device in ["mic", "system"] {
+            sender
+                .send(AudioInput {
+                    data: vec![0.1; 16000],
+                    sample_rate: 16000,
+                    channels: 1,
+                    device: device.to_string(),
+                    peak: 0.1,
+                    rms: 0.1,
+                })
+                .await
+                .unwrap();
+        }
+        for _ in 0..2 {
+            let result = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+                .await
+                .expect("worker stalled")
+                .unwrap();
+            assert_eq!(result.transcription.as_deref(), Some("hello"));
+        }
+
+        let status = status_rx.borrow().clone();
+        assert_eq!(status.inputs_processed, 2);
+        assert_eq!(status.inputs_queued, 0);
+        assert!(status.last_latency.is_some());
+    }
+
+    /// Hangs on the chunks of the "stalled" device, answers the others right away
+    struct StallingEngine;
+
+    impl SttEngine for StallingEngine {
+        fn name(&self) -> &'static str {
+            "stalling"
+        }
+
+        fn transcribe<'a>(
+            &'a self,
+            _audio_data: &'a [f32],
+            _sample_rate: u32,
+            _channels: u16,
+            device_name: &'a str,
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<EngineOutput>> + Send + 'a>> {
+            Box::pin(async move {
+                if device_name == "stalled" {
+                    std::future::pending::<()>().await;
+                }
+                Ok(EngineOutput::from("hello".to_string()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stalled_device_does_not_block_others() {
+        let options = SttOptions {
+            max_concurrent_transcriptions: 2,
+            input_queue_capacity: 1,
+            ..Default::default()
+        };
+        let (sender, mut receiver, _, _) =
+            create_comm_channel(Box::new(StallingEngine), Vec::new(), VadEngineEnum::None, None, &None, options).unwrap();
+
+        // More than the stalled device's worker and queue hold
+        for device in ["stalled", "stalled", "stalled", "stalled", "live"] {
+            let input = AudioInput {
+                data: vec![0.1; 16000],
+                sample_rate: 16000,
+                channels: 1,
+                device: device.to_string(),
+                peak: 0.1,
+                rms: 0.1,
+            };
+            tokio::time::timeout(Duration::from_secs(5), sender.send(input))
+                .await
+                .expect("input queue stalled")
+                .unwrap();
+        }
+
+        let result = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("the stalled device held up the others")
+            .unwrap();
+        assert_eq!(result.input.device, "live");
+        assert_eq!(result.transcription.as_deref(), Some("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_perform_stt_errors_are_typed() {
+        let input = |value: f32| AudioInput {
+            data: vec![value; 16000],
+            sample_rate: 16000,
+            channels: 1,
+            device: "test".to_string(),
+            peak: value,
+            rms: value,
+        };
+
+        let mut vad = create_vad_engine(VadEngineEnum::WebRtc { aggressiveness: None }).unwrap();
+        let silent = perform_stt(&input(0.0), &HangingEngine, &[], vad.as_mut(), &None, &SttOptions::default(), None).await;
+        assert!(matches!(silent, Err(SttError::NoSpeech)));
+
+        let mut vad = create_vad_engine(VadEngineEnum::None).unwrap();
+        let options = SttOptions {
+            transcribe_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let timed_out = perform_stt(&input(0.1), &HangingEngine, &[], vad.as_mut(), &None, &options, None).await;
+        match timed_out {
+            Err(SttError::EngineFailed { engine, .. }) => assert_eq!(engine, "hanging"),
+            other => panic!("expected EngineFailed, got {:?}", other.map(|output| output.text)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_chain_stops_at_first_success() {
+        let input = AudioInput {
+            data: vec![0.1; 16000],
+            sample_rate: 16000,
+            channels: 1,
+            device: "test".to_string(),
+            peak: 0.1,
+            rms: 0.1,
+        };
+        let options = SttOptions {
+            transcribe_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let mut vad = create_vad_engine(VadEngineEnum::None).unwrap();
+
+        let output = perform_stt(&input, &HangingEngine, &[&HangingEngine, &FixedEngine, &HangingEngine], vad.as_mut(), &None, &options, None)
+            .await
+            .unwrap();
+        assert_eq!(output.text, "hello");
+        assert_eq!(output.engine, "fixed");
+        assert!(output.used_fallback);
+    }
+
+    #[tokio::test]
+    async fn test_recording_saved_as_wav_without_ffmpeg() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = SttOptions {
+            output_format: AudioFormat::Mp4,
+            ffmpeg: FfmpegOptions {
+                binary: Some(dir.path().join("no-ffmpeg-here")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let input = AudioInput {
+            data: vec![0.1; 16000],
+            sample_rate: 16000,
+            channels: 1,
+            device: "test".to_string(),
+            peak: 0.1,
+            rms: 0.1,
+        };
+        let mut vad = create_vad_engine(VadEngineEnum::None).unwrap();
+        let output_path = Some(dir.path().to_path_buf());
+
+        let output = perform_stt(&input, &FixedEngine, &[], vad.as_mut(), &output_path, &options, None)
+            .await
+            .unwrap();
+        assert_eq!(output.text, "hello");
+        let path = output.path.unwrap();
+        assert!(path.ends_with(".wav"));
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 16000);
+    }
+
+    #[tokio::test]
+    async fn test_max_silence_finishes_after_consecutive_silent_chunks() {
+        let options = SttOptions {
+            max_silence: Some(Duration::from_secs(2)),
+            ..Default::default()
+        };
+        let (sender, mut receiver, _, state_rx) = create_comm_channel(
+            Box::new(HangingEngine),
+            Vec::new(),
+            VadEngineEnum::WebRtc { aggressiveness: None },
+            None,
+            &None,
+            options,
+        )
+        .unwrap();
+
+        // One second of silence at a time, the engine is never reached
+        for expected_finished in [false, true] {
+            sender
+                .send(AudioInput {
+                    data: vec![0.0; 16000],
+                    sample_rate: 16000,
+                    channels: 1,
+                    device: "test".to_string(),
+                    peak: 0.0,
+                    rms: 0.0,
+                })
+                .await
+                .unwrap();
+            let result = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+                .await
+                .expect("worker stalled")
+                .unwrap();
+            assert!(result.error.is_some());
+            let finished = *state_rx.borrow() == RecordingState::RecordingFinished;
+            assert_eq!(finished, expected_finished);
+        }
+    }
+}
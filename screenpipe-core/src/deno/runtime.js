@@ -5,42 +5,42 @@ function argsToMessage(...args) {
     return args.map((arg) => JSON.stringify(arg)).join(" ");
 }
 
-const sendLog = async (level, ...args) => {
+// Printed locally and handed to the host, which forwards it to the app's /log endpoint
+const sendLog = (level, ...args) => {
     const message = argsToMessage(...args);
-    const logApiUrl = process.env.SCREENPIPE_LOG_API_URL || "http://localhost:11435/log";
-    const pipeId = globalThis.metadata.id || "unknown";
-
-    try {
-        await ops.op_fetch(logApiUrl, {
-            method: "POST",
-            headers: {
-                "Content-Type": "application/json",
-            },
-            body: JSON.stringify({
-                pipeId,
-                level,
-                message,
-                timestamp: new Date().toISOString(),
-            }),
-        });
-    } catch (error) {
-        // core.print(`[js][error]: Failed to send log: ${error}\n`, true);
-    }
+    core.print(`[js][${level}]: ${message}\n`, level === "error");
+    ops.op_log(level, message);
 };
 
 const console = {
-    log: (...args) => {
-        core.print(`[js][info]: ${argsToMessage(...args)}\n`, false);
-        // sendLog("info", ...args);
-    },
-    error: (...args) => {
-        core.print(`[js][error]: ${argsToMessage(...args)}\n`, true);
-        // sendLog("error", ...args);
-    },
+    log: (...args) => sendLog("info", ...args),
+    info: (...args) => sendLog("info", ...args),
+    warn: (...args) => sendLog("warn", ...args),
+    error: (...args) => sendLog("error", ...args),
+    debug: (...args) => sendLog("debug", ...args),
 };
 
 globalThis.console = console;
 
+const BASE64_ALPHABET = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+function decodeBase64(base64) {
+    const clean = base64.replace(/=+$/, "");
+    const bytes = new Uint8Array(Math.floor((clean.length * 3) / 4));
+    let buffer = 0;
+    let bits = 0;
+    let index = 0;
+    for (const char of clean) {
+        buffer = (buffer << 6) | BASE64_ALPHABET.indexOf(char);
+        bits += 6;
+        if (bits >= 8) {
+            bits -= 8;
+            bytes[index++] = (buffer >> bits) & 0xff;
+        }
+    }
+    return bytes;
+}
+
 const pipe = {
     readFile: (path) => {
         return ops.op_read_file(path);
@@ -61,14 +61,24 @@ const pipe = {
     },
     fetch: async (url, options) => {
         try {
+            // options.responseType: "text" (default) or "binary", for images, models, ...
+            // options.timeout: milliseconds before the fetch rejects, none by default
             const responseString = await ops.op_fetch(url, options);
             const response = JSON.parse(responseString);
+            const binary = response.base64 !== undefined;
             return {
-                ok: response.status >= 200 && response.status < 300,
+                ok: response.ok,
+                redirected: response.redirected,
+                url: response.url,
                 status: response.status,
                 statusText: response.statusText,
                 headers: response.headers, // Use the headers directly without wrapping in Headers object
-                text: async () => response.text,
+                text: async () => (binary ? core.decode(decodeBase64(response.base64)) : response.text),
+                // Base64 of the body, binary responses only
+                base64: async () => response.base64,
+                arrayBuffer: async () => binary
+                    ? decodeBase64(response.base64).buffer
+                    : core.encode(response.text).buffer,
                 json: async () => {
                     try {
                         return JSON.parse(response.text);
@@ -83,25 +93,45 @@ const pipe = {
             throw error;
         }
     },
+    // Handed to the host, which shows it like the app's /notify endpoint does. Rate limited,
+    // resolves to false when the notification was not sent
     sendNotification: async ({ title, body }) => {
-        // try to fetch this url, if not live do try to send the notification to the server
-        const notificationApiUrl = process.env.SCREENPIPE_SERVER_URL || "http://localhost:11435";
-
         try {
-            const response = await ops.op_fetch(notificationApiUrl + "/notify", {
-                headers: {
-                    "Content-Type": "application/json",
-                },
-                method: "POST",
-                body: JSON.stringify({ title, body })
-            });
-            console.log("Notification sent:", response);
-            return JSON.parse(response);
+            ops.op_notify(title, body);
+            return true;
         } catch (error) {
-            console.log("Failed to send notification to server, is your notification server running?");
+            console.error("Failed to send notification:", error.message);
             return false;
         }
     },
+    // Hands the result of this run to the host, which gets it back from run_pipe. The last
+    // value set wins
+    setResult: (value) => {
+        ops.op_set_result(value ?? null);
+    },
+    // Marks this run as failed, like throwing but the pipe keeps running until it exits
+    fail: (message) => {
+        ops.op_fail(String(message));
+    },
+    // How screenpipe is set up: { dataDir, version, port, audioEnabled, visionEnabled,
+    // audioTranscriptionEngine, ocrEngine, audioDevices, monitorIds, piiRemoval }. Read-only,
+    // not to be confused with the pipe's own `pipe.config`
+    getScreenpipeConfig: () => {
+        return Object.freeze(ops.op_get_config());
+    },
+    // The `args` the host started this run of the pipe with, as parsed JSON (null if none)
+    getArgs: () => {
+        return ops.op_get_args();
+    },
+    // Transcribes an audio or video file in the pipe directory, see the host's transcriber for
+    // the options. Resolves to { text, words, segments, engine, used_fallback }
+    transcribe: async (path, options = {}) => {
+        return ops.op_transcribe(path, options);
+    },
+    // Read-only SELECT over the screenpipe database, rows come back as objects keyed by column
+    queryDb: async (sql, params = []) => {
+        return ops.op_db_query(sql, params);
+    },
     loadConfig: async () => {
         try {
             console.log("Attempting to load pipe.json");
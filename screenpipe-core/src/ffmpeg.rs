@@ -14,9 +14,22 @@ const EXECUTABLE_NAME: &str = "ffmpeg.exe";
 #[cfg(windows)]
 const CREATE_NO_WINDOW: u32 = 0x08000000;
 
+/// Environment variable pointing at the ffmpeg executable, checked before the search
+pub const FFMPEG_PATH_ENV: &str = "FFMPEG_PATH";
+
 pub fn find_ffmpeg_path() -> Option<PathBuf> {
     debug!("Starting search for ffmpeg executable");
 
+    // An explicit path wins over everything else, e.g. a custom build
+    if let Some(path) = std::env::var_os(FFMPEG_PATH_ENV) {
+        let path = PathBuf::from(path);
+        if path.is_file() {
+            debug!("Found ffmpeg at {}: {:?}", FFMPEG_PATH_ENV, path);
+            return Some(path);
+        }
+        error!("{} is set to {:?}, which is not a file", FFMPEG_PATH_ENV, path);
+    }
+
     // Check if `ffmpeg` is in the PATH environment variable
     if let Ok(path) = which(EXECUTABLE_NAME) {
         debug!("Found ffmpeg in PATH: {:?}", path);
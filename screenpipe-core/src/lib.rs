@@ -1,5 +1,5 @@
 pub mod ffmpeg;
-pub use ffmpeg::find_ffmpeg_path;
+pub use ffmpeg::{find_ffmpeg_path, FFMPEG_PATH_ENV};
 pub mod llm;
 pub use llm::*;
 #[cfg(feature = "pipes")]
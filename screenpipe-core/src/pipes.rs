@@ -1,6 +1,7 @@
 #[allow(clippy::module_inception)]
 #[cfg(feature = "pipes")]
 mod pipes {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
     use deno_ast::MediaType;
     use deno_ast::ParseParams;
     use deno_ast::SourceTextInfo;
@@ -9,23 +10,39 @@ mod pipes {
     use deno_core::op2;
     use deno_core::ModuleLoadResponse;
     use deno_core::ModuleSourceCode;
+    use deno_core::OpState;
     use regex::Regex;
     use reqwest::header::HeaderMap;
     use reqwest::header::HeaderValue;
     use reqwest::header::CONTENT_TYPE;
-    use std::collections::HashMap;
+    use std::cell::RefCell;
+    use std::collections::{HashMap, VecDeque};
     use std::env;
+    use std::fmt;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::net::{IpAddr, SocketAddr};
     use std::path::PathBuf;
     use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+    use tokio::time::Instant;
     use tracing::debug;
 
+    use reqwest::dns::{Addrs, Name, Resolve, Resolving};
     use reqwest::Client;
+    use serde::Serialize;
     use serde_json::Value;
+    use sha2::{Digest, Sha256};
+    use sqlx::sqlite::SqliteConnectOptions;
+    use sqlx::{Column, ConnectOptions, Row, TypeInfo, ValueRef};
+    use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
     use reqwest;
     use std::path::Path;
     use tracing::{error, info};
-    use url::Url;
+    use url::{Host, Url};
 
     // Add this function near the top of the file, after the imports
     fn sanitize_pipe_name(name: &str) -> String {
@@ -33,20 +50,901 @@ mod pipes {
         re.replace_all(name, "-").to_string()
     }
 
+    /// Per-pipe permissions, set by the host when it starts a pipe.
+    #[derive(Clone, Debug, Default)]
+    pub struct PipeOptions {
+        /// Let the file ops reach paths outside the pipe's own directory; only for trusted pipes
+        pub allow_full_fs: bool,
+        /// Which hosts the fetch ops may reach
+        pub network: NetworkPolicy,
+        /// Stop the pipe once it has run this long; pipes usually loop forever, so no limit
+        /// when `None`
+        pub max_runtime: Option<Duration>,
+        /// Receives what the pipe logs, see `forward_pipe_logs`
+        pub log_sender: Option<UnboundedSender<PipeLogEntry>>,
+        /// Database `op_db_query` reads, `<screenpipe_dir>/db.sqlite` when `None`
+        pub db_path: Option<PathBuf>,
+        /// Receives the pipe's notifications, see `forward_pipe_notifications`
+        pub notification_sender: Option<UnboundedSender<PipeNotification>>,
+        /// How many notifications the pipe may send before `op_notify` starts failing
+        pub notification_rate_limit: RateLimit,
+        /// Input for this run of the pipe, e.g. a date range to summarize. The pipe reads it as
+        /// `globalThis.metadata.args` or through `pipe.getArgs()`; `null` when not set.
+        pub args: Value,
+        /// Runs `op_transcribe`; pipes can't transcribe when `None`
+        pub transcriber: Option<Arc<dyn PipeTranscriber>>,
+        /// Let the pipe's `.env` file override variables of the same name from the process
+        /// environment, which wins by default
+        pub dotenv_overrides_env: bool,
+        /// How screenpipe is set up, for `op_get_config`
+        pub config: PipeHostConfig,
+    }
+
+    /// The screenpipe configuration a pipe reads with `pipe.getScreenpipeConfig()`, instead of
+    /// parsing `process.env`. A copy, changing it in the pipe changes nothing on the host:
+    ///
+    /// ```json
+    /// {
+    ///   "dataDir": "/home/me/.screenpipe",
+    ///   "version": "0.1.98",
+    ///   "port": 3030,
+    ///   "audioEnabled": true,
+    ///   "visionEnabled": true,
+    ///   "audioTranscriptionEngine": "whisper-large",
+    ///   "ocrEngine": "tesseract",
+    ///   "audioDevices": ["MacBook Pro Microphone (input)"],
+    ///   "monitorIds": [1],
+    ///   "piiRemoval": false
+    /// }
+    /// ```
+    #[derive(Clone, Debug, Default, PartialEq, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct PipeHostConfig {
+        /// Always the `screenpipe_dir` the pipe runs with, `run_js` sets it
+        pub data_dir: PathBuf,
+        pub version: String,
+        /// Port of the screenpipe API
+        pub port: Option<u16>,
+        pub audio_enabled: bool,
+        pub vision_enabled: bool,
+        /// As passed on the command line, e.g. `whisper-large`
+        pub audio_transcription_engine: Option<String>,
+        pub ocr_engine: Option<String>,
+        /// Recorded audio devices, empty when recording the defaults
+        pub audio_devices: Vec<String>,
+        /// Recorded monitors, empty when recording every monitor
+        pub monitor_ids: Vec<u32>,
+        pub pii_removal: bool,
+    }
+
+    /// Transcribes audio files for pipes' `op_transcribe`. Provided by the host, since the STT
+    /// engines live in `screenpipe-audio`, which depends on this crate.
+    pub trait PipeTranscriber: Send + Sync + fmt::Debug {
+        /// Transcribes the file at `path`, already checked against the pipe's sandbox, and
+        /// returns the transcript as JSON. `options` are what the pipe passed, `null` if none.
+        fn transcribe<'a>(
+            &'a self,
+            path: &'a Path,
+            options: &'a Value,
+        ) -> Pin<Box<dyn Future<Output = anyhow::Result<Value>> + Send + 'a>>;
+    }
+
+    /// Longest a single `op_transcribe` may take unless the pipe passes a `timeoutMs`.
+    const TRANSCRIBE_TIMEOUT: Duration = Duration::from_secs(600);
+
+    /// The host's transcriber, for `op_transcribe`.
+    struct PipeTranscriberSlot(Option<Arc<dyn PipeTranscriber>>);
+
+    /// The `args` of the running pipe, for `op_get_args`.
+    struct PipeArgs(Value);
+
+    /// A notification requested by a pipe. Serializes like the app's `NotificationPayload`,
+    /// `{ "title": "...", "body": "..." }`.
+    #[derive(Clone, Debug, Serialize)]
+    pub struct PipeNotification {
+        pub title: String,
+        pub body: String,
+    }
+
+    /// At most `max` events in any `per` window.
+    #[derive(Clone, Copy, Debug)]
+    pub struct RateLimit {
+        pub max: usize,
+        pub per: Duration,
+    }
+
+    impl Default for RateLimit {
+        fn default() -> Self {
+            Self {
+                max: 5,
+                per: Duration::from_secs(60),
+            }
+        }
+    }
+
+    /// Where `op_notify` sends notifications, and when it last did.
+    struct PipeNotifier {
+        sender: Option<UnboundedSender<PipeNotification>>,
+        limit: RateLimit,
+        sent: VecDeque<Instant>,
+    }
+
+    impl PipeNotifier {
+        fn notify(&mut self, notification: PipeNotification) -> anyhow::Result<()> {
+            let Some(sender) = &self.sender else {
+                anyhow::bail!("Notifications are not available to this pipe");
+            };
+            let now = Instant::now();
+            while self
+                .sent
+                .front()
+                .is_some_and(|sent| now.duration_since(*sent) >= self.limit.per)
+            {
+                self.sent.pop_front();
+            }
+            if self.sent.len() >= self.limit.max {
+                anyhow::bail!(
+                    "Notification rate limit reached: at most {} per {:?}",
+                    self.limit.max,
+                    self.limit.per
+                );
+            }
+            sender
+                .send(notification)
+                .map_err(|_| anyhow::anyhow!("Notification host is gone"))?;
+            self.sent.push_back(now);
+            Ok(())
+        }
+    }
+
+    /// The screenpipe database, opened read-only for each query.
+    struct PipeDatabase(PathBuf);
+
+    /// Only single SELECT (or WITH ... SELECT) statements; the connection is read-only as well.
+    fn ensure_select_statement(sql: &str) -> anyhow::Result<()> {
+        let statement = sql.trim().trim_end_matches(';').trim_end();
+        if statement.contains(';') {
+            anyhow::bail!("Only a single statement can be run per query");
+        }
+        let keyword = statement
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if keyword != "select" && keyword != "with" {
+            anyhow::bail!("Only SELECT queries are allowed, got '{}'", keyword);
+        }
+        Ok(())
+    }
+
+    fn sqlite_value_to_json(row: &sqlx::sqlite::SqliteRow, index: usize) -> anyhow::Result<Value> {
+        let raw = row.try_get_raw(index)?;
+        if raw.is_null() {
+            return Ok(Value::Null);
+        }
+        let value = match raw.type_info().name() {
+            "INTEGER" => Value::from(row.try_get::<i64, _>(index)?),
+            "REAL" => Value::from(row.try_get::<f64, _>(index)?),
+            "BLOB" => Value::from(row.try_get::<Vec<u8>, _>(index)?),
+            _ => Value::from(row.try_get::<String, _>(index)?),
+        };
+        Ok(value)
+    }
+
+    /// Error of `run_js` and `run_pipe` when the pipe ran but failed: it threw, or called
+    /// `pipe.fail(message)`. Any other error means the host couldn't run the pipe (or stopped
+    /// it), tell them apart with `error.downcast_ref::<PipeFailure>()`.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct PipeFailure {
+        pub message: String,
+    }
+
+    impl fmt::Display for PipeFailure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Pipe failed: {}", self.message)
+        }
+    }
+
+    impl std::error::Error for PipeFailure {}
+
+    /// What the running pipe reported through `pipe.setResult` and `pipe.fail`.
+    #[derive(Default)]
+    struct PipeResult {
+        value: Value,
+        failure: Option<String>,
+    }
+
+    /// A line logged by a pipe, shaped like the entries the app's `/log` endpoint takes.
+    #[derive(Clone, Debug, Serialize)]
+    pub struct PipeLogEntry {
+        pub pipe_id: String,
+        pub level: String,
+        pub message: String,
+        pub timestamp: String,
+    }
+
+    /// Where `op_log` sends a pipe's log entries.
+    struct PipeLogSink {
+        pipe_id: String,
+        sender: Option<UnboundedSender<PipeLogEntry>>,
+    }
+
+    /// POSTs the log entries of pipes to `log_url`, the app's `/log` endpoint, until every
+    /// sender is dropped. `auth_token` is sent as a bearer token when the endpoint requires one.
+    pub async fn forward_pipe_logs(
+        receiver: UnboundedReceiver<PipeLogEntry>,
+        log_url: String,
+        auth_token: Option<String>,
+    ) {
+        post_each(receiver, log_url, auth_token).await
+    }
+
+    /// POSTs the notifications of pipes to `notify_url`, the app's `/notify` endpoint, which
+    /// shows them like any other screenpipe notification.
+    pub async fn forward_pipe_notifications(
+        receiver: UnboundedReceiver<PipeNotification>,
+        notify_url: String,
+        auth_token: Option<String>,
+    ) {
+        post_each(receiver, notify_url, auth_token).await
+    }
+
+    async fn post_each<T: Serialize>(
+        mut receiver: UnboundedReceiver<T>,
+        url: String,
+        auth_token: Option<String>,
+    ) {
+        let client = Client::new();
+        let mut reachable = true;
+        while let Some(item) = receiver.recv().await {
+            let mut request = client.post(&url).json(&item);
+            if let Some(token) = &auth_token {
+                request = request.bearer_auth(token);
+            }
+            match request.send().await {
+                Ok(response) if response.status() == reqwest::StatusCode::UNAUTHORIZED => {
+                    if reachable {
+                        debug!("{} rejected the pipe output, check SCREENPIPE_SERVER_TOKEN", url);
+                        reachable = false;
+                    }
+                }
+                Ok(_) => reachable = true,
+                // Only reported once until it comes back, the app isn't always running
+                Err(e) if reachable => {
+                    debug!("Failed to forward pipe output to {}: {}", url, e);
+                    reachable = false;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// When the pipe's `max_runtime` runs out, kept in the op state for the timer ops.
+    struct PipeDeadline(Option<Instant>);
+
+    /// Hosts a pipe's fetch ops may reach. Private, loopback and link-local addresses are denied
+    /// unless listed in `allowed_private_hosts`, so a pipe can't reach internal services or
+    /// cloud metadata endpoints.
+    ///
+    /// Entries are a host (`example.com`) or a host and port (`localhost:3030`).
+    #[derive(Clone, Debug, Default)]
+    pub struct NetworkPolicy {
+        /// Only these hosts may be fetched; any public host when `None`
+        pub allowed_hosts: Option<Vec<String>>,
+        /// Private hosts the pipe may still fetch, e.g. the local screenpipe server
+        pub allowed_private_hosts: Vec<String>,
+    }
+
+    /// The names a pipe may use for this machine.
+    const LOOPBACK_HOSTS: [&str; 3] = ["localhost", "127.0.0.1", "[::1]"];
+
+    impl NetworkPolicy {
+        /// Lets the pipe fetch the private `entry`, a host or a host and port. A loopback host is
+        /// allowed under all of `localhost`, `127.0.0.1` and `[::1]`.
+        pub fn allow_private_host(&mut self, entry: &str) {
+            let (host, port) = match entry.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                    (host, Some(port))
+                }
+                _ => (entry, None),
+            };
+            let hosts = if LOOPBACK_HOSTS
+                .iter()
+                .any(|loopback| loopback.eq_ignore_ascii_case(host))
+            {
+                LOOPBACK_HOSTS.to_vec()
+            } else {
+                vec![host]
+            };
+
+            for host in hosts {
+                let entry = match port {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host.to_string(),
+                };
+                if !self.allowed_private_hosts.contains(&entry) {
+                    self.allowed_private_hosts.push(entry);
+                }
+            }
+        }
+
+        fn is_listed(entries: &[String], host: &str, port: Option<u16>) -> bool {
+            entries.iter().any(|entry| match entry.rsplit_once(':') {
+                Some((entry_host, entry_port))
+                    if !entry_host.is_empty() && entry_port.parse::<u16>().is_ok() =>
+                {
+                    entry_host.eq_ignore_ascii_case(host)
+                        && port == entry_port.parse::<u16>().ok()
+                }
+                _ => entry.eq_ignore_ascii_case(host),
+            })
+        }
+
+        /// Whether `host` is listed under any port.
+        fn is_listed_host(entries: &[String], host: &str) -> bool {
+            entries.iter().any(|entry| {
+                let entry_host = match entry.rsplit_once(':') {
+                    Some((entry_host, entry_port))
+                        if !entry_host.is_empty() && entry_port.parse::<u16>().is_ok() =>
+                    {
+                        entry_host
+                    }
+                    _ => entry.as_str(),
+                };
+                entry_host.eq_ignore_ascii_case(host)
+            })
+        }
+
+        /// The checks that don't need a DNS lookup; also applied to redirects.
+        fn check_without_lookup(&self, url: &Url) -> anyhow::Result<()> {
+            if !matches!(url.scheme(), "http" | "https") {
+                anyhow::bail!("Unsupported URL scheme '{}'", url.scheme());
+            }
+            let host = url
+                .host_str()
+                .ok_or_else(|| anyhow::anyhow!("URL has no host"))?;
+            let port = url.port_or_known_default();
+
+            if let Some(allowed_hosts) = &self.allowed_hosts {
+                if !Self::is_listed(allowed_hosts, host, port)
+                    && !Self::is_listed(&self.allowed_private_hosts, host, port)
+                {
+                    anyhow::bail!("Host '{}' is not in the pipe's allowlist", host);
+                }
+            }
+            if Self::is_listed(&self.allowed_private_hosts, host, port) {
+                return Ok(());
+            }
+            // The resolver only sees the name, so it lets a listed host reach private addresses
+            // on any port
+            if Self::is_listed_host(&self.allowed_private_hosts, host) {
+                anyhow::bail!("Host '{}' is only allowed on its listed ports", host);
+            }
+            let literal = match url.host() {
+                Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+                Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+                _ => None,
+            };
+            if host.eq_ignore_ascii_case("localhost") || literal.is_some_and(is_private_address) {
+                anyhow::bail!("Host '{}' is a private address", host);
+            }
+            Ok(())
+        }
+
+        /// Checks `url` against the policy, resolving its host to catch names that point at
+        /// private addresses.
+        pub async fn check(&self, url: &str) -> anyhow::Result<()> {
+            let parsed = Url::parse(url)?;
+            self.check_without_lookup(&parsed)
+                .map_err(|e| anyhow::anyhow!("Fetch of '{}' blocked: {}", url, e))?;
+
+            let host = parsed.host_str().unwrap_or_default();
+            let port = parsed.port_or_known_default().unwrap_or(80);
+            if Self::is_listed(&self.allowed_private_hosts, host, Some(port)) {
+                return Ok(());
+            }
+            if let Some(Host::Domain(domain)) = parsed.host() {
+                for addr in tokio::net::lookup_host((domain, port)).await? {
+                    if is_private_address(addr.ip()) {
+                        anyhow::bail!(
+                            "Fetch of '{}' blocked: '{}' resolves to the private address {}",
+                            url,
+                            domain,
+                            addr.ip()
+                        );
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        /// A client whose redirects are held to the same policy, and whose every connection
+        /// goes through a resolver that drops private addresses.
+        fn client(&self) -> anyhow::Result<Client> {
+            let policy = self.clone();
+            let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= 10 {
+                    return attempt.error("too many redirects");
+                }
+                match policy.check_without_lookup(attempt.url()) {
+                    Ok(()) => attempt.follow(),
+                    Err(e) => attempt.error(format!("redirect blocked: {}", e)),
+                }
+            });
+            let resolver = PolicyResolver {
+                allowed_private_hosts: self.allowed_private_hosts.clone(),
+            };
+            Ok(Client::builder()
+                .redirect(redirect)
+                .dns_resolver(Arc::new(resolver))
+                .build()?)
+        }
+    }
+
+    /// Resolves the hosts a pipe connects to, dropping private addresses unless the host is
+    /// allowed them. `NetworkPolicy::check` resolves the host once; without this a name could be
+    /// rebound to an internal address before reqwest resolves it again, or reached by a redirect.
+    struct PolicyResolver {
+        allowed_private_hosts: Vec<String>,
+    }
+
+    impl Resolve for PolicyResolver {
+        fn resolve(&self, name: Name) -> Resolving {
+            let allow_private =
+                NetworkPolicy::is_listed_host(&self.allowed_private_hosts, name.as_str());
+            Box::pin(async move {
+                let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                    .await?
+                    .filter(|addr| allow_private || !is_private_address(addr.ip()))
+                    .collect();
+                if addrs.is_empty() {
+                    return Err(format!("'{}' resolves to no public address", name.as_str()).into());
+                }
+                let addrs: Addrs = Box::new(addrs.into_iter());
+                Ok(addrs)
+            })
+        }
+    }
+
+    fn is_private_address(ip: IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ip) => {
+                let [a, b, ..] = ip.octets();
+                ip.is_private()
+                    || ip.is_loopback()
+                    || ip.is_link_local()
+                    || ip.is_unspecified()
+                    || ip.is_broadcast()
+                    // Carrier-grade NAT, 100.64.0.0/10
+                    || (a == 100 && (b & 0xc0) == 64)
+            }
+            IpAddr::V6(ip) => {
+                if let Some(ip) = ip.to_ipv4_mapped() {
+                    return is_private_address(IpAddr::V4(ip));
+                }
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    // Unique local, fc00::/7
+                    || (first & 0xfe00) == 0xfc00
+                    // Link-local, fe80::/10
+                    || (first & 0xffc0) == 0xfe80
+            }
+        }
+    }
+
+    /// Confines the file ops of a pipe to its own directory.
+    #[derive(Clone, Debug)]
+    pub struct FsSandbox {
+        root: PathBuf,
+        allow_full_fs: bool,
+    }
+
+    impl FsSandbox {
+        pub fn new(root: &Path, allow_full_fs: bool) -> anyhow::Result<Self> {
+            let root = root.canonicalize().map_err(|e| {
+                anyhow::anyhow!("Failed to canonicalize pipe directory '{}': {}", root.display(), e)
+            })?;
+            Ok(Self {
+                root,
+                allow_full_fs,
+            })
+        }
+
+        pub fn root(&self) -> &Path {
+            &self.root
+        }
+
+        /// Resolves a path given by the pipe, relative ones against the pipe directory, following
+        /// `..` and symlinks. Fails if the result is outside the pipe directory, unless full
+        /// filesystem access was allowed.
+        pub fn resolve(&self, path: &str) -> anyhow::Result<PathBuf> {
+            let joined = self.root.join(path);
+            let resolved = match joined.canonicalize() {
+                Ok(resolved) => resolved,
+                Err(_) => {
+                    // A file about to be written doesn't exist yet, so resolve its directory
+                    let (Some(parent), Some(file_name)) = (joined.parent(), joined.file_name())
+                    else {
+                        anyhow::bail!("Invalid path '{}'", path);
+                    };
+                    parent
+                        .canonicalize()
+                        .map_err(|e| anyhow::anyhow!("Failed to resolve '{}': {}", path, e))?
+                        .join(file_name)
+                }
+            };
+
+            if !self.allow_full_fs && !resolved.starts_with(&self.root) {
+                anyhow::bail!(
+                    "Access to '{}' denied: outside of the pipe directory {}",
+                    path,
+                    self.root.display()
+                );
+            }
+            Ok(resolved)
+        }
+    }
+
+    /// Which `SCREENPIPE_*` environment variables a pipe sees, from the `env` list of its
+    /// `pipe.json`: exact names, or prefixes ending in `*`:
+    ///
+    /// ```json
+    /// { "env": ["SCREENPIPE_NOTION_TOKEN", "SCREENPIPE_OPENAI_*"] }
+    /// ```
+    ///
+    /// A pipe without the list sees every `SCREENPIPE_*` variable, and `op_get_env` reads any
+    /// variable. With the list, both only reach the `SCREENPIPE_*` variables it names, so a
+    /// third-party pipe can't read the keys meant for another.
+    ///
+    /// The variables in `HOST_ONLY_ENV` are never exposed, whatever the list says.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct PipeEnvScope {
+        allowed: Option<Vec<String>>,
+    }
+
+    /// Kept from pipes: the server token would let a pipe call the app's `/notify` and `/log`
+    /// itself, past the notification rate limit and under another pipe's name.
+    const HOST_ONLY_ENV: [&str; 1] = ["SCREENPIPE_SERVER_TOKEN"];
+
+    impl PipeEnvScope {
+        pub fn new(allowed: Option<Vec<String>>) -> Self {
+            Self { allowed }
+        }
+
+        /// Reads the `env` list of `<pipe_dir>/pipe.json`; no list when the pipe has no
+        /// `pipe.json` or the field is missing.
+        pub fn from_pipe_dir(pipe_dir: &Path) -> anyhow::Result<Self> {
+            let config: Value = match std::fs::read_to_string(pipe_dir.join("pipe.json")) {
+                Ok(config) => serde_json::from_str(&config)?,
+                Err(_) => Value::Null,
+            };
+            let allowed = match config.get("env") {
+                Some(Value::Array(entries)) => entries
+                    .iter()
+                    .map(|entry| {
+                        entry
+                            .as_str()
+                            .map(str::to_string)
+                            .ok_or_else(|| anyhow::anyhow!("pipe.json env entries must be strings"))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()
+                    .map(Some)?,
+                Some(_) => anyhow::bail!("pipe.json env must be a list of variable names"),
+                None => None,
+            };
+            Ok(Self { allowed })
+        }
+
+        /// Whether `key` is injected into the pipe's `process.env`.
+        pub fn allows(&self, key: &str) -> bool {
+            if !key.starts_with("SCREENPIPE_") || HOST_ONLY_ENV.contains(&key) {
+                return false;
+            }
+            match &self.allowed {
+                None => true,
+                Some(allowed) => allowed.iter().any(|entry| match entry.strip_suffix('*') {
+                    Some(prefix) => key.starts_with(prefix),
+                    None => key == entry,
+                }),
+            }
+        }
+
+        /// Whether `op_get_env` may read `key`.
+        fn can_read(&self, key: &str) -> bool {
+            !HOST_ONLY_ENV.contains(&key) && (self.allowed.is_none() || self.allows(key))
+        }
+    }
+
+    /// The variables of a pipe's `.env` file, see `parse_dotenv`. Only that pipe sees them, in
+    /// `process.env` and through `op_get_env`.
+    struct PipeDotenv {
+        vars: HashMap<String, String>,
+        overrides_env: bool,
+    }
+
+    impl PipeDotenv {
+        /// Reads `<pipe_dir>/.env`; no variables when the pipe has none.
+        fn load(pipe_dir: &Path, overrides_env: bool) -> anyhow::Result<Self> {
+            let vars = match std::fs::read_to_string(pipe_dir.join(".env")) {
+                // A variable set twice keeps its last value
+                Ok(contents) => parse_dotenv(&contents)
+                    .map_err(|e| anyhow::anyhow!("Invalid .env file: {}", e))?
+                    .into_iter()
+                    .collect(),
+                Err(_) => HashMap::new(),
+            };
+            Ok(Self {
+                vars,
+                overrides_env,
+            })
+        }
+    }
+
+    /// Parses a dotenv file: `KEY=value` lines, optionally prefixed with `export`, and `#`
+    /// comments. Values may be single quoted (taken literally) or double quoted (expanding `\n`,
+    /// `\r`, `\t`, `\"` and `\\`), both can span lines. Unquoted values end at a `#` after
+    /// whitespace.
+    pub fn parse_dotenv(contents: &str) -> anyhow::Result<Vec<(String, String)>> {
+        let mut vars = Vec::new();
+        let mut lines = contents.lines().enumerate();
+        while let Some((index, line)) = lines.next() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line = line
+                .strip_prefix("export ")
+                .map(str::trim_start)
+                .unwrap_or(line);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("line {}: expected KEY=value", line_number))?;
+            let key = key.trim();
+            if key.is_empty()
+                || key.starts_with(|c: char| c.is_ascii_digit())
+                || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                anyhow::bail!("line {}: invalid variable name '{}'", line_number, key);
+            }
+
+            let value = value.trim_start();
+            let value = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let mut raw = value[1..].to_string();
+                    let end = loop {
+                        if let Some(end) = closing_quote(&raw, quote) {
+                            break end;
+                        }
+                        match lines.next() {
+                            Some((_, next)) => {
+                                raw.push('\n');
+                                raw.push_str(next);
+                            }
+                            None => anyhow::bail!(
+                                "line {}: unterminated quoted value of {}",
+                                line_number,
+                                key
+                            ),
+                        }
+                    };
+                    let rest = raw[end + 1..].trim();
+                    if !rest.is_empty() && !rest.starts_with('#') {
+                        anyhow::bail!("line {}: unexpected text after the value of {}", line_number, key);
+                    }
+                    if quote == '"' {
+                        unescape_dotenv(&raw[..end])
+                    } else {
+                        raw[..end].to_string()
+                    }
+                }
+                _ => {
+                    let end = value
+                        .char_indices()
+                        .find(|&(i, c)| c == '#' && (i == 0 || value[..i].ends_with(char::is_whitespace)))
+                        .map(|(i, _)| i)
+                        .unwrap_or(value.len());
+                    value[..end].trim_end().to_string()
+                }
+            };
+            vars.push((key.to_string(), value));
+        }
+        Ok(vars)
+    }
+
+    /// Byte index of the quote closing a value that started with `quote`, skipping escaped
+    /// quotes in double quoted values.
+    fn closing_quote(raw: &str, quote: char) -> Option<usize> {
+        let mut escaped = false;
+        for (i, c) in raw.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && quote == '"' {
+                escaped = true;
+            } else if c == quote {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn unescape_dotenv(raw: &str) -> String {
+        let mut value = String::with_capacity(raw.len());
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('r') => value.push('\r'),
+                Some('t') => value.push('\t'),
+                Some(other) => value.push(other),
+                None => value.push('\\'),
+            }
+        }
+        value
+    }
+
+    #[op2]
+    fn op_set_result(state: &mut OpState, #[serde] value: Value) {
+        state.borrow_mut::<PipeResult>().value = value;
+    }
+
+    #[op2]
+    fn op_fail(state: &mut OpState, #[string] message: String) {
+        state.borrow_mut::<PipeResult>().failure = Some(message);
+    }
+
+    #[op2]
+    fn op_log(state: &mut OpState, #[string] level: String, #[string] message: String) {
+        let sink = state.borrow::<PipeLogSink>();
+        if let Some(sender) = &sink.sender {
+            let _ = sender.send(PipeLogEntry {
+                pipe_id: sink.pipe_id.clone(),
+                level,
+                message,
+                timestamp: chrono::Utc::now().to_rfc3339(),
+            });
+        }
+    }
+
+    #[op2(async)]
+    #[serde]
+    async fn op_db_query(
+        state: Rc<RefCell<OpState>>,
+        #[string] sql: String,
+        #[serde] params: Option<Vec<Value>>,
+    ) -> anyhow::Result<Value, AnyError> {
+        ensure_select_statement(&sql)?;
+        let db_path = state.borrow().borrow::<PipeDatabase>().0.clone();
+        let mut connection = SqliteConnectOptions::new()
+            .filename(&db_path)
+            .read_only(true)
+            .connect()
+            .await
+            .map_err(|e| {
+                error!("Failed to open database '{}': {}", db_path.display(), e);
+                AnyError::from(e)
+            })?;
+
+        let mut query = sqlx::query(&sql);
+        for param in params.unwrap_or_default() {
+            query = match param {
+                Value::Null => query.bind(None::<String>),
+                Value::Bool(b) => query.bind(b),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => query.bind(i),
+                    None => query.bind(n.as_f64()),
+                },
+                Value::String(s) => query.bind(s),
+                other => query.bind(other.to_string()),
+            };
+        }
+        let rows = query.fetch_all(&mut connection).await?;
+
+        let mut results = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let mut object = serde_json::Map::new();
+            for column in row.columns() {
+                object.insert(
+                    column.name().to_string(),
+                    sqlite_value_to_json(row, column.ordinal())?,
+                );
+            }
+            results.push(Value::Object(object));
+        }
+        Ok(Value::Array(results))
+    }
+
+    #[op2]
+    fn op_notify(
+        state: &mut OpState,
+        #[string] title: String,
+        #[string] body: String,
+    ) -> anyhow::Result<()> {
+        state
+            .borrow_mut::<PipeNotifier>()
+            .notify(PipeNotification { title, body })
+    }
+
+    #[op2(async)]
+    #[serde]
+    async fn op_transcribe(
+        state: Rc<RefCell<OpState>>,
+        #[string] path: String,
+        #[serde] options: Option<Value>,
+    ) -> anyhow::Result<Value, AnyError> {
+        let (full_path, transcriber, deadline) = {
+            let state = state.borrow();
+            (
+                state.borrow::<FsSandbox>().resolve(&path)?,
+                state.borrow::<PipeTranscriberSlot>().0.clone(),
+                state.borrow::<PipeDeadline>().0,
+            )
+        };
+        let transcriber = transcriber
+            .ok_or_else(|| AnyError::msg("Transcription is not available to this pipe"))?;
+        let options = options.unwrap_or(Value::Null);
+
+        let timeout = options
+            .get("timeoutMs")
+            .and_then(Value::as_u64)
+            .map(Duration::from_millis)
+            .unwrap_or(TRANSCRIBE_TIMEOUT);
+        // Never past the pipe's own max runtime
+        let timeout_at = match deadline {
+            Some(deadline) => deadline.min(Instant::now() + timeout),
+            None => Instant::now() + timeout,
+        };
+        tokio::time::timeout_at(timeout_at, transcriber.transcribe(&full_path, &options))
+            .await
+            .map_err(|_| AnyError::msg(format!("Transcription of '{}' timed out", path)))?
+    }
+
+    #[op2]
+    #[serde]
+    fn op_get_args(state: &mut OpState) -> Value {
+        state.borrow::<PipeArgs>().0.clone()
+    }
+
+    #[op2]
+    #[serde]
+    fn op_get_config(state: &mut OpState) -> PipeHostConfig {
+        state.borrow::<PipeHostConfig>().clone()
+    }
+
     #[op2]
     #[string]
-    fn op_get_env(#[string] key: String) -> Option<String> {
-        env::var(&key).ok()
+    fn op_get_env(state: &mut OpState, #[string] key: String) -> Option<String> {
+        let dotenv = state.borrow::<PipeDotenv>();
+        let from_dotenv = dotenv.vars.get(&key).cloned();
+        if dotenv.overrides_env && from_dotenv.is_some() {
+            return from_dotenv;
+        }
+        if !state.borrow::<PipeEnvScope>().can_read(&key) {
+            debug!("Pipe tried to read {}, which its pipe.json env doesn't list", key);
+            return from_dotenv;
+        }
+        env::var(&key).ok().or(from_dotenv)
     }
 
     #[op2(async)]
     #[string]
     async fn op_fetch(
+        state: Rc<RefCell<OpState>>,
         #[string] url: String,
         #[serde] options: Option<Value>,
     ) -> anyhow::Result<String, AnyError> {
-        let client = Client::new();
+        let policy = state.borrow().borrow::<NetworkPolicy>().clone();
+        policy.check(&url).await?;
+        let client = policy.client()?;
         let mut request = client.get(&url);
+        // `text` by default; `binary` returns the body base64-encoded
+        let mut binary = false;
+        let mut timeout = None;
 
         if let Some(opts) = options {
             if let Some(method) = opts.get("method").and_then(|m| m.as_str()) {
@@ -55,6 +953,9 @@ mod pipes {
                     "POST" => client.post(&url),
                     "PUT" => client.put(&url),
                     "DELETE" => client.delete(&url),
+                    "PATCH" => client.patch(&url),
+                    // For requests that only want the status and headers
+                    "HEAD" => client.head(&url),
                     // Add other methods as needed
                     _ => return Err(anyhow::anyhow!("Unsupported HTTP method")),
                 };
@@ -71,37 +972,86 @@ mod pipes {
             if let Some(body) = opts.get("body").and_then(|b| b.as_str()) {
                 request = request.body(body.to_string());
             }
-        }
 
-        let response = match request.send().await {
-            Ok(resp) => resp,
-            Err(e) => return Err(anyhow::anyhow!(e)),
+            binary = match opts.get("responseType").and_then(|t| t.as_str()) {
+                None | Some("text") => false,
+                Some("binary") => true,
+                Some(other) => {
+                    return Err(anyhow::anyhow!("Unsupported responseType '{}'", other))
+                }
+            };
+
+            // Milliseconds the whole request may take, reading the body included
+            if let Some(ms) = opts.get("timeout").filter(|ms| !ms.is_null()) {
+                let ms = ms.as_f64().filter(|ms| *ms > 0.0).ok_or_else(|| {
+                    anyhow::anyhow!("timeout must be a positive number of milliseconds")
+                })?;
+                timeout = Some(Duration::from_millis(ms as u64));
+            }
+        }
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let request_error = |e: reqwest::Error| match timeout {
+            Some(timeout) if e.is_timeout() => {
+                anyhow::anyhow!("Fetch of '{}' timed out after {:?}", url, timeout)
+            }
+            _ => anyhow::anyhow!(e),
         };
 
-        let status = response.status();
-        let headers = response.headers().clone();
-        let text = match response.text().await {
-            Ok(t) => t,
-            Err(e) => return Err(anyhow::anyhow!(e)),
+        let exchange = async {
+            let response = request.send().await.map_err(request_error)?;
+
+            let status = response.status();
+            let headers = response.headers().clone();
+            let final_url = response.url().to_string();
+            let redirected = response.url().as_str() != Url::parse(&url)?.as_str();
+
+            let mut result = serde_json::json!({
+                "ok": status.is_success(),
+                "redirected": redirected,
+                "url": final_url,
+                "status": status.as_u16(),
+                "statusText": status.to_string(),
+                "headers": headers.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+                    .collect::<HashMap<String, String>>(),
+            });
+            if binary {
+                let bytes = response.bytes().await.map_err(request_error)?;
+                result["base64"] = Value::from(BASE64.encode(&bytes));
+            } else {
+                let text = response.text().await.map_err(request_error)?;
+                result["text"] = Value::from(text);
+            }
+            Ok::<_, AnyError>(result)
         };
 
-        let result = serde_json::json!({
-            "status": status.as_u16(),
-            "statusText": status.to_string(),
-            "headers": headers.iter()
-                .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-                .collect::<HashMap<String, String>>(),
-            "text": text,
-        });
+        // Aborted when the pipe runs out of time, so the pipe sees the error instead of hanging
+        // until it is terminated
+        let deadline = state.borrow().borrow::<PipeDeadline>().0;
+        let result = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, exchange)
+                .await
+                .map_err(|_| {
+                    AnyError::msg(format!(
+                        "Fetch of '{}' aborted, the pipe reached its max runtime",
+                        url
+                    ))
+                })??,
+            None => exchange.await?,
+        };
 
         Ok(result.to_string())
     }
 
     #[op2(async)]
     #[string]
-    async fn op_read_file(#[string] path: String) -> anyhow::Result<String, AnyError> {
-        let current_dir = std::env::current_dir()?;
-        let full_path = current_dir.join(path);
+    async fn op_read_file(
+        state: Rc<RefCell<OpState>>,
+        #[string] path: String,
+    ) -> anyhow::Result<String, AnyError> {
+        let full_path = state.borrow().borrow::<FsSandbox>().resolve(&path)?;
         tokio::fs::read_to_string(&full_path).await.map_err(|e| {
             error!("Failed to read file '{}': {}", full_path.display(), e);
             AnyError::from(e)
@@ -111,19 +1061,26 @@ mod pipes {
     #[op2(async)]
     #[string]
     async fn op_write_file(
+        state: Rc<RefCell<OpState>>,
         #[string] path: String,
         #[string] contents: String,
     ) -> anyhow::Result<(), AnyError> {
-        tokio::fs::write(&path, contents).await.map_err(|e| {
-            error!("Failed to write file '{}': {}", path, e);
+        let full_path = state.borrow().borrow::<FsSandbox>().resolve(&path)?;
+        tokio::fs::write(&full_path, contents).await.map_err(|e| {
+            error!("Failed to write file '{}': {}", full_path.display(), e);
             AnyError::from(e)
         })
     }
 
     #[op2(async)]
     #[string]
-    async fn op_fetch_get(#[string] url: String) -> anyhow::Result<String, AnyError> {
-        let response = reqwest::get(&url).await?;
+    async fn op_fetch_get(
+        state: Rc<RefCell<OpState>>,
+        #[string] url: String,
+    ) -> anyhow::Result<String, AnyError> {
+        let policy = state.borrow().borrow::<NetworkPolicy>().clone();
+        policy.check(&url).await?;
+        let response = policy.client()?.get(&url).send().await?;
         let status = response.status();
         let text = response.text().await?;
 
@@ -141,10 +1098,13 @@ mod pipes {
     #[op2(async)]
     #[string]
     async fn op_fetch_post(
+        state: Rc<RefCell<OpState>>,
         #[string] url: String,
         #[string] body: String,
     ) -> anyhow::Result<String, AnyError> {
-        let client = reqwest::Client::new();
+        let policy = state.borrow().borrow::<NetworkPolicy>().clone();
+        policy.check(&url).await?;
+        let client = policy.client()?;
 
         // Create a HeaderMap and add the Content-Type header
         let mut headers = HeaderMap::new();
@@ -167,14 +1127,27 @@ mod pipes {
     }
 
     #[op2(async)]
-    async fn op_set_timeout(delay: f64) -> anyhow::Result<(), AnyError> {
-        tokio::time::sleep(std::time::Duration::from_millis(delay as u64)).await;
-        Ok(())
+    async fn op_set_timeout(
+        state: Rc<RefCell<OpState>>,
+        delay: f64,
+    ) -> anyhow::Result<(), AnyError> {
+        let wake = Instant::now() + Duration::from_millis(delay as u64);
+        match state.borrow().borrow::<PipeDeadline>().0 {
+            Some(deadline) if deadline < wake => {
+                tokio::time::sleep_until(deadline).await;
+                Err(AnyError::msg("Pipe reached its max runtime"))
+            }
+            _ => {
+                tokio::time::sleep_until(wake).await;
+                Ok(())
+            }
+        }
     }
 
     #[op2(fast)]
-    fn op_remove_file(#[string] path: String) -> anyhow::Result<()> {
-        std::fs::remove_file(path)?;
+    fn op_remove_file(state: &mut OpState, #[string] path: String) -> anyhow::Result<()> {
+        let full_path = state.borrow::<FsSandbox>().resolve(&path)?;
+        std::fs::remove_file(full_path)?;
         Ok(())
     }
 
@@ -263,36 +1236,133 @@ mod pipes {
             op_set_timeout,
             op_fetch,
             op_get_env,
+            op_log,
+            op_db_query,
+            op_notify,
+            op_get_args,
+            op_transcribe,
+            op_set_result,
+            op_fail,
+            op_get_config,
         ]
     }
 
+    /// Runs the pipe at `file_path` to completion and returns the value it passed to
+    /// `pipe.setResult`, `null` if none. Fails with a `PipeFailure` when the pipe itself failed.
     pub async fn run_js(
         pipe: &str,
         file_path: &str,
         screenpipe_dir: PathBuf,
-    ) -> anyhow::Result<()> {
+        options: PipeOptions,
+    ) -> anyhow::Result<Value> {
         let main_module = deno_core::resolve_path(file_path, env::current_dir()?.as_path())?;
+        // The pipe's own directory is the one holding its main module
+        let pipe_dir = main_module
+            .to_file_path()
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+            .ok_or_else(|| anyhow::anyhow!("Invalid pipe file path: {}", file_path))?;
+        let sandbox = FsSandbox::new(&pipe_dir, options.allow_full_fs)?;
+        let env_scope = PipeEnvScope::from_pipe_dir(&pipe_dir)?;
+        let dotenv = PipeDotenv::load(&pipe_dir, options.dotenv_overrides_env)?;
+        if options.allow_full_fs {
+            info!("Pipe '{}' has full filesystem access", pipe);
+        }
+
         let mut js_runtime = deno_core::JsRuntime::new(deno_core::RuntimeOptions {
             module_loader: Some(Rc::new(TsModuleLoader)),
             startup_snapshot: Some(RUNTIME_SNAPSHOT),
             extensions: vec![runjs::init_ops()],
             ..Default::default()
         });
+        js_runtime.op_state().borrow_mut().put(sandbox);
+        js_runtime.op_state().borrow_mut().put(env_scope.clone());
+        js_runtime.op_state().borrow_mut().put(options.network);
+        let db_path = options
+            .db_path
+            .unwrap_or_else(|| screenpipe_dir.join("db.sqlite"));
+        js_runtime
+            .op_state()
+            .borrow_mut()
+            .put(PipeDatabase(db_path));
+        js_runtime
+            .op_state()
+            .borrow_mut()
+            .put(PipeArgs(options.args));
+        js_runtime.op_state().borrow_mut().put(PipeHostConfig {
+            data_dir: screenpipe_dir.clone(),
+            ..options.config
+        });
+        js_runtime
+            .op_state()
+            .borrow_mut()
+            .put(PipeTranscriberSlot(options.transcriber));
+        js_runtime.op_state().borrow_mut().put(PipeNotifier {
+            sender: options.notification_sender,
+            limit: options.notification_rate_limit,
+            sent: VecDeque::new(),
+        });
+        js_runtime.op_state().borrow_mut().put(PipeLogSink {
+            pipe_id: pipe.to_string(),
+            sender: options.log_sender,
+        });
+        let deadline = options.max_runtime.map(|limit| Instant::now() + limit);
+        js_runtime
+            .op_state()
+            .borrow_mut()
+            .put(PipeDeadline(deadline));
+        js_runtime
+            .op_state()
+            .borrow_mut()
+            .put(PipeResult::default());
+
+        // A pipe stuck in synchronous JS never yields to tokio, so a watchdog thread terminates
+        // the isolate once the deadline passes. Dropping `_watchdog` stops it.
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let isolate = js_runtime.v8_isolate().thread_safe_handle();
+        let _watchdog = options.max_runtime.map(|limit| {
+            let (done_tx, done_rx) = mpsc::channel::<()>();
+            let timed_out = timed_out.clone();
+            let isolate = isolate.clone();
+            std::thread::spawn(move || {
+                if let Err(mpsc::RecvTimeoutError::Timeout) = done_rx.recv_timeout(limit) {
+                    timed_out.store(true, Ordering::SeqCst);
+                    isolate.terminate_execution();
+                }
+            });
+            done_tx
+        });
+        let timeout_error = || {
+            anyhow::anyhow!(
+                "Pipe '{}' exceeded its max runtime of {:?}",
+                pipe,
+                options.max_runtime.unwrap_or_default()
+            )
+        };
 
         // Set some metadata on the runtime
         js_runtime.execute_script("main", "globalThis.metadata = { }")?;
         // Set the pipe id
         js_runtime.execute_script("main", format!("globalThis.metadata.id = '{}'", pipe))?;
+        // Set the arguments of this run
+        js_runtime.execute_script("main", "globalThis.metadata.args = pipe.getArgs()")?;
 
         // Initialize process.env
         js_runtime.execute_script("main", "globalThis.process = { env: {} }")?;
 
-        for (key, value) in env::vars() {
-            if key.starts_with("SCREENPIPE_") {
-                js_runtime
-                    .execute_script("main", format!("process.env['{}'] = '{}'", key, value))?;
+        let mut pipe_env: HashMap<String, String> = env::vars()
+            .filter(|(key, _)| env_scope.allows(key))
+            .collect();
+        for (key, value) in &dotenv.vars {
+            if dotenv.overrides_env || !pipe_env.contains_key(key) {
+                pipe_env.insert(key.clone(), value.clone());
             }
         }
+        js_runtime.execute_script(
+            "main",
+            format!("Object.assign(process.env, {})", serde_json::to_string(&pipe_env)?),
+        )?;
+        js_runtime.op_state().borrow_mut().put(dotenv);
 
         // Set additional environment variables
         let home_dir = dirs::home_dir().unwrap_or_default();
@@ -323,44 +1393,180 @@ mod pipes {
         let evaluate_future = js_runtime.mod_evaluate(mod_id);
 
         // Run the event loop and handle potential errors
-        match js_runtime.run_event_loop(Default::default()).await {
-            Ok(_) => (),
-            Err(e) => {
-                error!("Error in JavaScript runtime event loop: {}", e);
-                // ! avoid crashing screenpipe if pipes broken - maybe disable it
-                // You can choose to return the error or handle it differently
-                // return Err(anyhow::anyhow!("JavaScript runtime error: {}", e));
+        let event_loop = js_runtime.run_event_loop(Default::default());
+        let event_loop_result = match deadline {
+            // Also covers a pipe waiting on a hung fetch, which the watchdog can't interrupt
+            Some(deadline) => match tokio::time::timeout_at(deadline, event_loop).await {
+                Ok(result) => result,
+                Err(_) => {
+                    isolate.terminate_execution();
+                    error!("{}", timeout_error());
+                    return Err(timeout_error());
+                }
+            },
+            None => event_loop.await,
+        };
+        if timed_out.load(Ordering::SeqCst) {
+            error!("{}", timeout_error());
+            return Err(timeout_error());
+        }
+        if let Err(e) = event_loop_result {
+            // e.g. a rejected promise the pipe didn't await
+            error!("Error in JavaScript runtime event loop: {}", e);
+            return Err(PipeFailure {
+                message: e.to_string(),
             }
+            .into());
         }
 
         // Evaluate the module and handle potential errors
-        match evaluate_future.await {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                error!("Error evaluating JavaScript module: {}", e);
-                // You can choose to return the error or handle it differently
-                Err(anyhow::anyhow!("JavaScript module evaluation error: {}", e))
+        let evaluate_result = evaluate_future.await;
+        if timed_out.load(Ordering::SeqCst) {
+            error!("{}", timeout_error());
+            return Err(timeout_error());
+        }
+        if let Err(e) = evaluate_result {
+            error!("Error evaluating JavaScript module: {}", e);
+            return Err(PipeFailure {
+                message: e.to_string(),
             }
+            .into());
+        }
+
+        let result = js_runtime.op_state().borrow_mut().take::<PipeResult>();
+        match result.failure {
+            Some(message) => Err(PipeFailure { message }.into()),
+            None => Ok(result.value),
         }
     }
 
+    /// Runs an installed pipe, see `run_js` for what it returns.
     #[allow(clippy::manual_async_fn)]
-    pub async fn run_pipe(pipe: String, screenpipe_dir: PathBuf) -> anyhow::Result<()> {
+    pub async fn run_pipe(
+        pipe: String,
+        screenpipe_dir: PathBuf,
+        options: PipeOptions,
+    ) -> anyhow::Result<Value> {
         debug!(
             "Running pipe: {}, screenpipe_dir: {}",
             pipe,
             screenpipe_dir.display()
         );
 
-        let pipe_dir = match Url::parse(&pipe) {
+        let pipe_dir = resolve_pipe_dir(&pipe, &screenpipe_dir)?;
+        let main_module = find_pipe_file(&pipe_dir)?;
+
+        match run_js(&pipe, &main_module.to_string_lossy(), screenpipe_dir, options).await {
+            Ok(result) => {
+                info!("JS execution completed successfully");
+                Ok(result)
+            }
+            Err(error) => {
+                error!("Error during JS execution: {}", error);
+                Err(error)
+            }
+        }
+    }
+
+    /// How long `run_pipe_watch` waits for the saves to settle before reloading, editors often
+    /// write a file several times in a row
+    const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+    /// Development mode of `run_pipe`: runs the pipe and, whenever its pipe.ts (or pipe.js,
+    /// main.ts, main.js) changes, tears down the running isolate and starts it again from the
+    /// new source. A pipe that exits or fails is restarted on the next change. Runs until the
+    /// watcher fails.
+    pub async fn run_pipe_watch(
+        pipe: String,
+        screenpipe_dir: PathBuf,
+        options: PipeOptions,
+    ) -> anyhow::Result<()> {
+        use notify::{RecursiveMode, Watcher};
+
+        let pipe_dir = resolve_pipe_dir(&pipe, &screenpipe_dir)?;
+
+        let (change_tx, mut change_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(
+            move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if !event.kind.is_access() => {
+                    let _ = change_tx.send(event.paths);
+                }
+                Ok(_) => {}
+                Err(e) => error!("Pipe watcher error: {}", e),
+            },
+        )?;
+        watcher.watch(&pipe_dir, RecursiveMode::NonRecursive)?;
+        info!("Watching {:?} for changes", pipe_dir);
+
+        loop {
+            // Looked up on every reload, the pipe may have been renamed from .js to .ts
+            let main_module = find_pipe_file(&pipe_dir);
+            let watched_name = main_module
+                .as_ref()
+                .ok()
+                .and_then(|path| path.file_name().map(|name| name.to_os_string()));
+            let is_pipe_file = |paths: &[PathBuf]| {
+                paths.iter().any(|path| match &watched_name {
+                    Some(name) => path.file_name() == Some(name.as_os_str()),
+                    // No pipe file yet, any file created in the directory may be it
+                    None => true,
+                })
+            };
+
+            let run = async {
+                match &main_module {
+                    Ok(main_module) => {
+                        info!("Starting pipe {} from {:?}", pipe, main_module);
+                        run_js(&pipe, &main_module.to_string_lossy(), screenpipe_dir.clone(), options.clone()).await
+                    }
+                    Err(e) => Err(anyhow::anyhow!("{}", e)),
+                }
+            };
+            tokio::pin!(run);
+
+            // Dropping `run` drops its JsRuntime, which tears down the isolate
+            let changed = loop {
+                tokio::select! {
+                    result = &mut run => {
+                        match result {
+                            Ok(result) => info!("Pipe {} exited with {}, waiting for changes", pipe, result),
+                            Err(e) => error!("Pipe {} failed: {}, waiting for changes", pipe, e),
+                        }
+                        break false;
+                    }
+                    paths = change_rx.recv() => match paths {
+                        Some(paths) if is_pipe_file(&paths) => break true,
+                        Some(_) => {}
+                        None => anyhow::bail!("Pipe watcher stopped"),
+                    },
+                }
+            };
+
+            if !changed {
+                loop {
+                    match change_rx.recv().await {
+                        Some(paths) if is_pipe_file(&paths) => break,
+                        Some(_) => {}
+                        None => anyhow::bail!("Pipe watcher stopped"),
+                    }
+                }
+            }
+
+            while let Ok(Some(_)) = tokio::time::timeout(WATCH_DEBOUNCE, change_rx.recv()).await {}
+            info!("Pipe {} changed, reloading", pipe);
+        }
+    }
+
+    fn resolve_pipe_dir(pipe: &str, screenpipe_dir: &Path) -> anyhow::Result<PathBuf> {
+        let pipe_dir = match Url::parse(pipe) {
             Ok(_) => {
                 info!("Input appears to be a URL. Attempting to download...");
 
-                PathBuf::from(&pipe)
+                PathBuf::from(pipe)
             }
             Err(_) => {
                 info!("Input appears to be a local path. Attempting to canonicalize...");
-                match screenpipe_dir.join("pipes").join(&pipe).canonicalize() {
+                match screenpipe_dir.join("pipes").join(pipe).canonicalize() {
                     Ok(path) => path,
                     Err(e) => {
                         error!("Failed to canonicalize path: {}", e);
@@ -371,167 +1577,699 @@ mod pipes {
         };
 
         info!("Pipe directory: {:?}", pipe_dir);
-
-        let main_module = find_pipe_file(&pipe_dir)?;
-
-        match run_js(&pipe, &main_module.to_string_lossy(), screenpipe_dir).await {
-            Ok(_) => info!("JS execution completed successfully"),
-            Err(error) => {
-                error!("Error during JS execution: {}", error);
-                anyhow::bail!("Error during JS execution: {}", error);
-            }
-        }
-
-        Ok(())
+        Ok(pipe_dir)
     }
 
-    pub async fn download_pipe(source: &str, screenpipe_dir: PathBuf) -> anyhow::Result<PathBuf> {
-        info!("Processing pipe from source: {}", source);
+    /// Where a pipe is downloaded from, detected from the source given to `download_pipe`.
+    #[derive(Debug, PartialEq)]
+    pub enum PipeSource {
+        /// `https://github.com/<owner>/<repo>/tree/<branch>/<path>`, listed through the GitHub
+        /// contents API
+        GitHub { api_url: String },
+        /// A single file on `raw.githubusercontent.com`
+        GitHubRaw { url: String },
+        /// `https://<host>/<group>/<project>/-/tree/<branch>/<path>` on gitlab.com or a
+        /// self-hosted GitLab, listed through the GitLab repository API. Private projects need
+        /// a `GITLAB_TOKEN` in the environment.
+        GitLab {
+            api_base: String,
+            project: String,
+            branch: String,
+            path: String,
+        },
+        /// `git+https://<host>/<repo>.git[#<ref>][:<subdir>]`, cloned with the `git` binary
+        Git {
+            url: String,
+            reference: Option<String>,
+            subdir: Option<String>,
+        },
+        /// A folder on this machine
+        Local(PathBuf),
+    }
 
-        if let Ok(parsed_url) = Url::parse(source) {
-            // Handle URLs
-            let client = Client::new();
+    impl PipeSource {
+        pub fn detect(source: &str) -> anyhow::Result<Self> {
+            if let Some(git_url) = source.strip_prefix("git+") {
+                return Self::parse_git(git_url);
+            }
+            let Ok(parsed_url) = Url::parse(source) else {
+                return Ok(PipeSource::Local(PathBuf::from(source)));
+            };
             match parsed_url.host_str() {
                 Some("github.com") => {
                     let api_url = get_raw_github_url(source)
                         .map_err(|e| anyhow::anyhow!("Failed to parse GitHub URL: {}", e))?;
-                    let pipe_name = sanitize_pipe_name(
-                        Path::new(&api_url).file_name().unwrap().to_str().unwrap(),
-                    );
-                    download_github_folder(&client, &api_url, screenpipe_dir, &pipe_name).await
+                    Ok(PipeSource::GitHub { api_url })
                 }
-                Some("raw.githubusercontent.com") => {
-                    let pipe_name = sanitize_pipe_name(
-                        Path::new(source).file_name().unwrap().to_str().unwrap(),
-                    );
-                    download_single_file(&client, source, screenpipe_dir, &pipe_name).await
+                Some("raw.githubusercontent.com") => Ok(PipeSource::GitHubRaw {
+                    url: source.to_string(),
+                }),
+                Some(host) if host == "gitlab.com" || parsed_url.path().contains("/-/tree/") => {
+                    Self::parse_gitlab(&parsed_url)
                 }
                 _ => anyhow::bail!("Unsupported URL format"),
             }
-        } else {
-            // Handle local folders
-            let source_path = Path::new(source);
-            if !source_path.exists() {
-                anyhow::bail!("Local source path does not exist");
+        }
+
+        fn parse_gitlab(url: &Url) -> anyhow::Result<Self> {
+            let (project, tree) = url
+                .path()
+                .trim_matches('/')
+                .split_once("/-/tree/")
+                .ok_or_else(|| anyhow::anyhow!("Invalid GitLab URL format, expected a /-/tree/ URL"))?;
+            let (branch, path) = tree.split_once('/').unwrap_or((tree, ""));
+            if project.is_empty() || branch.is_empty() {
+                anyhow::bail!("Invalid GitLab URL format");
             }
-            if !source_path.is_dir() {
-                anyhow::bail!("Local source is not a directory");
+            let mut api_base = format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default());
+            if let Some(port) = url.port() {
+                api_base.push_str(&format!(":{}", port));
             }
+            Ok(PipeSource::GitLab {
+                api_base: format!("{}/api/v4", api_base),
+                project: project.to_string(),
+                branch: branch.to_string(),
+                path: path.trim_end_matches('/').to_string(),
+            })
+        }
+
+        fn parse_git(git_url: &str) -> anyhow::Result<Self> {
+            let (url, fragment) = match git_url.split_once('#') {
+                Some((url, fragment)) => (url, Some(fragment)),
+                None => (git_url, None),
+            };
+            let parsed_url = Url::parse(url)?;
+            if parsed_url.scheme() != "https" {
+                anyhow::bail!("Unsupported git URL scheme '{}'", parsed_url.scheme());
+            }
+            let (reference, subdir) = match fragment {
+                Some(fragment) => match fragment.split_once(':') {
+                    Some((reference, subdir)) => (reference, subdir),
+                    None => (fragment, ""),
+                },
+                None => ("", ""),
+            };
+            let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+            Ok(PipeSource::Git {
+                url: url.to_string(),
+                reference: non_empty(reference),
+                subdir: non_empty(subdir.trim_matches('/')),
+            })
+        }
 
-            let pipe_name = sanitize_pipe_name(
-                source_path
+        /// The name the pipe is installed under, before sanitizing.
+        fn pipe_name(&self) -> String {
+            let last_segment = |path: &str| {
+                Path::new(path)
                     .file_name()
                     .and_then(|name| name.to_str())
-                    .unwrap_or("unknown_pipe"),
-            );
-            let dest_dir = screenpipe_dir.join("pipes").join(&pipe_name);
+                    .unwrap_or("unknown_pipe")
+                    .to_string()
+            };
+            match self {
+                PipeSource::GitHub { api_url } => last_segment(api_url),
+                PipeSource::GitHubRaw { url } => last_segment(url),
+                PipeSource::GitLab { project, path, .. } if path.is_empty() => last_segment(project),
+                PipeSource::GitLab { path, .. } => last_segment(path),
+                PipeSource::Git {
+                    subdir: Some(subdir),
+                    ..
+                } => last_segment(subdir),
+                PipeSource::Git { url, .. } => last_segment(url.trim_end_matches(".git")),
+                PipeSource::Local(path) => path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("unknown_pipe")
+                    .to_string(),
+            }
+        }
+    }
+
+    /// Installs a pipe under `<screenpipe_dir>/pipes`. When its `pipe.json` lists SHA-256
+    /// checksums, the installed files must match them; with `verify` every installed file must
+    /// be listed, so a pipe without checksums is refused. See `verify_pipe_checksums`.
+    pub async fn download_pipe(
+        source: &str,
+        screenpipe_dir: PathBuf,
+        verify: bool,
+    ) -> anyhow::Result<PathBuf> {
+        info!("Processing pipe from source: {}", source);
+
+        let source = PipeSource::detect(source)?;
+        let pipe_name = sanitize_pipe_name(&source.pipe_name());
+        let pipes_dir = screenpipe_dir.join("pipes");
+        let pipe_dir = pipes_dir.join(&pipe_name);
+
+        // A local pipe replaces the installed one, to pick up the author's edits
+        let reinstall = pipe_dir.exists();
+        if reinstall && !matches!(source, PipeSource::Local(_)) {
+            info!("Pipe already exists: {:?}", pipe_dir);
+            return Ok(pipe_dir);
+        }
+
+        // Installed into a hidden directory next to the pipe, and renamed into place once
+        // complete and verified: a partial pipe would be taken as already installed by the next
+        // download. Dropping `staging` removes it on any error.
+        tokio::fs::create_dir_all(&pipes_dir).await?;
+        let staging = tempfile::Builder::new()
+            .prefix(&format!(".{}-", pipe_name))
+            .tempdir_in(&pipes_dir)?;
+        install_pipe(&source, staging.path(), &pipe_name).await?;
+        if let Err(e) = verify_pipe_checksums(staging.path(), verify) {
+            error!("Refusing to install pipe {}: {}", pipe_name, e);
+            return Err(e);
+        }
+
+        if reinstall {
+            // Keeps the state written by `screenpipe pipe enable/update` when the author's copy
+            // has no pipe.json of its own
+            let installed_config = pipe_dir.join("pipe.json");
+            let staged_config = staging.path().join("pipe.json");
+            if installed_config.exists() && !staged_config.exists() {
+                tokio::fs::copy(&installed_config, &staged_config).await?;
+            }
+            // Moved aside rather than deleted until the new copy is in place
+            let replaced = tempfile::Builder::new()
+                .prefix(&format!(".{}-old-", pipe_name))
+                .tempdir_in(&pipes_dir)?;
+            let replaced_dir = replaced.path().join(&pipe_name);
+            tokio::fs::rename(&pipe_dir, &replaced_dir).await?;
+            if let Err(e) = tokio::fs::rename(staging.path(), &pipe_dir).await {
+                tokio::fs::rename(&replaced_dir, &pipe_dir).await?;
+                return Err(e.into());
+            }
+        } else {
+            tokio::fs::rename(staging.path(), &pipe_dir).await?;
+        }
+        // Renamed away, nothing left to remove
+        let _ = staging.into_path();
+
+        info!("Pipe installed to: {:?}", pipe_dir);
+        Ok(pipe_dir)
+    }
+
+    /// Checks the files of an installed pipe against the `checksums` of its `pipe.json`, a map
+    /// from paths relative to the pipe directory to hex SHA-256 digests:
+    ///
+    /// ```json
+    /// { "checksums": { "pipe.ts": "9f86d081884c7d65...", "lib/util.ts": "..." } }
+    /// ```
+    ///
+    /// With `require_all`, every file except `pipe.json` itself must be listed.
+    pub fn verify_pipe_checksums(pipe_dir: &Path, require_all: bool) -> anyhow::Result<()> {
+        let config_path = pipe_dir.join("pipe.json");
+        let config: Value = match std::fs::read_to_string(&config_path) {
+            Ok(config) => serde_json::from_str(&config)?,
+            Err(_) => Value::Null,
+        };
+        let checksums = match config.get("checksums") {
+            Some(Value::Object(checksums)) => checksums.clone(),
+            Some(_) => anyhow::bail!("pipe.json checksums must be an object"),
+            None if require_all => anyhow::bail!("pipe.json declares no checksums to verify"),
+            None => return Ok(()),
+        };
 
-            tokio::fs::create_dir_all(&dest_dir).await?;
-            copy_local_folder(source_path, &dest_dir).await?;
+        let mut files = Vec::new();
+        let mut pending = vec![pipe_dir.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    let relative = path.strip_prefix(pipe_dir)?;
+                    // Same separators as the pipe.json keys on every platform
+                    let relative = relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    files.push((relative, path));
+                }
+            }
+        }
+
+        for (relative, path) in &files {
+            if relative == "pipe.json" {
+                continue;
+            }
+            let Some(expected) = checksums.get(relative.as_str()) else {
+                if require_all {
+                    anyhow::bail!("No checksum for {} in pipe.json", relative);
+                }
+                continue;
+            };
+            let expected = expected
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Checksum for {} must be a string", relative))?;
+            let digest = Sha256::digest(std::fs::read(path)?);
+            let actual: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                anyhow::bail!(
+                    "Checksum mismatch for {}: expected {}, got {}",
+                    relative,
+                    expected,
+                    actual
+                );
+            }
+        }
 
-            info!("Local pipe copied successfully to: {:?}", dest_dir);
-            Ok(dest_dir)
+        for listed in checksums.keys() {
+            if !files.iter().any(|(relative, _)| relative == listed) {
+                anyhow::bail!("{} is listed in pipe.json checksums but was not installed", listed);
+            }
         }
+        info!("Verified checksums of pipe {:?}", pipe_dir);
+        Ok(())
+    }
+
+    /// Puts the files of the pipe from `source` into the existing `pipe_dir`.
+    async fn install_pipe(
+        source: &PipeSource,
+        pipe_dir: &Path,
+        pipe_name: &str,
+    ) -> anyhow::Result<()> {
+        let client = Client::new();
+        match source {
+            PipeSource::GitHub { api_url } => {
+                download_github_folder(&client, api_url, pipe_dir, pipe_name).await
+            }
+            PipeSource::GitHubRaw { url } => {
+                download_single_file(&client, url, pipe_dir, pipe_name).await
+            }
+            PipeSource::GitLab {
+                api_base,
+                project,
+                branch,
+                path,
+            } => {
+                download_gitlab_folder(
+                    &client,
+                    api_base,
+                    project,
+                    branch,
+                    path,
+                    pipe_dir,
+                )
+                .await
+            }
+            PipeSource::Git {
+                url,
+                reference,
+                subdir,
+            } => {
+                clone_git_pipe(
+                    url,
+                    reference.as_deref(),
+                    subdir.as_deref(),
+                    pipe_dir,
+                )
+                .await
+            }
+            PipeSource::Local(source_path) => {
+                if !source_path.exists() {
+                    anyhow::bail!("Local source path does not exist");
+                }
+                if !source_path.is_dir() {
+                    anyhow::bail!("Local source is not a directory");
+                }
+
+                copy_local_folder(source_path, pipe_dir).await?;
+
+                info!("Local pipe copied successfully to: {:?}", pipe_dir);
+                Ok(())
+            }
+        }
+    }
+
+    /// Largest total size of a downloaded or copied pipe.
+    const MAX_PIPE_SIZE: u64 = 100 * 1024 * 1024;
+
+    /// Which files of a pipe get installed, by path relative to the pipe's root: code and
+    /// `pipe.json` at the top, anything in subdirectories (`lib/`, assets, ...) except hidden
+    /// entries and `node_modules`.
+    fn is_pipe_file(relative_path: &Path) -> bool {
+        if is_skipped(relative_path) {
+            return false;
+        }
+        if relative_path.components().count() > 1 {
+            return true;
+        }
+        relative_path
+            .to_str()
+            .map(|s| s.ends_with(".ts") || s.ends_with(".js") || s == "pipe.json")
+            .unwrap_or(false)
+    }
+
+    fn is_skipped(relative_path: &Path) -> bool {
+        relative_path.components().any(|component| {
+            let name = component.as_os_str().to_string_lossy();
+            name.starts_with('.') || name == "node_modules"
+        })
+    }
+
+    /// Keeps the total size of a pipe under `MAX_PIPE_SIZE`.
+    #[derive(Default)]
+    struct SizeBudget {
+        total: u64,
+    }
+
+    impl SizeBudget {
+        fn add(&mut self, bytes: u64) -> anyhow::Result<()> {
+            self.total += bytes;
+            if self.total > MAX_PIPE_SIZE {
+                anyhow::bail!("Pipe is larger than {} MB", MAX_PIPE_SIZE / 1024 / 1024);
+            }
+            Ok(())
+        }
+    }
+
+    /// Joins a path from a remote listing under `base`, refusing ones that would leave it.
+    fn join_relative(base: &Path, relative_path: &str) -> anyhow::Result<PathBuf> {
+        let relative = Path::new(relative_path);
+        if relative
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            anyhow::bail!("Invalid path in pipe: {}", relative_path);
+        }
+        Ok(base.join(relative))
     }
 
     async fn copy_local_folder(src: &Path, dst: &Path) -> anyhow::Result<()> {
-        for entry in std::fs::read_dir(src)? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
-            let file_name = entry.file_name();
+        let mut budget = SizeBudget::default();
+        // Symlinks are followed, so remember each directory to not loop through them
+        let mut visited = std::collections::HashSet::new();
+        let mut pending = vec![(src.to_path_buf(), PathBuf::new())];
+
+        while let Some((dir, relative_dir)) = pending.pop() {
+            if !visited.insert(dir.canonicalize()?) {
+                info!("Skipping already copied directory: {:?}", dir);
+                continue;
+            }
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let src_path = entry.path();
+                let relative_path = relative_dir.join(entry.file_name());
+                let metadata = match std::fs::metadata(&src_path) {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        info!("Skipping {:?}: {}", src_path, e);
+                        continue;
+                    }
+                };
 
-            if file_type.is_file() {
-                if file_name
-                    .to_str()
-                    .map(|s| s.ends_with(".ts") || s.ends_with(".js") || s == "pipe.json")
-                    .unwrap_or(false)
-                {
-                    let src_path = entry.path();
-                    let dst_path = dst.join(file_name);
+                if metadata.is_dir() {
+                    if !is_skipped(&relative_path) {
+                        pending.push((src_path, relative_path));
+                    }
+                } else if metadata.is_file() && is_pipe_file(&relative_path) {
+                    budget.add(metadata.len())?;
+                    let dst_path = dst.join(&relative_path);
+                    if let Some(parent) = dst_path.parent() {
+                        tokio::fs::create_dir_all(parent).await?;
+                    }
                     tokio::fs::copy(&src_path, &dst_path).await?;
                     info!("Copied: {:?} to {:?}", src_path, dst_path);
                 }
-            } else if file_type.is_dir() {
-                // Optionally handle subdirectories if needed
             }
         }
         Ok(())
     }
 
+    /// Most files of a GitHub pipe downloaded at once.
+    const GITHUB_CONCURRENT_DOWNLOADS: usize = 8;
+
+    /// Lists the folder, then downloads its files `GITHUB_CONCURRENT_DOWNLOADS` at a time. A
+    /// failed file doesn't stop the others, the error names every file that failed, and
+    /// `download_pipe` removes the partial directory.
     async fn download_github_folder(
         client: &Client,
         api_url: &str,
-        screenpipe_dir: PathBuf,
+        pipe_dir: &Path,
         pipe_name: &str,
-    ) -> anyhow::Result<PathBuf> {
-        let response = client
-            .get(api_url)
-            .header("Accept", "application/vnd.github.v3+json")
-            .header("User-Agent", "screenpipe")
-            .send()
-            .await?;
-
-        let contents: Value = response.json().await?;
+    ) -> anyhow::Result<()> {
+        let mut budget = SizeBudget::default();
+        let mut pending = vec![(api_url.to_string(), String::new())];
+        // (relative path, download URL) of every file to download
+        let mut files = Vec::new();
+
+        while let Some((url, relative_dir)) = pending.pop() {
+            let response = client
+                .get(&url)
+                .header("Accept", "application/vnd.github.v3+json")
+                .header("User-Agent", "screenpipe")
+                .send()
+                .await?;
+
+            let contents: Value = response.json().await?;
+
+            let Some(items) = contents.as_array() else {
+                anyhow::bail!("Invalid response from GitHub API");
+            };
+
+            for item in items {
+                let (Some(file_name), Some(item_type)) =
+                    (item["name"].as_str(), item["type"].as_str())
+                else {
+                    continue;
+                };
+                let relative_path = if relative_dir.is_empty() {
+                    file_name.to_string()
+                } else {
+                    format!("{}/{}", relative_dir, file_name)
+                };
+                match item_type {
+                    "dir" => {
+                        if let Some(dir_url) = item["url"].as_str() {
+                            if !is_skipped(Path::new(&relative_path)) {
+                                pending.push((dir_url.to_string(), relative_path));
+                            }
+                        }
+                    }
+                    // Symlinks and submodules are left out
+                    "file" if is_pipe_file(Path::new(&relative_path)) => {
+                        budget.add(item["size"].as_u64().unwrap_or(0))?;
+                        let download_url = item["download_url"]
+                            .as_str()
+                            .ok_or_else(|| anyhow::anyhow!("No download URL for {}", relative_path))?;
+                        files.push((relative_path, download_url.to_string()));
+                    }
+                    _ => {}
+                }
+            }
+        }
 
-        if !contents.is_array() {
-            anyhow::bail!("Invalid response from GitHub API");
+        let mut downloads = tokio::task::JoinSet::new();
+        let mut failures = Vec::new();
+        let mut record = |joined: Result<(String, anyhow::Result<()>), tokio::task::JoinError>| {
+            match joined {
+                Ok((_, Ok(()))) => {}
+                Ok((relative_path, Err(e))) => {
+                    error!("Failed to download {}: {}", relative_path, e);
+                    failures.push(format!("{}: {}", relative_path, e));
+                }
+                Err(e) => failures.push(e.to_string()),
+            }
+        };
+        for (relative_path, download_url) in files {
+            if downloads.len() >= GITHUB_CONCURRENT_DOWNLOADS {
+                if let Some(joined) = downloads.join_next().await {
+                    record(joined);
+                }
+            }
+            let client = client.clone();
+            let pipe_dir = pipe_dir.to_path_buf();
+            downloads.spawn(async move {
+                let result =
+                    download_github_file(&client, &download_url, &pipe_dir, &relative_path).await;
+                (relative_path, result)
+            });
+        }
+        while let Some(joined) = downloads.join_next().await {
+            record(joined);
+        }
+        if !failures.is_empty() {
+            anyhow::bail!(
+                "Failed to download {} file(s) of pipe {}: {}",
+                failures.len(),
+                pipe_name,
+                failures.join("; ")
+            );
         }
 
-        let pipe_dir = screenpipe_dir.join("pipes").join(pipe_name);
+        info!("Pipe downloaded successfully to: {:?}", pipe_dir);
+        Ok(())
+    }
 
-        // Check if the pipe directory already exists
-        if pipe_dir.exists() {
-            info!("Pipe already exists: {:?}", pipe_dir);
-            return Ok(pipe_dir);
+    async fn download_github_file(
+        client: &Client,
+        download_url: &str,
+        pipe_dir: &Path,
+        relative_path: &str,
+    ) -> anyhow::Result<()> {
+        let response = client.get(download_url).send().await?.error_for_status()?;
+        let file_content = response.bytes().await?;
+        let file_path = join_relative(pipe_dir, relative_path)?;
+        if let Some(parent) = file_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
         }
+        tokio::fs::write(&file_path, &file_content).await?;
+        info!("Downloaded: {:?}", file_path);
+        Ok(())
+    }
 
-        tokio::fs::create_dir_all(&pipe_dir).await?;
+    async fn download_gitlab_folder(
+        client: &Client,
+        api_base: &str,
+        project: &str,
+        branch: &str,
+        path: &str,
+        pipe_dir: &Path,
+    ) -> anyhow::Result<()> {
+        let project_url = format!(
+            "{}/projects/{}/repository",
+            api_base,
+            url::form_urlencoded::byte_serialize(project.as_bytes()).collect::<String>()
+        );
+        let with_token = |request: reqwest::RequestBuilder| match env::var("GITLAB_TOKEN") {
+            Ok(token) => request.header("PRIVATE-TOKEN", token),
+            Err(_) => request,
+        };
 
-        for item in contents.as_array().unwrap() {
-            let file_name = item["name"].as_str().unwrap();
-            if file_name.ends_with(".ts") || file_name.ends_with(".js") || file_name == "pipe.json"
-            {
-                if file_name.starts_with("main")
-                    || file_name.starts_with("pipe")
-                    || file_name == "pipe.json"
-                {
-                    let download_url = item["download_url"].as_str().unwrap();
-                    let file_content = client.get(download_url).send().await?.bytes().await?;
-                    let file_path = pipe_dir.join(file_name);
-                    tokio::fs::write(&file_path, &file_content).await?;
-                    info!("Downloaded: {:?}", file_path);
+        // The tree is paginated, GitLab names the next page in `x-next-page` until the last one
+        let mut entries = Vec::new();
+        let mut page = "1".to_string();
+        loop {
+            let response = with_token(client.get(format!("{}/tree", project_url)))
+                .query(&[
+                    ("path", path),
+                    ("ref", branch),
+                    ("recursive", "true"),
+                    ("per_page", "100"),
+                    ("page", page.as_str()),
+                ])
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                anyhow::bail!("GitLab API error: HTTP {}", response.status());
+            }
+            let next_page = response
+                .headers()
+                .get("x-next-page")
+                .and_then(|next_page| next_page.to_str().ok())
+                .map(|next_page| next_page.trim().to_string())
+                .filter(|next_page| !next_page.is_empty());
+            let contents: Value = response.json().await?;
+            let Some(page_entries) = contents.as_array() else {
+                anyhow::bail!("Invalid response from GitLab API");
+            };
+            entries.extend(page_entries.iter().cloned());
+            match next_page {
+                Some(next_page) => page = next_page,
+                None => break,
+            }
+        }
+
+        let mut budget = SizeBudget::default();
+        for entry in &entries {
+            if entry["type"].as_str() != Some("blob") {
+                continue;
+            }
+            let Some(file_path) = entry["path"].as_str() else {
+                continue;
+            };
+            // Listed paths are from the repository root
+            let relative_path = file_path
+                .strip_prefix(path)
+                .unwrap_or(file_path)
+                .trim_start_matches('/');
+            if is_pipe_file(Path::new(relative_path)) {
+                let raw_url = format!(
+                    "{}/files/{}/raw",
+                    project_url,
+                    url::form_urlencoded::byte_serialize(file_path.as_bytes()).collect::<String>()
+                );
+                let response = with_token(client.get(&raw_url))
+                    .query(&[("ref", branch)])
+                    .send()
+                    .await?;
+                if !response.status().is_success() {
+                    anyhow::bail!(
+                        "Failed to download {} from GitLab: HTTP {}",
+                        file_path,
+                        response.status()
+                    );
                 }
+                let file_content = response.bytes().await?;
+                budget.add(file_content.len() as u64)?;
+                let dest_path = join_relative(pipe_dir, relative_path)?;
+                if let Some(parent) = dest_path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(&dest_path, &file_content).await?;
+                info!("Downloaded: {:?}", dest_path);
             }
         }
 
         info!("Pipe downloaded successfully to: {:?}", pipe_dir);
-        Ok(pipe_dir)
+        Ok(())
+    }
+
+    async fn clone_git_pipe(
+        url: &str,
+        reference: Option<&str>,
+        subdir: Option<&str>,
+        pipe_dir: &Path,
+    ) -> anyhow::Result<()> {
+        let checkout = tempfile::tempdir()?;
+        let mut command = tokio::process::Command::new("git");
+        command.args(["clone", "--depth", "1"]);
+        if let Some(reference) = reference {
+            command.args(["--branch", reference]);
+        }
+        let output = command
+            .arg(url)
+            .arg(checkout.path())
+            .output()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to run git, is it installed? {}", e))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git clone of {} failed: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let source_dir = match subdir {
+            Some(subdir) => join_relative(checkout.path(), subdir)?,
+            None => checkout.path().to_path_buf(),
+        };
+        if !source_dir.is_dir() {
+            anyhow::bail!("{} has no directory {:?}", url, subdir.unwrap_or_default());
+        }
+
+        copy_local_folder(&source_dir, pipe_dir).await?;
+
+        info!("Pipe cloned successfully to: {:?}", pipe_dir);
+        Ok(())
     }
 
     async fn download_single_file(
         client: &Client,
         url: &str,
-        screenpipe_dir: PathBuf,
+        pipe_dir: &Path,
         pipe_name: &str,
-    ) -> anyhow::Result<PathBuf> {
-        let response = client.get(url).send().await?;
+    ) -> anyhow::Result<()> {
+        let response = client.get(url).send().await?.error_for_status()?;
         let content = response.bytes().await?;
 
-        let pipe_dir = screenpipe_dir.join("pipes").join(pipe_name);
-
-        // Check if the pipe directory already exists
-        if pipe_dir.exists() {
-            info!("Pipe already exists: {:?}", pipe_dir);
-            return Ok(pipe_dir);
-        }
-
-        tokio::fs::create_dir_all(&pipe_dir).await?;
-
         let file_path = pipe_dir.join(pipe_name);
         tokio::fs::write(&file_path, &content).await?;
 
         info!("Downloaded single file: {:?}", file_path);
-        Ok(pipe_dir)
+        Ok(())
     }
 
     fn get_raw_github_url(url: &str) -> anyhow::Result<String> {
@@ -1,9 +1,13 @@
 #[cfg(feature = "pipes")]
 #[cfg(test)]
 mod tests {
-    use screenpipe_core::{download_pipe, run_js, run_pipe};
-    use serde_json::json;
-    use std::{path::PathBuf, sync::Once};
+    use screenpipe_core::{
+        download_pipe, parse_dotenv, run_js, run_pipe, run_pipe_watch, FsSandbox, NetworkPolicy,
+        PipeEnvScope, PipeFailure, PipeHostConfig, PipeLogEntry, PipeOptions, PipeSource,
+        PipeTranscriber, RateLimit,
+    };
+    use serde_json::{json, Value};
+    use std::{path::PathBuf, sync::Once, time::Duration};
     use tempfile::TempDir;
     use tokio::{
         fs::{create_dir_all, File},
@@ -46,7 +50,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let screenpipe_dir = temp_dir.path().to_path_buf();
 
-        let result = run_js("", file_path, screenpipe_dir).await;
+        let result = run_js("", file_path, screenpipe_dir, PipeOptions::default()).await;
 
         assert!(result.is_ok());
         println!("result: {:?}", result);
@@ -76,7 +80,12 @@ mod tests {
 
         let pipe_dir = setup_test_pipe(&temp_dir, "simple_pipe", code).await;
 
-        let result = run_pipe(pipe_dir.to_string_lossy().to_string(), screenpipe_dir).await;
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await;
         assert!(result.is_ok());
     }
 
@@ -94,12 +103,16 @@ mod tests {
 
         let pipe_dir = setup_test_pipe(&temp_dir, "http_pipe", code).await;
 
-        let result = run_pipe(pipe_dir.to_string_lossy().to_string(), screenpipe_dir).await;
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
-    #[ignore] // TODO: fix this test (not implemented yet)
     async fn test_pipe_with_error() {
         let temp_dir = TempDir::new().unwrap();
         let screenpipe_dir = temp_dir.path().to_path_buf();
@@ -111,12 +124,56 @@ mod tests {
 
         let pipe_dir = setup_test_pipe(&temp_dir, "error_pipe", code).await;
 
-        let result = run_pipe(pipe_dir.to_string_lossy().to_string(), screenpipe_dir).await;
-        assert!(result.is_err());
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await;
+        let error = result.unwrap_err();
+        let failure = error.downcast_ref::<PipeFailure>().expect("not a pipe failure");
+        assert!(failure.message.contains("Intentional error"), "{}", failure.message);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_reports_result_and_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            pipe.setResult({ summarized: 3, ok: true });
+        "#;
+        let pipe_dir = setup_test_pipe(&temp_dir, "result_pipe", code).await;
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir.clone(),
+            PipeOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, json!({ "summarized": 3, "ok": true }));
+
+        let code = r#"
+            pipe.setResult("partial");
+            pipe.fail("no frames to summarize");
+        "#;
+        let pipe_dir = setup_test_pipe(&temp_dir, "failing_pipe", code).await;
+        let error = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<PipeFailure>(),
+            Some(&PipeFailure {
+                message: "no frames to summarize".to_string()
+            })
+        );
     }
 
     #[tokio::test]
-    #[ignore] // TODO: fix this test (file operations work but not in this test for some reason)
     async fn test_pipe_with_file_operations() {
         let temp_dir = TempDir::new().unwrap();
         let screenpipe_dir = temp_dir.path().to_path_buf();
@@ -126,18 +183,58 @@ mod tests {
             await pipe.writeFile("output.txt", "Hello, Screenpipe!");
             const content = await pipe.readFile("output.txt");
             console.log(`File content: ${content}`);
+            if (content !== "Hello, Screenpipe!") {
+                throw new Error(`unexpected file content: ${content}`);
+            }
+
+            let blocked = false;
+            try {
+                await pipe.writeFile("../outside.txt", "escaped");
+            } catch (error) {
+                console.log(`write blocked: ${error}`);
+                blocked = true;
+            }
+            if (!blocked) {
+                throw new Error("write outside the pipe directory was allowed");
+            }
         "#;
 
         let pipe_dir = setup_test_pipe(&temp_dir, "file_pipe", code).await;
 
-        let result = run_pipe(pipe_dir.to_string_lossy().to_string(), screenpipe_dir).await;
-        assert!(result.is_ok());
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir.clone(),
+            PipeOptions::default(),
+        )
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
 
         // Verify that the file was created and contains the expected content
         let output_file = pipe_dir.join("output.txt");
         assert!(output_file.exists());
         let content = tokio::fs::read_to_string(output_file).await.unwrap();
         assert_eq!(content, "Hello, Screenpipe!");
+        assert!(!temp_dir.path().join("outside.txt").exists());
+
+        // A trusted pipe reaches the rest of the filesystem
+        let code = r#"
+            await pipe.writeFile("../outside.txt", "trusted");
+        "#;
+        let pipe_dir = setup_test_pipe(&temp_dir, "trusted_file_pipe", code).await;
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions {
+                allow_full_fs: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+        let content = tokio::fs::read_to_string(temp_dir.path().join("outside.txt"))
+            .await
+            .unwrap();
+        assert_eq!(content, "trusted");
     }
 
     async fn setup_test_pipe_with_config(
@@ -187,8 +284,699 @@ mod tests {
         // Change the working directory to the pipe directory
         std::env::set_current_dir(&pipe_dir).unwrap();
 
-        let result = run_pipe(pipe_dir.to_string_lossy().to_string(), screenpipe_dir).await;
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe execution failed: {:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_cannot_read_outside_its_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            let leaked = false;
+            try {
+                await pipe.readFile("/etc/passwd");
+                leaked = true;
+            } catch (error) {
+                console.log(`read blocked: ${error}`);
+            }
+            try {
+                await pipe.readFile("../../../../../../etc/passwd");
+                leaked = true;
+            } catch (error) {
+                console.log(`read blocked: ${error}`);
+            }
+            if (leaked) {
+                throw new Error("pipe read /etc/passwd");
+            }
+        "#;
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "sandboxed_pipe", code).await;
+
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe escaped its sandbox: {:?}", result);
+    }
+
+    #[test]
+    fn test_fs_sandbox_rejects_escapes() {
+        let temp_dir = TempDir::new().unwrap();
+        let pipe_dir = temp_dir.path().join("pipe");
+        std::fs::create_dir_all(pipe_dir.join("data")).unwrap();
+        std::fs::write(pipe_dir.join("pipe.json"), "{}").unwrap();
+
+        let sandbox = FsSandbox::new(&pipe_dir, false).unwrap();
+        assert!(sandbox.resolve("/etc/passwd").is_err());
+        assert!(sandbox.resolve("../../../../../../etc/passwd").is_err());
+        assert!(sandbox.resolve("data/../../outside.txt").is_err());
+        assert!(sandbox.resolve("..").is_err());
+
+        assert_eq!(
+            sandbox.resolve("pipe.json").unwrap(),
+            sandbox.root().join("pipe.json")
+        );
+        // Files that don't exist yet can still be written inside the pipe directory
+        assert_eq!(
+            sandbox.resolve("data/../output.txt").unwrap(),
+            sandbox.root().join("output.txt")
+        );
+
+        let trusted = FsSandbox::new(&pipe_dir, true).unwrap();
+        assert_eq!(
+            trusted.resolve("/etc/passwd").unwrap(),
+            std::path::Path::new("/etc/passwd").canonicalize().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_network_policy_denies_private_hosts() {
+        let policy = NetworkPolicy::default();
+        assert!(policy.check("http://127.0.0.1:3030/search").await.is_err());
+        assert!(policy.check("http://localhost:3030/search").await.is_err());
+        assert!(policy
+            .check("http://169.254.169.254/latest/meta-data/")
+            .await
+            .is_err());
+        assert!(policy.check("http://10.0.0.1/").await.is_err());
+        assert!(policy.check("http://[::1]/").await.is_err());
+        assert!(policy.check("file:///etc/passwd").await.is_err());
+
+        let policy = NetworkPolicy {
+            allowed_private_hosts: vec!["localhost:3030".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.check("http://localhost:3030/search").await.is_ok());
+        assert!(policy.check("http://localhost:8080/").await.is_err());
+
+        let policy = NetworkPolicy {
+            allowed_hosts: Some(vec!["8.8.8.8".to_string()]),
+            ..Default::default()
+        };
+        assert!(policy.check("https://8.8.8.8/").await.is_ok());
+        assert!(policy.check("https://1.1.1.1/").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_network_policy_allows_loopback_aliases() {
+        let mut policy = NetworkPolicy::default();
+        policy.allow_private_host("localhost:3035");
+        policy.allow_private_host("127.0.0.1:3035");
+        policy.allow_private_host("192.168.1.20");
+        assert_eq!(
+            policy.allowed_private_hosts,
+            vec!["localhost:3035", "127.0.0.1:3035", "[::1]:3035", "192.168.1.20"]
+        );
+
+        assert!(policy.check("http://localhost:3035/search").await.is_ok());
+        assert!(policy.check("http://127.0.0.1:3035/search").await.is_ok());
+        assert!(policy.check("http://[::1]:3035/search").await.is_ok());
+        assert!(policy.check("http://192.168.1.20:8080/").await.is_ok());
+        assert!(policy.check("http://localhost:3030/search").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pipe_fetch_blocked_by_network_policy() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            let blocked = false;
+            try {
+                await pipe.fetch("http://169.254.169.254/latest/meta-data/");
+            } catch (error) {
+                console.log(`fetch blocked: ${error}`);
+                blocked = true;
+            }
+            if (!blocked) {
+                throw new Error("pipe reached a link-local address");
+            }
+        "#;
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "network_pipe", code).await;
+
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe fetch was not blocked: {:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_stopped_after_max_runtime() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            console.log("Looping forever...");
+            while (true) {}
+        "#;
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "runaway_pipe", code).await;
+
+        let options = PipeOptions {
+            max_runtime: Some(Duration::from_millis(500)),
+            ..Default::default()
+        };
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            run_pipe(pipe_dir.to_string_lossy().to_string(), screenpipe_dir, options),
+        )
+        .await
+        .expect("runaway pipe was not stopped");
+        let error = result.expect_err("runaway pipe should fail");
+        assert!(
+            error.to_string().contains("max runtime"),
+            "unexpected error: {}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pipe_logs_reach_the_host() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            console.log("hello", 42);
+            console.error("something broke");
+        "#;
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "logging_pipe", code).await;
+
+        let (log_sender, mut log_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let options = PipeOptions {
+            log_sender: Some(log_sender),
+            ..Default::default()
+        };
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            options,
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe execution failed: {:?}", result);
+
+        let mut entries: Vec<PipeLogEntry> = Vec::new();
+        while let Ok(entry) = log_receiver.try_recv() {
+            entries.push(entry);
+        }
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].level, "info");
+        assert_eq!(entries[0].message, "\"hello\" 42");
+        assert_eq!(entries[1].level, "error");
+        assert_eq!(entries[1].message, "\"something broke\"");
+        assert!(entries[0].pipe_id.ends_with("logging_pipe"));
+    }
+
+    #[tokio::test]
+    async fn test_pipe_watch_reloads_on_change() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+        let pipe_dir = setup_test_pipe(&temp_dir, "watched_pipe", r#"console.log("v1");"#).await;
+
+        let (log_sender, mut log_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let options = PipeOptions {
+            log_sender: Some(log_sender),
+            ..Default::default()
+        };
+        let watch = run_pipe_watch(pipe_dir.to_string_lossy().to_string(), screenpipe_dir, options);
+
+        let edit = async {
+            let first: PipeLogEntry = log_receiver.recv().await.unwrap();
+            assert_eq!(first.message, "\"v1\"");
+            tokio::fs::write(pipe_dir.join("pipe.ts"), r#"console.log("v2");"#)
+                .await
+                .unwrap();
+            log_receiver.recv().await.unwrap()
+        };
+
+        let reloaded = tokio::time::timeout(Duration::from_secs(30), async {
+            tokio::select! {
+                result = watch => panic!("watch stopped: {:?}", result),
+                entry = edit => entry,
+            }
+        })
+        .await
+        .expect("pipe was not reloaded");
+        assert_eq!(reloaded.message, "\"v2\"");
+    }
+
+    #[tokio::test]
+    async fn test_pipe_fetch_times_out() {
+        use tokio::net::TcpListener;
+
+        // Accepts the connection and never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = format!(
+            r#"
+            try {{
+                await pipe.fetch("http://127.0.0.1:{}/slow", {{ timeout: 200 }});
+                pipe.fail("fetch did not time out");
+            }} catch (error) {{
+                pipe.setResult(error.message);
+            }}
+        "#,
+            port
+        );
+        let pipe_dir = setup_test_pipe(&temp_dir, "timeout_fetch_pipe", &code).await;
+
+        let options = PipeOptions {
+            network: NetworkPolicy {
+                allowed_private_hosts: vec![format!("127.0.0.1:{}", port)],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            run_pipe(pipe_dir.to_string_lossy().to_string(), screenpipe_dir, options),
+        )
+        .await
+        .expect("fetch hung")
+        .unwrap();
+        assert!(result.as_str().unwrap().contains("timed out"), "{}", result);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_fetch_binary_response() {
+        use tokio::io::AsyncReadExt;
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await.unwrap();
+            let mut response =
+                b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\n".to_vec();
+            response.extend_from_slice(&[0x00, 0x9f, 0x80, 0xff]);
+            socket.write_all(&response).await.unwrap();
+        });
+
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = format!(
+            r#"
+            const response = await pipe.fetch("http://127.0.0.1:{}/image.png", {{ responseType: "binary" }});
+            const bytes = new Uint8Array(await response.arrayBuffer());
+            if (!response.ok || response.redirected || await response.base64() !== "AJ+A/w==") {{
+                throw new Error(`unexpected response: ${{JSON.stringify(response)}}`);
+            }}
+            if (JSON.stringify(Array.from(bytes)) !== "[0,159,128,255]") {{
+                throw new Error(`unexpected bytes: ${{Array.from(bytes)}}`);
+            }}
+        "#,
+            port
+        );
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "binary_fetch_pipe", &code).await;
+
+        let options = PipeOptions {
+            network: NetworkPolicy {
+                allowed_private_hosts: vec![format!("127.0.0.1:{}", port)],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            options,
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe execution failed: {:?}", result);
+    }
+
+    #[derive(Debug)]
+    struct EchoTranscriber;
+
+    impl PipeTranscriber for EchoTranscriber {
+        fn transcribe<'a>(
+            &'a self,
+            path: &'a std::path::Path,
+            options: &'a Value,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<Value>> + Send + 'a>>
+        {
+            Box::pin(async move {
+                Ok(json!({
+                    "text": path.file_name().unwrap().to_string_lossy(),
+                    "engine": options["engine"],
+                }))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipe_transcribe_uses_host_transcriber() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            const result = await pipe.transcribe("meeting.wav", { engine: "whisper-tiny" });
+            if (result.text !== "meeting.wav" || result.engine !== "whisper-tiny") {
+                throw new Error(`unexpected transcript: ${JSON.stringify(result)}`);
+            }
+            let blocked = false;
+            try {
+                await pipe.transcribe("/etc/passwd");
+            } catch (error) {
+                blocked = true;
+            }
+            if (!blocked) {
+                throw new Error("transcribed a file outside the pipe directory");
+            }
+        "#;
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "transcribe_pipe", code).await;
+        tokio::fs::write(pipe_dir.join("meeting.wav"), b"RIFF").await.unwrap();
+
+        let options = PipeOptions {
+            transcriber: Some(std::sync::Arc::new(EchoTranscriber)),
+            ..Default::default()
+        };
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            options,
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe execution failed: {:?}", result);
+    }
+
+    #[test]
+    fn test_pipe_env_scope_matches_names_and_prefixes() {
+        let unscoped = PipeEnvScope::default();
+        assert!(unscoped.allows("SCREENPIPE_OPENAI_KEY"));
+        assert!(!unscoped.allows("AWS_SECRET_ACCESS_KEY"));
+        assert!(!unscoped.allows("SCREENPIPE_SERVER_TOKEN"));
+
+        let scoped = PipeEnvScope::new(Some(vec![
+            "SCREENPIPE_NOTION_TOKEN".to_string(),
+            "SCREENPIPE_OPENAI_*".to_string(),
+        ]));
+        assert!(scoped.allows("SCREENPIPE_NOTION_TOKEN"));
+        assert!(scoped.allows("SCREENPIPE_OPENAI_KEY"));
+        assert!(!scoped.allows("SCREENPIPE_NOTION_TOKEN_2"));
+        assert!(!scoped.allows("SCREENPIPE_ANTHROPIC_KEY"));
+
+        let everything = PipeEnvScope::new(Some(vec!["SCREENPIPE_*".to_string()]));
+        assert!(!everything.allows("SCREENPIPE_SERVER_TOKEN"));
+    }
+
+    #[tokio::test]
+    async fn test_pipe_only_sees_declared_env() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+        std::env::set_var("SCREENPIPE_TEST_SCOPED_ALLOWED", "visible");
+        std::env::set_var("SCREENPIPE_TEST_SCOPED_SECRET", "hidden");
+
+        let code = r#"
+            if (process.env.SCREENPIPE_TEST_SCOPED_ALLOWED !== "visible") {
+                throw new Error("declared variable missing");
+            }
+            if (process.env.SCREENPIPE_TEST_SCOPED_SECRET !== undefined) {
+                throw new Error("undeclared variable leaked");
+            }
+        "#;
+        let config = json!({ "env": ["SCREENPIPE_TEST_SCOPED_ALLOWED"] }).to_string();
+        let pipe_dir = setup_test_pipe_with_config(&temp_dir, "env_pipe", code, &config).await;
+
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe execution failed: {:?}", result);
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let contents = r#"
+# notion integration
+export NOTION_TOKEN=secret_abc # the workspace token
+EMPTY=
+HASH_IN_VALUE=a#b
+SINGLE='literal \n $value'
+DOUBLE="line one\nsays \"hi\""
+MULTILINE="first
+second"
+"#;
+        let vars = parse_dotenv(contents).unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("NOTION_TOKEN".to_string(), "secret_abc".to_string()),
+                ("EMPTY".to_string(), "".to_string()),
+                ("HASH_IN_VALUE".to_string(), "a#b".to_string()),
+                ("SINGLE".to_string(), "literal \\n $value".to_string()),
+                ("DOUBLE".to_string(), "line one\nsays \"hi\"".to_string()),
+                ("MULTILINE".to_string(), "first\nsecond".to_string()),
+            ]
+        );
+
+        assert!(parse_dotenv("NO_EQUALS_SIGN").is_err());
+        assert!(parse_dotenv("1BAD=value").is_err());
+        assert!(parse_dotenv("OPEN=\"never closed").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pipe_reads_its_dotenv() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+        std::env::set_var("SCREENPIPE_TEST_DOTENV_SHARED", "from process");
+
+        let code = r#"
+            pipe.setResult({
+                token: process.env.NOTION_TOKEN,
+                shared: process.env.SCREENPIPE_TEST_DOTENV_SHARED,
+            });
+        "#;
+        let pipe_dir = setup_test_pipe(&temp_dir, "dotenv_pipe", code).await;
+        tokio::fs::write(
+            pipe_dir.join(".env"),
+            "NOTION_TOKEN=\"secret value\"\nSCREENPIPE_TEST_DOTENV_SHARED=from dotenv\n",
+        )
+        .await
+        .unwrap();
+
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir.clone(),
+            PipeOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, json!({ "token": "secret value", "shared": "from process" }));
+
+        let options = PipeOptions {
+            dotenv_overrides_env: true,
+            ..Default::default()
+        };
+        let result = run_pipe(pipe_dir.to_string_lossy().to_string(), screenpipe_dir, options)
+            .await
+            .unwrap();
+        assert_eq!(result, json!({ "token": "secret value", "shared": "from dotenv" }));
+        // Never leaks into the host's environment
+        assert!(std::env::var("NOTION_TOKEN").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pipe_reads_screenpipe_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            const config = pipe.getScreenpipeConfig();
+            try {
+                config.port = 1;
+            } catch (_) {
+                // Frozen
+            }
+            pipe.setResult(pipe.getScreenpipeConfig());
+        "#;
+        let pipe_dir = setup_test_pipe(&temp_dir, "config_pipe", code).await;
+
+        let options = PipeOptions {
+            config: PipeHostConfig {
+                port: Some(3030),
+                audio_enabled: true,
+                audio_transcription_engine: Some("whisper-large".to_string()),
+                monitor_ids: vec![1, 2],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir.clone(),
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["dataDir"], json!(screenpipe_dir));
+        assert_eq!(result["port"], 3030);
+        assert_eq!(result["audioEnabled"], true);
+        assert_eq!(result["visionEnabled"], false);
+        assert_eq!(result["audioTranscriptionEngine"], "whisper-large");
+        assert_eq!(result["ocrEngine"], Value::Null);
+        assert_eq!(result["monitorIds"], json!([1, 2]));
+    }
+
+    #[tokio::test]
+    async fn test_pipe_receives_args() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            const args = globalThis.metadata.args;
+            if (args.from !== "2024-09-01" || args.limit !== 10 || pipe.getArgs().tags[1] !== "b") {
+                throw new Error(`unexpected args: ${JSON.stringify(args)}`);
+            }
+        "#;
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "args_pipe", code).await;
+
+        let options = PipeOptions {
+            args: json!({ "from": "2024-09-01", "limit": 10, "tags": ["a", "b"] }),
+            ..Default::default()
+        };
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            options,
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe execution failed: {:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_db_query_is_read_only() {
+        use sqlx::sqlite::SqliteConnectOptions;
+        use sqlx::ConnectOptions;
+
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let mut connection = SqliteConnectOptions::new()
+            .filename(screenpipe_dir.join("db.sqlite"))
+            .create_if_missing(true)
+            .connect()
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE audio_transcriptions (id INTEGER PRIMARY KEY, transcription TEXT)")
+            .execute(&mut connection)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO audio_transcriptions (transcription) VALUES ('hello'), ('world')")
+            .execute(&mut connection)
+            .await
+            .unwrap();
+
+        let code = r#"
+            const rows = await pipe.queryDb(
+                "SELECT id, transcription FROM audio_transcriptions WHERE id > ? ORDER BY id",
+                [0]
+            );
+            if (rows.length !== 2 || rows[1].transcription !== "world" || rows[0].id !== 1) {
+                throw new Error(`unexpected rows: ${JSON.stringify(rows)}`);
+            }
+            for (const sql of [
+                "DELETE FROM audio_transcriptions",
+                "SELECT 1; DROP TABLE audio_transcriptions",
+            ]) {
+                let rejected = false;
+                try {
+                    await pipe.queryDb(sql);
+                } catch (error) {
+                    rejected = true;
+                }
+                if (!rejected) {
+                    throw new Error(`query was not rejected: ${sql}`);
+                }
+            }
+        "#;
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "db_pipe", code).await;
+
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            PipeOptions::default(),
+        )
+        .await;
         assert!(result.is_ok(), "Pipe execution failed: {:?}", result);
+
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM audio_transcriptions")
+            .fetch_one(&mut connection)
+            .await
+            .unwrap();
+        assert_eq!(count.0, 2);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_notifications_are_rate_limited() {
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let code = r#"
+            const results = [];
+            for (let i = 0; i < 5; i++) {
+                results.push(await pipe.sendNotification({ title: `title ${i}`, body: "body" }));
+            }
+            if (JSON.stringify(results) !== JSON.stringify([true, true, false, false, false])) {
+                throw new Error(`unexpected results: ${JSON.stringify(results)}`);
+            }
+        "#;
+
+        let pipe_dir = setup_test_pipe(&temp_dir, "notify_pipe", code).await;
+
+        let (notification_sender, mut notification_receiver) =
+            tokio::sync::mpsc::unbounded_channel();
+        let options = PipeOptions {
+            notification_sender: Some(notification_sender),
+            notification_rate_limit: RateLimit {
+                max: 2,
+                per: Duration::from_secs(60),
+            },
+            ..Default::default()
+        };
+        let result = run_pipe(
+            pipe_dir.to_string_lossy().to_string(),
+            screenpipe_dir,
+            options,
+        )
+        .await;
+        assert!(result.is_ok(), "Pipe execution failed: {:?}", result);
+
+        let first = notification_receiver.try_recv().unwrap();
+        assert_eq!(first.title, "title 0");
+        assert_eq!(first.body, "body");
+        assert_eq!(notification_receiver.try_recv().unwrap().title, "title 1");
+        assert!(notification_receiver.try_recv().is_err());
     }
 
     #[tokio::test]
@@ -199,7 +987,7 @@ mod tests {
         let screenpipe_dir = temp_dir.path().to_path_buf();
 
         let github_url = "https://github.com/mediar-ai/screenpipe/tree/main/examples/typescript/pipe-stream-ocr-text";
-        let result = download_pipe(github_url, screenpipe_dir.clone()).await;
+        let result = download_pipe(github_url, screenpipe_dir.clone(), false).await;
 
         assert!(
             result.is_ok(),
@@ -221,6 +1009,178 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_pipe_source_detection() {
+        assert_eq!(
+            PipeSource::detect(
+                "https://github.com/mediar-ai/screenpipe/tree/main/examples/typescript/pipe-stream-ocr-text"
+            )
+            .unwrap(),
+            PipeSource::GitHub {
+                api_url: "https://api.github.com/repos/mediar-ai/screenpipe/contents/examples/typescript/pipe-stream-ocr-text?ref=main".to_string()
+            }
+        );
+        assert_eq!(
+            PipeSource::detect("https://gitlab.example.com/tools/pipes/-/tree/dev/summarize").unwrap(),
+            PipeSource::GitLab {
+                api_base: "https://gitlab.example.com/api/v4".to_string(),
+                project: "tools/pipes".to_string(),
+                branch: "dev".to_string(),
+                path: "summarize".to_string(),
+            }
+        );
+        assert_eq!(
+            PipeSource::detect("git+https://example.com/me/pipes.git#v1.2:pipes/daily").unwrap(),
+            PipeSource::Git {
+                url: "https://example.com/me/pipes.git".to_string(),
+                reference: Some("v1.2".to_string()),
+                subdir: Some("pipes/daily".to_string()),
+            }
+        );
+        assert_eq!(
+            PipeSource::detect("git+https://example.com/me/my-pipe.git").unwrap(),
+            PipeSource::Git {
+                url: "https://example.com/me/my-pipe.git".to_string(),
+                reference: None,
+                subdir: None,
+            }
+        );
+        assert!(PipeSource::detect("git+ssh://git@example.com/me/my-pipe.git").is_err());
+        assert!(PipeSource::detect("https://gitlab.com/tools/pipes").is_err());
+        assert!(PipeSource::detect("https://example.com/invalid/url").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_local_pipe_with_subdirectories() {
+        init();
+        let source_dir = TempDir::new().unwrap();
+        let source = source_dir.path().join("multi-file-pipe");
+        create_dir_all(source.join("lib/components")).await.unwrap();
+        create_dir_all(source.join("node_modules/dep")).await.unwrap();
+        tokio::fs::write(source.join("pipe.ts"), "import { greet } from './lib/greet.ts';")
+            .await
+            .unwrap();
+        tokio::fs::write(source.join("README.md"), "# pipe").await.unwrap();
+        tokio::fs::write(source.join("lib/greet.ts"), "export const greet = () => 'hi';")
+            .await
+            .unwrap();
+        tokio::fs::write(source.join("lib/components/logo.svg"), "<svg/>")
+            .await
+            .unwrap();
+        tokio::fs::write(source.join("node_modules/dep/index.js"), "")
+            .await
+            .unwrap();
+        // A loop back to the pipe's root must not be followed forever
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&source, source.join("lib/loop")).unwrap();
+
+        let temp_dir = TempDir::new().unwrap();
+        let pipe_dir = download_pipe(
+            &source.to_string_lossy(),
+            temp_dir.path().to_path_buf(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert!(pipe_dir.join("pipe.ts").exists());
+        assert!(pipe_dir.join("lib/greet.ts").exists());
+        assert!(pipe_dir.join("lib/components/logo.svg").exists());
+        assert!(!pipe_dir.join("README.md").exists());
+        assert!(!pipe_dir.join("node_modules").exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_pipe_verifies_checksums() {
+        init();
+        let code = "console.log('hi');";
+        let wrong_sha256 = "00".repeat(32);
+
+        let source_dir = TempDir::new().unwrap();
+        let write_pipe = |name: &str, config: Value| {
+            let source = source_dir.path().join(name);
+            std::fs::create_dir_all(&source).unwrap();
+            std::fs::write(source.join("pipe.ts"), code).unwrap();
+            std::fs::write(source.join("pipe.json"), config.to_string()).unwrap();
+            source.to_string_lossy().to_string()
+        };
+        let actual_sha256 = {
+            use sha2::{Digest, Sha256};
+            Sha256::digest(code.as_bytes())
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+
+        let pinned = write_pipe("pinned", json!({ "checksums": { "pipe.ts": actual_sha256 } }));
+        assert!(download_pipe(&pinned, screenpipe_dir.clone(), true).await.is_ok());
+
+        let tampered = write_pipe("tampered", json!({ "checksums": { "pipe.ts": wrong_sha256 } }));
+        assert!(download_pipe(&tampered, screenpipe_dir.clone(), false)
+            .await
+            .is_err());
+        // Nothing is left behind to be picked up as already installed, not even the staging
+        // directory it was installed into
+        assert!(!screenpipe_dir.join("pipes/tampered").exists());
+        let installed: Vec<_> = std::fs::read_dir(screenpipe_dir.join("pipes"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(installed, vec!["pinned".to_string()]);
+
+        let unpinned = write_pipe("unpinned", json!({}));
+        assert!(download_pipe(&unpinned, screenpipe_dir.clone(), true)
+            .await
+            .is_err());
+        assert!(download_pipe(&unpinned, screenpipe_dir.clone(), false)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reinstall_local_pipe_verifies_checksums() {
+        init();
+        let source_dir = TempDir::new().unwrap();
+        let source_path = source_dir.path().join("edited");
+        std::fs::create_dir_all(&source_path).unwrap();
+        std::fs::write(source_path.join("pipe.ts"), "console.log('v1');").unwrap();
+        let source = source_path.to_string_lossy().to_string();
+
+        let temp_dir = TempDir::new().unwrap();
+        let screenpipe_dir = temp_dir.path().to_path_buf();
+        let pipe_dir = download_pipe(&source, screenpipe_dir.clone(), false)
+            .await
+            .unwrap();
+
+        // The edit is picked up, replacing the installed copy
+        std::fs::write(source_path.join("pipe.ts"), "console.log('v2');").unwrap();
+        download_pipe(&source, screenpipe_dir.clone(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("pipe.ts")).unwrap(),
+            "console.log('v2');"
+        );
+
+        // An edit that doesn't pass --verify leaves the installed copy alone
+        std::fs::write(source_path.join("pipe.ts"), "console.log('v3');").unwrap();
+        assert!(download_pipe(&source, screenpipe_dir.clone(), true)
+            .await
+            .is_err());
+        assert_eq!(
+            std::fs::read_to_string(pipe_dir.join("pipe.ts")).unwrap(),
+            "console.log('v2');"
+        );
+        let installed: Vec<_> = std::fs::read_dir(screenpipe_dir.join("pipes"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(installed, vec!["edited".to_string()]);
+    }
+
     #[tokio::test]
     async fn test_download_pipe_invalid_url() {
         init();
@@ -228,7 +1188,7 @@ mod tests {
         let screenpipe_dir = temp_dir.path().to_path_buf();
 
         let invalid_url = "https://example.com/invalid/url";
-        let result = download_pipe(invalid_url, screenpipe_dir.clone()).await;
+        let result = download_pipe(invalid_url, screenpipe_dir.clone(), false).await;
 
         assert!(result.is_err(), "Expected an error for invalid URL");
     }
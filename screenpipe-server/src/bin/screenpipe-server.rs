@@ -3,7 +3,7 @@ use std::{
 };
 use std::io::Write;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 #[allow(unused_imports)]
 use colored::Colorize;
 use crossbeam::queue::SegQueue;
@@ -12,9 +12,9 @@ use futures::{pin_mut, stream::FuturesUnordered, StreamExt};
 use log::{debug, error, info};
 use screenpipe_audio::{
     default_input_device, default_output_device, list_audio_devices, parse_audio_device,
-    AudioDevice, DeviceControl,
+    AudioDevice, DeviceControl, PipeSttTranscriber,
 };
-use screenpipe_core::find_ffmpeg_path;
+use screenpipe_core::{find_ffmpeg_path, PipeHostConfig};
 use screenpipe_server::{
     cli::{Cli, CliAudioTranscriptionEngine, CliOcrEngine, Command, PipeCommand},
     start_continuous_recording, DatabaseManager, PipeManager, ResourceMonitor, Server,
@@ -66,7 +66,44 @@ async fn main() -> anyhow::Result<()> {
     let local_data_dir = get_base_dir(cli.data_dir)?;
     let local_data_dir_clone = local_data_dir.clone();
 
-    let pipe_manager = Arc::new(PipeManager::new(local_data_dir_clone.clone()));
+    // Set by the app when it spawns us, its notify/log server rejects requests without it
+    let server_token = env::var("SCREENPIPE_SERVER_TOKEN").ok().filter(|t| !t.is_empty());
+    let engine_name = |engine: Option<clap::builder::PossibleValue>| {
+        engine.map(|engine| engine.get_name().to_string())
+    };
+    let host_config = PipeHostConfig {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        port: Some(cli.port),
+        audio_enabled: !cli.disable_audio,
+        vision_enabled: !cli.disable_vision,
+        audio_transcription_engine: engine_name(cli.audio_transcription_engine.to_possible_value()),
+        ocr_engine: engine_name(cli.ocr_engine.to_possible_value()),
+        audio_devices: cli.audio_device.clone(),
+        monitor_ids: cli.monitor_id.clone(),
+        pii_removal: cli.use_pii_removal,
+        ..Default::default()
+    };
+    let pipe_manager = Arc::new(
+        PipeManager::new(local_data_dir_clone.clone())
+            .with_host_config(host_config)
+            .with_max_runtime(cli.pipe_max_runtime_secs.map(Duration::from_secs))
+            .with_allowed_private_hosts(cli.pipe_allowed_host.clone())
+            .with_trusted_pipes(cli.pipe_trusted.clone())
+            .with_log_endpoint(
+                env::var("SCREENPIPE_LOG_API_URL")
+                    .unwrap_or_else(|_| "http://localhost:11435/log".to_string()),
+                server_token.clone(),
+            )
+            .with_notification_endpoint(
+                format!(
+                    "{}/notify",
+                    env::var("SCREENPIPE_SERVER_URL")
+                        .unwrap_or_else(|_| "http://localhost:11435".to_string())
+                ),
+                server_token,
+            )
+            .with_transcriber(Arc::new(PipeSttTranscriber::new(Some(Duration::from_secs(300))))),
+    );
 
     if let Some(pipe_command) = cli.command {
         match pipe_command {
@@ -592,7 +629,7 @@ async fn handle_pipe_command(pipe: PipeCommand, pipe_manager: &PipeManager) -> a
                 println!("  id: {}, enabled: {}", pipe.id, pipe.enabled);
             }
         }
-        PipeCommand::Download { url } => match pipe_manager.download_pipe(&url).await {
+        PipeCommand::Download { url, verify } => match pipe_manager.download_pipe(&url, verify).await {
             Ok(pipe_id) => println!("pipe downloaded successfully. id: {}. now enable it with `screenpipe pipe enable {}`", pipe_id, pipe_id),
             Err(e) => eprintln!("failed to download pipe: {}", e),
         },
@@ -642,6 +679,12 @@ async fn handle_pipe_command(pipe: PipeCommand, pipe_manager: &PipeManager) -> a
                 println!("pipe purge cancelled.");
             }
         },
+        PipeCommand::Dev { id } => {
+            println!("running pipe {}, it reloads when its source changes. press ctrl+c to stop", id);
+            if let Err(e) = pipe_manager.watch_pipe(&id).await {
+                eprintln!("failed to run pipe: {}", e);
+            }
+        }
     }
     Ok(())
 }
@@ -61,7 +61,7 @@ pub enum CliVadEngine {
 impl From<CliVadEngine> for VadEngineEnum {
     fn from(cli_engine: CliVadEngine) -> Self {
         match cli_engine {
-            CliVadEngine::WebRtc => VadEngineEnum::WebRtc,
+            CliVadEngine::WebRtc => VadEngineEnum::WebRtc { aggressiveness: None },
             CliVadEngine::Silero => VadEngineEnum::Silero,
         }
     }
@@ -187,6 +187,19 @@ pub struct Cli {
     #[arg(long = "deepgram-api-key")]
     pub deepgram_api_key: Option<String>,
 
+    /// Stop a pipe after it has run for this many seconds (pipes run until they exit by default)
+    #[arg(long)]
+    pub pipe_max_runtime_secs: Option<u64>,
+
+    /// Private hosts (`host` or `host:port`) pipes may fetch besides the screenpipe API and
+    /// Ollama on localhost:11434 (can be specified multiple times)
+    #[arg(long)]
+    pub pipe_allowed_host: Vec<String>,
+
+    /// Pipes given full filesystem access (can be specified multiple times)
+    #[arg(long)]
+    pub pipe_trusted: Vec<String>,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 }
@@ -210,6 +223,10 @@ pub enum PipeCommand {
     Download {
         /// URL of the pipe to download
         url: String,
+        /// Refuse the pipe unless its pipe.json lists a SHA-256 checksum for every file, and
+        /// they all match
+        #[arg(long)]
+        verify: bool,
     },
     /// Get info for a specific pipe
     Info {
@@ -235,4 +252,9 @@ pub enum PipeCommand {
     },
     /// Purge all pipes
     Purge,
+    /// Run a pipe in the foreground, reloading it whenever its pipe.ts changes
+    Dev {
+        /// ID of the pipe to run
+        id: String,
+    },
 }
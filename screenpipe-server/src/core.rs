@@ -5,8 +5,9 @@ use crossbeam::queue::SegQueue;
 use futures::future::join_all;
 use log::{debug, error, info, warn};
 use screenpipe_audio::{
-    create_whisper_channel, record_and_transcribe, vad_engine::VadEngineEnum, AudioDevice,
-    AudioInput, AudioTranscriptionEngine, DeviceControl, TranscriptionResult,
+    audio_queue, create_whisper_channel, record_and_transcribe, vad_engine::VadEngineEnum,
+    AudioDevice, AudioInputSender, AudioTranscriptionEngine, DeviceControl, DropPolicy, Task,
+    TranscriptionResult,
 };
 use screenpipe_core::pii_removal::remove_pii;
 use screenpipe_integrations::friend_wearable::initialize_friend_wearable_loop;
@@ -45,8 +46,7 @@ pub async fn start_continuous_recording(
 ) -> Result<()> {
     let (whisper_sender, whisper_receiver, whisper_shutdown_flag) = if audio_disabled {
         // Create a dummy channel if no audio devices are available, e.g. audio disabled
-        let (input_sender, _): (UnboundedSender<AudioInput>, UnboundedReceiver<AudioInput>) =
-            unbounded_channel();
+        let (input_sender, _) = audio_queue(1, DropPolicy::DropNewest);
         let (_, output_receiver): (
             UnboundedSender<TranscriptionResult>,
             UnboundedReceiver<TranscriptionResult>,
@@ -62,6 +62,7 @@ pub async fn start_continuous_recording(
             VadEngineEnum::from(vad_engine),
             deepgram_api_key,
             &PathBuf::from(output_path.as_ref()),
+            Task::Transcribe,
         )
         .await?
     };
@@ -249,7 +250,7 @@ async fn record_video(
 async fn record_audio(
     db: Arc<DatabaseManager>,
     chunk_duration: Duration,
-    whisper_sender: UnboundedSender<AudioInput>,
+    whisper_sender: AudioInputSender,
     mut whisper_receiver: UnboundedReceiver<TranscriptionResult>,
     audio_devices_control: Arc<SegQueue<(AudioDevice, DeviceControl)>>,
     friend_wearable_uid: Option<String>,
@@ -1,12 +1,18 @@
 use anyhow::Result;
 use log::debug;
-use screenpipe_core::{download_pipe, run_pipe};
+use screenpipe_core::{
+    download_pipe, forward_pipe_logs, forward_pipe_notifications, run_pipe, run_pipe_watch,
+    NetworkPolicy, PipeHostConfig, PipeLogEntry, PipeNotification, PipeOptions, PipeTranscriber,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::future::Future;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::UnboundedSender;
 use tracing::warn;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -18,24 +24,119 @@ pub struct PipeInfo {
 
 pub struct PipeManager {
     screenpipe_dir: PathBuf,
+    max_runtime: Option<Duration>,
+    log_sender: Option<UnboundedSender<PipeLogEntry>>,
+    notification_sender: Option<UnboundedSender<PipeNotification>>,
+    transcriber: Option<Arc<dyn PipeTranscriber>>,
+    host_config: PipeHostConfig,
+    allowed_private_hosts: Vec<String>,
+    trusted_pipes: Vec<String>,
 }
 
+/// Where a local Ollama listens, used by many of the example pipes
+const OLLAMA_HOST: &str = "localhost:11434";
+const DEFAULT_PORT: u16 = 3030;
+
 impl PipeManager {
     pub fn new(screenpipe_dir: PathBuf) -> Self {
-        PipeManager { screenpipe_dir }
+        PipeManager {
+            screenpipe_dir,
+            max_runtime: None,
+            log_sender: None,
+            notification_sender: None,
+            transcriber: None,
+            host_config: PipeHostConfig::default(),
+            allowed_private_hosts: Vec::new(),
+            trusted_pipes: Vec::new(),
+        }
+    }
+
+    /// What pipes see of screenpipe's configuration through `pipe.getScreenpipeConfig()`.
+    pub fn with_host_config(mut self, host_config: PipeHostConfig) -> Self {
+        self.host_config = host_config;
+        self
+    }
+
+    /// Let every pipe fetch these private hosts (`host` or `host:port`) besides the screenpipe
+    /// API and a local Ollama. The app isn't one: pipes reach it through `pipe.sendNotification`
+    /// and their logs, which the host forwards.
+    pub fn with_allowed_private_hosts(mut self, hosts: Vec<String>) -> Self {
+        self.allowed_private_hosts = hosts;
+        self
+    }
+
+    /// Give these pipes full filesystem access. Decided on the host, as a pipe.json ships
+    /// with the pipe.
+    pub fn with_trusted_pipes(mut self, pipe_ids: Vec<String>) -> Self {
+        self.trusted_pipes = pipe_ids;
+        self
+    }
+
+    /// Let pipes transcribe audio files with `pipe.transcribe`.
+    pub fn with_transcriber(mut self, transcriber: Arc<dyn PipeTranscriber>) -> Self {
+        self.transcriber = Some(transcriber);
+        self
+    }
+
+    /// Show the notifications of pipes through `notify_url`, the app's `/notify` endpoint,
+    /// authenticated with `auth_token`. Must be called from within a tokio runtime.
+    pub fn with_notification_endpoint(mut self, notify_url: String, auth_token: Option<String>) -> Self {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(forward_pipe_notifications(receiver, notify_url, auth_token));
+        self.notification_sender = Some(sender);
+        self
+    }
+
+    /// Forward what pipes log to `log_url`, the app's `/log` endpoint, authenticated with
+    /// `auth_token`. Must be called from within a tokio runtime.
+    pub fn with_log_endpoint(mut self, log_url: String, auth_token: Option<String>) -> Self {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(forward_pipe_logs(receiver, log_url, auth_token));
+        self.log_sender = Some(sender);
+        self
+    }
+
+    /// Stop every pipe started by this manager after `max_runtime`.
+    pub fn with_max_runtime(mut self, max_runtime: Option<Duration>) -> Self {
+        self.max_runtime = max_runtime;
+        self
+    }
+
+    fn pipe_options(&self, pipe: &PipeInfo) -> PipeOptions {
+        let mut network = NetworkPolicy::default();
+        let port = self.host_config.port.unwrap_or(DEFAULT_PORT);
+        network.allow_private_host(&format!("localhost:{}", port));
+        network.allow_private_host(OLLAMA_HOST);
+        for host in &self.allowed_private_hosts {
+            network.allow_private_host(host);
+        }
+
+        PipeOptions {
+            allow_full_fs: self.trusted_pipes.contains(&pipe.id),
+            network,
+            // Set with e.g. `screenpipe pipe update <id> '{"args": {...}}'`
+            args: pipe.config.get("args").cloned().unwrap_or(Value::Null),
+            max_runtime: self.max_runtime,
+            log_sender: self.log_sender.clone(),
+            notification_sender: self.notification_sender.clone(),
+            transcriber: self.transcriber.clone(),
+            config: self.host_config.clone(),
+            ..Default::default()
+        }
     }
 
     pub async fn start_pipe(
         &self,
         id: &str,
-    ) -> Result<impl Future<Output = Result<(), anyhow::Error>>> {
+    ) -> Result<impl Future<Output = Result<Value, anyhow::Error>>> {
         let pipes = self.list_pipes().await;
 
-        if let Some(_) = pipes.iter().find(|pipe| pipe.id == id) {
+        if let Some(pipe) = pipes.iter().find(|pipe| pipe.id == id) {
             let pipe_id = id.to_string();
             let screenpipe_dir = self.screenpipe_dir.clone();
 
-            let future = run_pipe(pipe_id.clone(), screenpipe_dir);
+            let options = self.pipe_options(pipe);
+            let future = run_pipe(pipe_id.clone(), screenpipe_dir, options);
 
             self.update_config(
                 id,
@@ -51,6 +152,19 @@ impl PipeManager {
         }
     }
 
+    /// Runs a pipe in the foreground, reloading it whenever its source changes. Leaves the pipe's
+    /// `enabled` flag untouched.
+    pub async fn watch_pipe(&self, id: &str) -> Result<()> {
+        let pipes = self.list_pipes().await;
+        let pipe = pipes
+            .iter()
+            .find(|pipe| pipe.id == id)
+            .ok_or_else(|| anyhow::anyhow!("pipe not found"))?;
+
+        let options = self.pipe_options(pipe);
+        run_pipe_watch(id.to_string(), self.screenpipe_dir.clone(), options).await
+    }
+
     pub async fn update_config(&self, id: &str, new_config: Value) -> Result<()> {
         debug!("Updating config for pipe: {}", id);
         let pipe_dir = self.screenpipe_dir.join("pipes").join(id);
@@ -122,6 +236,10 @@ impl PipeManager {
         if let Ok(mut entries) = tokio::fs::read_dir(pipe_dir).await {
             while let Ok(Some(entry)) = entries.next_entry().await {
                 let pipe_id = entry.file_name().to_string_lossy().into_owned();
+                // Hidden entries are pipes still being installed
+                if pipe_id.starts_with('.') {
+                    continue;
+                }
                 let config_path = entry.path().join("pipe.json");
                 pipe_infos.push(Self::load_pipe_info(pipe_id, config_path));
             }
@@ -130,11 +248,11 @@ impl PipeManager {
         futures::future::join_all(pipe_infos).await
     }
 
-    pub async fn download_pipe(&self, url: &str) -> Result<String> {
+    pub async fn download_pipe(&self, url: &str, verify: bool) -> Result<String> {
         // Remove any surrounding quotes and normalize backslashes
         let normalized_url = url.trim_matches('"').replace("\\", "/");
 
-        let pipe_dir = download_pipe(&normalized_url, self.screenpipe_dir.clone()).await?;
+        let pipe_dir = download_pipe(&normalized_url, self.screenpipe_dir.clone(), verify).await?;
         Ok(pipe_dir.file_name().unwrap().to_string_lossy().into_owned())
     }
 
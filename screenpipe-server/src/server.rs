@@ -545,6 +545,9 @@ pub async fn health_check(State(state): State<Arc<AppState>>) -> JsonResponse<He
 #[derive(Deserialize)]
 struct DownloadPipeRequest {
     url: String,
+    /// Refuse the pipe unless its pipe.json pins every file with a checksum
+    #[serde(default)]
+    verify: bool,
 }
 
 #[derive(Deserialize)]
@@ -564,7 +567,7 @@ async fn download_pipe_handler(
     JsonResponse(payload): JsonResponse<DownloadPipeRequest>,
 ) -> Result<JsonResponse<serde_json::Value>, (StatusCode, JsonResponse<Value>)> {
     debug!("Downloading pipe: {}", payload.url);
-    match download_pipe(&payload.url, state.screenpipe_dir.clone()).await {
+    match download_pipe(&payload.url, state.screenpipe_dir.clone(), payload.verify).await {
         Ok(pipe_dir) => {
             let pipe_id = pipe_dir.file_name().unwrap().to_string_lossy().into_owned();
 